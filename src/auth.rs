@@ -0,0 +1,507 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::OsRng;
+use reqwest::{Client, StatusCode, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::cli_operations::user_input::prompt_raw;
+use crate::config::{self, Profile};
+use crate::credentials::{self, Credentials};
+use crate::error::{self, Error, Result};
+
+/// Register `http://127.0.0.1:53682/callback` as a redirect URI of the Dropbox app to log in without copying a code.
+const DEFAULT_REDIRECT_PORT: u16 = 53682;
+/// How long `auth login` waits for the browser to come back.
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const USAGE: &str = "Usage: arino auth login [--app-key KEY] [--manual] [--port N] | arino auth status | arino auth logout";
+
+struct LoginOptions {
+    app_key: Option<String>,
+    /// Paste the code shown by Dropbox instead of receiving it on a local redirect.
+    manual: bool,
+    port: u16,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Account {
+    email: String,
+    name: AccountName,
+}
+
+#[derive(Deserialize)]
+struct AccountName {
+    display_name: String,
+}
+
+/// `arino auth login|status|logout`, returns the exit code.
+pub async fn run_from_args(cli_args: &[String]) -> i32 {
+    let result = match cli_args.first().map(String::as_str) {
+        Some("login") => match login_options(&cli_args[1..]) {
+            Some(options) => login(options).await,
+            None => {
+                eprintln!("{USAGE}");
+                return 2;
+            },
+        },
+        Some("status") if cli_args.len() == 1 => status().await,
+        Some("logout") if cli_args.len() == 1 => logout().await,
+        _ => {
+            eprintln!("{USAGE}");
+            return 2;
+        },
+    };
+
+    match result {
+        Ok(_) => 0,
+        Err(e) => {
+            error::report(&e);
+            e.exit_code()
+        },
+    }
+}
+
+fn login_options(cli_args: &[String]) -> Option<LoginOptions> {
+    let mut options = LoginOptions { app_key: None, manual: false, port: DEFAULT_REDIRECT_PORT };
+
+    let mut cli_args = cli_args.iter();
+    while let Some(arg) = cli_args.next() {
+        match arg.as_str() {
+            "--manual" => options.manual = true,
+            "--app-key" => options.app_key = Some(cli_args.next()?.clone()),
+            "--port" => options.port = cli_args.next()?.parse().ok()?,
+            _ => return None,
+        }
+    }
+
+    Some(options)
+}
+
+/// Authorizes Arino with the OAuth authorization code flow and PKCE, so that no app secret is needed,
+/// and stores the refresh token in the credential store.
+async fn login(options: LoginOptions) -> Result<()> {
+    let profile = config::active_profile();
+    let app_key = match options.app_key.or_else(|| profile.app_key.clone()) {
+        Some(app_key) => app_key,
+        None => prompt_raw("Dropbox app key"),
+    };
+    if app_key.is_empty() {
+        return Err(Error::Validation("A Dropbox app key is required, it is shown in the app console at https://www.dropbox.com/developers/apps".to_string()));
+    }
+
+    let verifier = random_text(48);
+    let challenge = BASE64_URL.encode(Sha256::digest(verifier.as_bytes()));
+    let state = random_text(16);
+
+    let listener = match options.manual {
+        true => None,
+        false => Some(TcpListener::bind(("127.0.0.1", options.port)).await?),
+    };
+    let redirect_uri = match &listener {
+        Some(listener) => Some(format!("http://127.0.0.1:{}/callback", listener.local_addr()?.port())),
+        None => None,
+    };
+
+    let authorize_url = authorize_url(&profile, &app_key, &challenge, &state, redirect_uri.as_deref())?;
+
+    println!("Open this address in a browser and allow Arino to access Dropbox:");
+    println!("{authorize_url}");
+
+    let code = match (&listener, &redirect_uri) {
+        (Some(listener), Some(redirect_uri)) => {
+            println!("Waiting for Dropbox to redirect to {redirect_uri}...");
+            match tokio::time::timeout(LOGIN_TIMEOUT, wait_for_code(listener, &state)).await {
+                Ok(code) => code?,
+                Err(_) => return Err(Error::Auth("No answer from the browser, run `arino auth login --manual` to paste the code instead".to_string())),
+            }
+        },
+        _ => prompt_raw("Authorization code"),
+    };
+    if code.is_empty() {
+        return Err(Error::Auth("No authorization code was entered".to_string()));
+    }
+
+    let token_response = exchange_code(&profile, &app_key, &code, &verifier, redirect_uri.as_deref()).await?;
+    let Some(refresh_token) = token_response.refresh_token else {
+        return Err(Error::Auth("Dropbox did not return a refresh token".to_string()));
+    };
+
+    let mut credentials = Credentials {
+        client_id: app_key,
+        client_secret: None,
+        refresh_token,
+        access_token: None,
+        expires_at: None,
+    };
+    credentials.set_access_token(token_response.access_token, token_response.expires_in);
+    credentials::save(&profile, &credentials)?;
+
+    println!("Logged in, the credentials are stored in {}", profile.credentials.display());
+
+    Ok(())
+}
+
+/// The page of the profile's authorize endpoint where the user allows Arino to access Dropbox.
+fn authorize_url(profile: &Profile, app_key: &str, challenge: &str, state: &str, redirect_uri: Option<&str>) -> Result<Url> {
+    let mut params = vec![
+        ("client_id", app_key),
+        ("response_type", "code"),
+        ("code_challenge", challenge),
+        ("code_challenge_method", "S256"),
+        // asks for a refresh token
+        ("token_access_type", "offline"),
+        ("state", state),
+    ];
+    if let Some(redirect_uri) = redirect_uri {
+        params.push(("redirect_uri", redirect_uri));
+    }
+
+    Url::parse_with_params(&profile.endpoints.authorize, &params)
+        .map_err(|e| Error::Validation(format!("Invalid authorize URL {}: {e}", profile.endpoints.authorize)))
+}
+
+/// Answers the browser's redirect and returns the authorization code it carries.
+async fn wait_for_code(listener: &TcpListener, state: &str) -> Result<String> {
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let mut buffer = vec![0u8; 8192];
+        let length = stream.read(&mut buffer).await?;
+        let request = String::from_utf8_lossy(&buffer[..length]);
+
+        // "GET /callback?code=...&state=... HTTP/1.1"
+        let target = request.lines().next().and_then(|line| line.split_whitespace().nth(1)).unwrap_or("/");
+        let url = match Url::parse(&format!("http://127.0.0.1{target}")) {
+            Ok(url) if url.path() == "/callback" => url,
+            // such as the browser asking for /favicon.ico
+            _ => {
+                respond(&mut stream, "404 Not Found", "Not found").await?;
+                continue;
+            },
+        };
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+        let code = match (query.get("error"), query.get("state"), query.get("code")) {
+            (Some(error), _, _) => {
+                let description = query.get("error_description").unwrap_or(error);
+                Err(Error::Auth(format!("Dropbox did not authorize Arino: {description}")))
+            },
+            (None, Some(returned_state), Some(code)) if returned_state == state => Ok(code.clone()),
+            _ => Err(Error::Auth("The redirect does not belong to this login, try again".to_string())),
+        };
+
+        match &code {
+            Ok(_) => respond(&mut stream, "200 OK", "Arino is logged in to Dropbox, this window can be closed.").await?,
+            Err(e) => respond(&mut stream, "400 Bad Request", &e.to_string()).await?,
+        }
+
+        return code;
+    }
+}
+
+/// The message may come from the redirect's query, such as an `error_description`, so it is escaped.
+async fn respond(stream: &mut TcpStream, status: &str, message: &str) -> Result<()> {
+    let body = format!("<!doctype html><title>Arino</title><p>{}</p>", escape_html(message));
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+
+    Ok(())
+}
+
+fn escape_html(text: &str) -> String {
+    text.chars().fold(String::with_capacity(text.len()), |mut escaped, c| {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+        escaped
+    })
+}
+
+async fn exchange_code(profile: &Profile, app_key: &str, code: &str, verifier: &str, redirect_uri: Option<&str>) -> Result<TokenResponse> {
+    let mut params = vec![
+        ("code", code),
+        ("grant_type", "authorization_code"),
+        ("client_id", app_key),
+        ("code_verifier", verifier),
+    ];
+    if let Some(redirect_uri) = redirect_uri {
+        params.push(("redirect_uri", redirect_uri));
+    }
+
+    let response = Client::new()
+        .post(format!("{}/oauth2/token", profile.endpoints.api))
+        .form(&params)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let error_text = response.text().await?;
+        return Err(Error::Auth(format!("Dropbox refused the authorization code: {error_text}")));
+    }
+
+    Ok(response.json().await?)
+}
+
+/// Shows which credentials the profile uses, how long the access token stays valid and, when Dropbox can be reached, the account.
+async fn status() -> Result<()> {
+    let profile = config::active_profile();
+    println!("Profile \"{}\"", profile.name);
+    if !profile.credentials.exists() && !profile.key.exists() {
+        println!("Not logged in, run `arino auth login`");
+        return Ok(());
+    }
+
+    let credentials = credentials::read(&profile)?;
+    match profile.credentials.exists() {
+        true => println!("Credentials: {}", profile.credentials.display()),
+        false => println!("Credentials: {}, moved into {} by the next command that needs Dropbox", profile.key.display(), profile.credentials.display()),
+    }
+    println!("App key: {}", credentials.client_id);
+    match (credentials.valid_access_token(), credentials.seconds_left()) {
+        (Some(_), Some(seconds_left)) => println!("Access token valid for {}h {}m", seconds_left / 3600, seconds_left % 3600 / 60),
+        _ => println!("No valid access token, a new one is requested by the next command that needs Dropbox"),
+    }
+
+    let Some(access_token) = credentials.valid_access_token() else {
+        return Ok(());
+    };
+    match current_account(&profile, access_token).await {
+        Ok(account) => println!("Dropbox account: {} <{}>", account.name.display_name, account.email),
+        Err(Error::Network(_)) => println!("Dropbox could not be reached to check the account"),
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
+async fn current_account(profile: &Profile, access_token: &str) -> Result<Account> {
+    let response = Client::new()
+        .post(format!("{}/2/users/get_current_account", profile.endpoints.api))
+        .bearer_auth(access_token)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?;
+
+    match response.status() {
+        status if status.is_success() => Ok(response.json().await?),
+        StatusCode::UNAUTHORIZED => Err(Error::Auth("Dropbox no longer accepts the access token, run `arino auth login`".to_string())),
+        status => Err(Error::Network(format!("Unexpected answer when checking the account: {status}"))),
+    }
+}
+
+/// Revokes the access token with Dropbox when possible, then deletes the local credentials.
+async fn logout() -> Result<()> {
+    let profile = config::active_profile();
+    if !profile.credentials.exists() && !profile.key.exists() {
+        println!("Not logged in");
+        return Ok(());
+    }
+
+    // Revoking is best effort: the local credentials are deleted even when Dropbox cannot be reached or the passphrase is lost
+    let revoked = match credentials::read(&profile) {
+        Ok(credentials) => match credentials.valid_access_token() {
+            Some(access_token) => revoke(&profile, access_token).await,
+            None => Ok(()),
+        },
+        Err(e) => Err(e),
+    };
+    if let Err(e) = revoked {
+        eprintln!("The access token was not revoked: {e}");
+    }
+
+    credentials::remove(&profile)?;
+    println!("Logged out, the credentials of profile \"{}\" were deleted", profile.name);
+
+    Ok(())
+}
+
+async fn revoke(profile: &Profile, access_token: &str) -> Result<()> {
+    let response = Client::new()
+        .post(format!("{}/2/auth/token/revoke", profile.endpoints.api))
+        .bearer_auth(access_token)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        return Err(Error::Auth(format!("Dropbox answered {}", response.status())));
+    }
+
+    Ok(())
+}
+
+/// Random URL-safe text from `length` random bytes, for the PKCE verifier and the state.
+fn random_text(length: usize) -> String {
+    let mut bytes = vec![0u8; length];
+    OsRng.fill_bytes(&mut bytes);
+    BASE64_URL.encode(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, ProfileEntry};
+
+    /// A profile whose Dropbox endpoints are the stub listening at `address`.
+    fn stub_profile(address: &str) -> Profile {
+        let entry = ProfileEntry {
+            authorize_url: Some(format!("http://{address}/oauth2/authorize")),
+            api_url: Some(format!("http://{address}")),
+            content_url: Some(format!("http://{address}")),
+            ..ProfileEntry::default()
+        };
+        let config = Config { default_profile: None, profiles: [("stub".to_string(), entry)].into() };
+
+        config::select(&config, Some("stub"), None).unwrap()
+    }
+
+    /// Reads one HTTP request, including the body announced by its Content-Length.
+    async fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let length = stream.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..length]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let content_length = head
+                    .lines()
+                    .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|value| value.trim().parse::<usize>().unwrap()))
+                    .unwrap_or(0);
+                if body.len() >= content_length || length == 0 {
+                    return text;
+                }
+            }
+        }
+    }
+
+    /// Sends the browser's redirect to `wait_for_code` and returns the code it got, with the status of the page it answered.
+    async fn redirect(query: &str) -> (Result<String>, StatusCode) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url = format!("http://{}/callback?{query}", listener.local_addr().unwrap());
+
+        let browser = tokio::spawn(async move { Client::new().get(url).send().await.unwrap().status() });
+        let code = wait_for_code(&listener, "expected-state").await;
+
+        (code, browser.await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn authorize_url_uses_the_profile_endpoint() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let profile = stub_profile(&address);
+
+        let url = authorize_url(&profile, "app-key", "challenge", "state", Some("http://127.0.0.1:53682/callback")).unwrap();
+        let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+
+        assert_eq!(format!("{}:{}{}", url.host_str().unwrap(), url.port().unwrap(), url.path()), format!("{address}/oauth2/authorize"));
+        assert_eq!(query["client_id"], "app-key");
+        assert_eq!(query["code_challenge"], "challenge");
+        assert_eq!(query["code_challenge_method"], "S256");
+        assert_eq!(query["token_access_type"], "offline");
+        assert_eq!(query["state"], "state");
+        assert_eq!(query["redirect_uri"], "http://127.0.0.1:53682/callback");
+    }
+
+    #[tokio::test]
+    async fn wait_for_code_returns_the_code() {
+        let (code, status) = redirect("code=the-code&state=expected-state").await;
+
+        assert_eq!(code.unwrap(), "the-code");
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn wait_for_code_skips_other_requests() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let browser = tokio::spawn(async move {
+            let client = Client::new();
+            let favicon = client.get(format!("http://{address}/favicon.ico")).send().await.unwrap().status();
+            let callback = client.get(format!("http://{address}/callback?code=the-code&state=expected-state")).send().await.unwrap().status();
+            (favicon, callback)
+        });
+        let code = wait_for_code(&listener, "expected-state").await;
+
+        assert_eq!(code.unwrap(), "the-code");
+        assert_eq!(browser.await.unwrap(), (StatusCode::NOT_FOUND, StatusCode::OK));
+    }
+
+    #[tokio::test]
+    async fn wait_for_code_reports_a_refusal() {
+        let (code, status) = redirect("error=access_denied&error_description=The+user+declined&state=expected-state").await;
+
+        match code {
+            Err(Error::Auth(message)) => assert_eq!(message, "Dropbox did not authorize Arino: The user declined"),
+            other => panic!("expected an auth error, got {other:?}"),
+        }
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn wait_for_code_escapes_the_error_description() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let url = format!("http://{}/callback?error=access_denied&error_description=%3Cscript%3Ealert(1)%3C%2Fscript%3E", listener.local_addr().unwrap());
+
+        let browser = tokio::spawn(async move { Client::new().get(url).send().await.unwrap().text().await.unwrap() });
+        let _ = wait_for_code(&listener, "expected-state").await;
+        let page = browser.await.unwrap();
+
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    }
+
+    #[tokio::test]
+    async fn wait_for_code_rejects_another_state() {
+        let (code, status) = redirect("code=the-code&state=other-state").await;
+
+        assert!(matches!(code, Err(Error::Auth(_))));
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn exchange_code_posts_the_verifier_and_redirect_uri() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let profile = stub_profile(&listener.local_addr().unwrap().to_string());
+
+        let dropbox = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut stream).await;
+            let body = r#"{"access_token": "access", "expires_in": 14400, "refresh_token": "refresh"}"#;
+            let response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}", body.len());
+            stream.write_all(response.as_bytes()).await.unwrap();
+            request
+        });
+        let token_response = exchange_code(&profile, "app-key", "the-code", "the-verifier", Some("http://127.0.0.1:53682/callback")).await.unwrap();
+        let request = dropbox.await.unwrap();
+
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /oauth2/token "));
+        let form: HashMap<String, String> = Url::parse(&format!("http://stub/?{body}")).unwrap().query_pairs().into_owned().collect();
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["code"], "the-code");
+        assert_eq!(form["client_id"], "app-key");
+        assert_eq!(form["code_verifier"], "the-verifier");
+        assert_eq!(form["redirect_uri"], "http://127.0.0.1:53682/callback");
+        assert_eq!(token_response.access_token, "access");
+        assert_eq!(token_response.refresh_token.as_deref(), Some("refresh"));
+    }
+}
@@ -16,11 +16,27 @@ pub enum Command {
     FetchDatabase,
     SyncDatabase,
     BackupDatabase,
+    BackupList,
+    RestoreBackup,
+    Snapshots,
+    SnapshotsDiff,
+    SnapshotsRestore,
+    Diff,
     Quit,
     Help,
     Unknown,
     UpdateIngredient,
     UpdateDishName,
+    Import,
+    Export,
+    ImportWebRecipe,
+    ExportRecipe,
+    PricesOf,
+    OutputFormat,
+    Undo,
+    Redo,
+    History,
+    Doctor,
 }
 
 pub fn get_command_bimap() -> BiMap<Command, String> {
@@ -39,11 +55,48 @@ pub fn get_command_bimap() -> BiMap<Command, String> {
     bimap.insert(Command::FetchDatabase, "fetch database".to_string());
     bimap.insert(Command::SyncDatabase, "sync database".to_string());
     bimap.insert(Command::BackupDatabase, "backup database".to_string());
+    bimap.insert(Command::BackupList, "backup list".to_string());
+    bimap.insert(Command::RestoreBackup, "restore backup".to_string());
+    bimap.insert(Command::Snapshots, "snapshots".to_string());
+    bimap.insert(Command::SnapshotsDiff, "snapshots diff".to_string());
+    bimap.insert(Command::SnapshotsRestore, "snapshots restore".to_string());
+    bimap.insert(Command::Diff, "diff".to_string());
     bimap.insert(Command::Help, "help".to_string());
     bimap.insert(Command::Quit, "quit".to_string());
     bimap.insert(Command::Unknown, "unknown".to_string());
     bimap.insert(Command::UpdateIngredient, "update ingredient".to_string());
     bimap.insert(Command::UpdateDishName, "update dish".to_string());
+    bimap.insert(Command::Import, "import".to_string());
+    bimap.insert(Command::Export, "export".to_string());
+    bimap.insert(Command::ImportWebRecipe, "import web recipe".to_string());
+    bimap.insert(Command::ExportRecipe, "export recipe".to_string());
+    bimap.insert(Command::PricesOf, "prices of".to_string());
+    bimap.insert(Command::OutputFormat, "output format".to_string());
+    bimap.insert(Command::Undo, "undo".to_string());
+    bimap.insert(Command::Redo, "redo".to_string());
+    bimap.insert(Command::History, "history".to_string());
+    bimap.insert(Command::Doctor, "doctor".to_string());
 
     bimap
-}
\ No newline at end of file
+}
+
+pub fn get_command_aliases() -> Vec<(String, Command)> {
+    vec![
+        ("q".to_string(), Command::Quit),
+        ("exit".to_string(), Command::Quit),
+        ("?".to_string(), Command::Help),
+        ("ls".to_string(), Command::ListAllDishes),
+        ("dishes".to_string(), Command::ListAllDishes),
+        ("ingredients".to_string(), Command::ListAllIngredients),
+        ("recipe".to_string(), Command::RecipeOf),
+        ("prices".to_string(), Command::PricesOf),
+        ("format".to_string(), Command::OutputFormat),
+        ("rm dish".to_string(), Command::DeleteDish),
+        ("rm ingredient".to_string(), Command::DeleteIngredient),
+        ("fetch".to_string(), Command::FetchDatabase),
+        ("sync".to_string(), Command::SyncDatabase),
+        ("backup".to_string(), Command::BackupDatabase),
+        ("backups".to_string(), Command::BackupList),
+        ("restore".to_string(), Command::RestoreBackup),
+    ]
+}
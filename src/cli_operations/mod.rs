@@ -1,5 +1,8 @@
 pub mod user_input;
 pub mod commands;
+pub mod parser;
+pub mod script;
+pub mod output;
 
 use crate::helper::flush;
 
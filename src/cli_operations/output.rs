@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::io::{self, Write};
+use std::sync::Mutex;
+use prettytable::{Cell, Row, Table};
+use serde::Serialize;
+
+/// How listing commands print their results.
+/// Everything other than `Table` prints only the data on stdout so that it can be piped into other programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Ndjson,
+    Csv,
+}
+
+impl OutputFormat {
+    pub fn from_name(name: &str) -> Option<OutputFormat> {
+        match name {
+            "table" => Some(OutputFormat::Table),
+            "json" => Some(OutputFormat::Json),
+            "ndjson" | "jsonl" => Some(OutputFormat::Ndjson),
+            "csv" => Some(OutputFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+static OUTPUT_FORMAT: Mutex<OutputFormat> = Mutex::new(OutputFormat::Table);
+
+pub fn output_format() -> OutputFormat {
+    *OUTPUT_FORMAT.lock().expect("output format lock poisoned")
+}
+
+pub fn set_output_format(format: OutputFormat) {
+    *OUTPUT_FORMAT.lock().expect("output format lock poisoned") = format;
+}
+
+pub fn is_table_output() -> bool {
+    output_format() == OutputFormat::Table
+}
+
+/// Prints records in the current output format.
+/// `headers` and `to_cells` are only used for tables; the other formats use the serialized field names.
+pub fn print_records<T: Serialize>(records: &[T], headers: &[&str], to_cells: impl Fn(&T) -> Vec<String>) {
+    if output_format() == OutputFormat::Table {
+        let mut table = Table::new();
+        table.add_row(Row::new(headers.iter().map(|header| Cell::new(header)).collect()));
+        for record in records {
+            table.add_row(Row::new(to_cells(record).iter().map(|cell| Cell::new(cell)).collect()));
+        }
+        table.printstd();
+        return;
+    }
+
+    match write_records(records, io::stdout().lock()) {
+        Ok(_) => {},
+        // the reader of a pipe (such as `head`) stopped early
+        Err(e) if is_broken_pipe(e.as_ref()) => {},
+        Err(e) => eprintln!("Error printing output: {e}"),
+    }
+}
+
+fn write_records<T: Serialize>(records: &[T], mut out: impl Write) -> Result<(), Box<dyn Error>> {
+    match output_format() {
+        OutputFormat::Table => {},
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut out, records)?;
+            writeln!(out)?;
+        },
+        OutputFormat::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut out, record)?;
+                writeln!(out)?;
+            }
+        },
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(&mut out);
+            for record in records {
+                writer.serialize(record)?;
+            }
+            writer.flush()?;
+        },
+    }
+    out.flush()?;
+
+    Ok(())
+}
+
+fn is_broken_pipe(error: &(dyn Error + 'static)) -> bool {
+    let kind = match (error.downcast_ref::<io::Error>(), error.downcast_ref::<serde_json::Error>()) {
+        (Some(e), _) => Some(e.kind()),
+        (None, Some(e)) => e.io_error_kind(),
+        (None, None) => None,
+    };
+    kind == Some(io::ErrorKind::BrokenPipe)
+}
@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+use bimap::BiMap;
+
+use super::commands::{get_command_aliases, Command};
+use super::user_input::{prompt_raw, separate_by};
+
+pub struct ParsedCommand {
+    pub command: Command,
+    pub args: Arguments,
+}
+
+/// Values typed after the command name, separated by commas.
+/// Commands take their values from here first and only prompt for what is missing.
+/// Values are lowercased like prompted input, except when read with the `raw` variants.
+pub struct Arguments {
+    values: VecDeque<String>,
+    last_label: Option<String>,
+}
+
+impl Arguments {
+    pub fn new(values: Vec<String>) -> Arguments {
+        Arguments { values: values.into(), last_label: None }
+    }
+
+    pub fn empty() -> Arguments {
+        Arguments { values: VecDeque::new(), last_label: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the next inline value, or prompts for it once the inline values run out.
+    /// An inline value left blank (e.g. `update ingredient tomato, , 5d`) is returned as an empty string.
+    /// Asking for the same label twice in a row means the previous value was rejected,
+    /// so the user is prompted instead of consuming the value meant for the next field.
+    pub fn next_or_prompt(&mut self, label: &str) -> String {
+        self.next_raw_or_prompt(label).to_lowercase()
+    }
+
+    pub fn next_raw_or_prompt(&mut self, label: &str) -> String {
+        let is_retry = self.last_label.as_deref() == Some(label);
+        self.last_label = Some(label.to_string());
+
+        if is_retry {
+            return prompt_raw(label);
+        }
+
+        match self.values.pop_front() {
+            Some(value) => value,
+            None => prompt_raw(label),
+        }
+    }
+
+    pub fn next_value(&mut self) -> Option<String> {
+        self.next_raw_value().map(|value| value.to_lowercase())
+    }
+
+    pub fn next_raw_value(&mut self) -> Option<String> {
+        self.values.pop_front()
+    }
+
+    pub fn take_all(&mut self) -> Vec<String> {
+        self.values.drain(..).map(|value| value.to_lowercase()).collect()
+    }
+}
+
+/// Splits a line such as `rec of fried rice` into the command and its arguments.
+/// Every word of the command may be abbreviated to a prefix; aliases must be typed in full.
+/// The longest matching command wins, with exact matches preferred over abbreviations.
+pub fn parse_command(user_input: &str, command_bimap: &BiMap<Command, String>) -> Result<ParsedCommand, String> {
+    let lowercase_input = user_input.to_lowercase();
+    let input_words: Vec<&str> = lowercase_input.split_whitespace().collect();
+    if input_words.is_empty() {
+        return Err("Unknown command".to_string());
+    }
+
+    // (command, number of words consumed, typed in full)
+    let mut candidates: Vec<(Command, usize, bool)> = Vec::new();
+
+    for (command, name) in command_bimap.iter() {
+        if *command == Command::Unknown {
+            continue;
+        }
+        let name_words: Vec<&str> = name.split_whitespace().collect();
+        if name_words.len() > input_words.len() {
+            continue;
+        }
+        let is_prefix = input_words.iter().zip(name_words.iter()).all(|(input, word)| word.starts_with(input));
+        if is_prefix {
+            let is_exact = input_words.iter().zip(name_words.iter()).all(|(input, word)| input == word);
+            candidates.push((*command, name_words.len(), is_exact));
+        }
+    }
+
+    for (alias, command) in get_command_aliases() {
+        let alias_words: Vec<&str> = alias.split_whitespace().collect();
+        if alias_words.len() <= input_words.len() && input_words[..alias_words.len()] == alias_words[..] {
+            candidates.push((command, alias_words.len(), true));
+        }
+    }
+
+    let longest = match candidates.iter().map(|(_, length, _)| *length).max() {
+        Some(length) => length,
+        None => return Err(incomplete_command_message(&input_words, command_bimap)),
+    };
+    candidates.retain(|(_, length, _)| *length == longest);
+    if candidates.iter().any(|(_, _, is_exact)| *is_exact) {
+        candidates.retain(|(_, _, is_exact)| *is_exact);
+    }
+    candidates.sort_by_key(|(command, _, _)| command_bimap.get_by_left(command).cloned());
+    candidates.dedup_by_key(|(command, _, _)| *command);
+
+    if candidates.len() > 1 {
+        let matches: Vec<String> = candidates
+            .iter()
+            .filter_map(|(command, _, _)| command_bimap.get_by_left(command).cloned())
+            .collect();
+        return Err(format!("Ambiguous command, did you mean: {}?", matches.join(", ")));
+    }
+
+    let raw_words: Vec<&str> = user_input.split_whitespace().collect();
+    let remaining_input = raw_words[longest..].join(" ");
+    let args = if remaining_input.is_empty() {
+        Arguments::empty()
+    } else {
+        Arguments::new(separate_by(",", remaining_input))
+    };
+
+    Ok(ParsedCommand { command: candidates[0].0, args })
+}
+
+fn incomplete_command_message(input_words: &[&str], command_bimap: &BiMap<Command, String>) -> String {
+    let mut suggestions: Vec<String> = command_bimap
+        .right_values()
+        .filter(|name| {
+            let name_words: Vec<&str> = name.split_whitespace().collect();
+            name_words.len() > input_words.len()
+                && input_words.iter().zip(name_words.iter()).all(|(input, word)| word.starts_with(input))
+        })
+        .cloned()
+        .collect();
+    suggestions.sort();
+
+    if suggestions.is_empty() {
+        "Unknown command".to_string()
+    } else {
+        format!("Incomplete command, did you mean: {}?", suggestions.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli_operations::commands::get_command_bimap;
+
+    fn parse(user_input: &str) -> Result<ParsedCommand, String> {
+        parse_command(user_input, &get_command_bimap())
+    }
+
+    fn values(parsed_command: &ParsedCommand) -> Vec<&str> {
+        parsed_command.args.values.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn passes_inline_values_to_the_command() {
+        let parsed_command = parse("recipe of fried rice").unwrap();
+        assert_eq!(parsed_command.command, Command::RecipeOf);
+        assert_eq!(values(&parsed_command), ["fried rice"]);
+
+        let parsed_command = parse("new ingredient tomato, vegetable, 7d").unwrap();
+        assert_eq!(parsed_command.command, Command::NewIngredient);
+        assert_eq!(values(&parsed_command), ["tomato", "vegetable", "7d"]);
+
+        let parsed_command = parse("list all dishes").unwrap();
+        assert!(parsed_command.args.is_empty());
+    }
+
+    #[test]
+    fn accepts_unique_prefixes_of_every_word() {
+        let parsed_command = parse("rec of Fried Rice").unwrap();
+        assert_eq!(parsed_command.command, Command::RecipeOf);
+        // the case of the values is kept until they are read
+        assert_eq!(values(&parsed_command), ["Fried Rice"]);
+
+        assert_eq!(parse("new ing").unwrap().command, Command::NewIngredient);
+        assert_eq!(parse("NEW DISH pasta").unwrap().command, Command::NewDish);
+    }
+
+    #[test]
+    fn accepts_aliases_typed_in_full() {
+        assert_eq!(parse("q").unwrap().command, Command::Quit);
+        assert_eq!(parse("exit").unwrap().command, Command::Quit);
+
+        let parsed_command = parse("rm dish pasta").unwrap();
+        assert_eq!(parsed_command.command, Command::DeleteDish);
+        assert_eq!(values(&parsed_command), ["pasta"]);
+    }
+
+    #[test]
+    fn prefers_the_longest_command() {
+        // "recipe" alone is an alias of "recipe of", but "recipe of" consumes more words
+        let parsed_command = parse("recipe of pasta").unwrap();
+        assert_eq!(parsed_command.command, Command::RecipeOf);
+        assert_eq!(values(&parsed_command), ["pasta"]);
+
+        let parsed_command = parse("export recipe pasta").unwrap();
+        assert_eq!(parsed_command.command, Command::ExportRecipe);
+        assert_eq!(values(&parsed_command), ["pasta"]);
+    }
+
+    #[test]
+    fn reports_ambiguous_incomplete_and_unknown_commands() {
+        let error = parse("d").err().unwrap();
+        assert!(error.starts_with("Ambiguous command"), "{error}");
+        assert!(error.contains("doctor"), "{error}");
+
+        assert_eq!(parse("new").err().unwrap(), "Incomplete command, did you mean: new dish, new ingredient?");
+        assert_eq!(parse("frobnicate").err().unwrap(), "Unknown command");
+        assert_eq!(parse("   ").err().unwrap(), "Unknown command");
+    }
+
+    #[test]
+    fn reads_inline_values_before_prompting() {
+        let mut args = Arguments::new(vec!["Tomato".to_string(), "".to_string(), "~/Recipes.csv".to_string()]);
+
+        assert_eq!(args.next_or_prompt("Name"), "tomato");
+        // a blank inline value is returned instead of prompting
+        assert_eq!(args.next_or_prompt("Category"), "");
+        assert_eq!(args.next_raw_or_prompt("File"), "~/Recipes.csv");
+        assert!(args.is_empty());
+        assert_eq!(args.next_value(), None);
+    }
+
+    #[test]
+    fn takes_the_remaining_values_lowercased() {
+        let mut args = Arguments::new(vec!["Rice".to_string(), "Egg".to_string(), "Onion".to_string()]);
+
+        assert_eq!(args.next_raw_value().as_deref(), Some("Rice"));
+        assert_eq!(args.take_all(), ["egg", "onion"]);
+        assert!(args.is_empty());
+    }
+}
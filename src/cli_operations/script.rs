@@ -0,0 +1,236 @@
+use std::fs;
+use bimap::BiMap;
+use rusqlite::Connection;
+
+use crate::database::cloud::fetch_before_change;
+use crate::database::delete::InUsePolicy;
+use crate::database::{commit_change, delete, get_connection, insert, modify, snapshots, validate, Outcome};
+use crate::error::{self, Error, Result};
+use super::commands::{get_command_bimap, Command};
+use super::parser::{parse_command, Arguments};
+
+pub struct ScriptOptions {
+    pub dry_run: bool,
+    pub keep_going: bool,
+}
+
+struct ScriptLine {
+    number: usize,
+    text: String,
+}
+
+/// Parses `run <file> [--dry-run] [--keep-going]` and runs the script.
+/// Returns the exit code: 0 when every command succeeded, 1 when a command failed,
+/// 2 for invalid arguments and the error's own code when the script could not be run.
+pub async fn run_from_args(args: &[String]) -> i32 {
+    let mut path: Option<&String> = None;
+    let mut options = ScriptOptions { dry_run: false, keep_going: false };
+
+    for arg in args {
+        match arg.as_str() {
+            "--dry-run" => options.dry_run = true,
+            "--keep-going" => options.keep_going = true,
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("Unexpected argument \"{arg}\"");
+                return 2;
+            },
+        }
+    }
+
+    match path {
+        Some(path) => match run(path, &options).await {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => {
+                error::report(&e);
+                e.exit_code()
+            },
+        },
+        None => {
+            eprintln!("Usage: arino run <script> [--dry-run] [--keep-going]");
+            2
+        },
+    }
+}
+
+/// Runs every command of the script inside one transaction.
+/// By default the first failure rolls everything back; with `keep_going` the failed
+/// commands are skipped and reported while the rest are committed.
+/// A dry run validates every command against the local database and writes nothing.
+/// Returns whether every command succeeded, or an error when the script could not be run at all.
+pub async fn run(path: &str, options: &ScriptOptions) -> Result<bool> {
+    let script = fs::read_to_string(path).map_err(|e| Error::from(e).in_file(path))?;
+    let lines = script_lines(&script);
+
+    if !options.dry_run {
+        fetch_before_change("run a script").await?;
+        // a script may delete rows
+        snapshots::take("run script")?;
+    }
+
+    let command_bimap = get_command_bimap();
+    let mut conn = get_connection()?;
+
+    let mut failures: Vec<(usize, String)> = Vec::new();
+
+    let outcome = commit_change(&mut conn, &format!("run {path}"), |tx| {
+        for line in &lines {
+            let result = match tx.savepoint() {
+                Ok(savepoint) => match run_line(&line.text, &command_bimap, &savepoint) {
+                    Ok(_) => savepoint.commit().map_err(|e| e.into()),
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e.into()),
+            };
+
+            if let Err(e) = result {
+                eprintln!("{path}:{}: {e}", line.number);
+                failures.push((line.number, e.to_string()));
+                if !options.keep_going {
+                    break;
+                }
+            }
+        }
+
+        let stopped = !failures.is_empty() && !options.keep_going;
+        Ok(if stopped || options.dry_run { Outcome::Rollback } else { Outcome::Commit })
+    }).await?;
+
+    if !failures.is_empty() && !options.keep_going {
+        eprintln!("Script stopped at line {}, no changes were written", failures[0].0);
+        return Ok(false);
+    }
+
+    let succeeded = lines.len() - failures.len();
+
+    if outcome == Outcome::Rollback {
+        println!("Dry run: {succeeded} of {} commands are valid, nothing was written", lines.len());
+        return Ok(failures.is_empty());
+    }
+
+    println!("Ran {succeeded} of {} commands from {path}", lines.len());
+    if !failures.is_empty() {
+        println!("Skipped {} failed commands:", failures.len());
+        failures.iter().for_each(|(number, e)| println!("-- line {number}: {e}"));
+    }
+
+    Ok(failures.is_empty())
+}
+
+/// Skips blank lines and `#` comments, keeping the original line numbers for error reports.
+fn script_lines(script: &str) -> Vec<ScriptLine> {
+    script
+        .lines()
+        .enumerate()
+        .map(|(index, text)| ScriptLine { number: index + 1, text: text.trim().to_string() })
+        .filter(|line| !line.text.is_empty() && !line.text.starts_with('#'))
+        .collect()
+}
+
+fn run_line(text: &str, command_bimap: &BiMap<Command, String>, conn: &Connection) -> Result<()> {
+    let parsed_command = parse_command(text, command_bimap).map_err(Error::Validation)?;
+    let mut args = parsed_command.args;
+
+    match parsed_command.command {
+        Command::NewIngredient => {
+            let name = required(&mut args, "ingredient name")?;
+            let category = required(&mut args, "category")?;
+            let lifespan = args.next_value().unwrap_or_default();
+            no_more(&mut args)?;
+            insert::ingredient_row(&name, &category, &lifespan, conn)
+        },
+        Command::AddPrice => {
+            let ingredient = required(&mut args, "ingredient name")?;
+            let price = required(&mut args, "price")?;
+            no_more(&mut args)?;
+            let price = validate::price_text(&price)?;
+            insert::price_row(&ingredient, price, conn)
+        },
+        Command::NewDish => {
+            let dish = required(&mut args, "dish name")?;
+            insert::dish_row(&dish, conn)?;
+            recipe_lines(&dish, &mut args, conn)
+        },
+        Command::AddRecipe => {
+            let dish = required(&mut args, "dish name")?;
+            if args.is_empty() {
+                return Err(Error::Validation("Expected at least one ingredient name and quantity".to_string()));
+            }
+            recipe_lines(&dish, &mut args, conn)
+        },
+        Command::DeleteIngredientFromRecipe => {
+            let dish = required(&mut args, "dish name")?;
+            let ingredient = required(&mut args, "ingredient name")?;
+            no_more(&mut args)?;
+            delete::recipe_row(&dish, &ingredient, conn)
+        },
+        Command::DeleteDish => {
+            let dish = required(&mut args, "dish name")?;
+            no_more(&mut args)?;
+            delete::dish_row(&dish, conn)
+        },
+        Command::DeleteIngredient => {
+            let ingredient = required(&mut args, "ingredient name")?;
+            let policy_name = args.next_value().unwrap_or_default();
+            let replacement = args.next_value().unwrap_or_default();
+            no_more(&mut args)?;
+            let policy = match InUsePolicy::from_name(&policy_name, &replacement) {
+                Some(policy) => policy,
+                None => return Err(Error::Validation("Expected \"block\", \"cascade\" or \"replace, <ingredient>\" after the ingredient name".to_string())),
+            };
+            delete::ingredient_row(&ingredient, &policy, conn)
+        },
+        Command::UpdateIngredient => {
+            let ingredient = required(&mut args, "ingredient name")?;
+            let new_name = args.next_value().unwrap_or_default();
+            let new_lifespan = args.next_value().unwrap_or_default();
+            let category = args.next_value().unwrap_or_default();
+            no_more(&mut args)?;
+            modify::ingredient_row(&ingredient, &new_name, &new_lifespan, &category, conn)
+        },
+        Command::UpdateDishName => {
+            let dish = required(&mut args, "dish name")?;
+            let new_name = required(&mut args, "new dish name")?;
+            no_more(&mut args)?;
+            modify::dish_name_row(&dish, &new_name, conn)
+        },
+        _ => {
+            let name = command_bimap.get_by_left(&parsed_command.command).cloned().unwrap_or_default();
+            Err(Error::Validation(format!("\"{name}\" cannot be used in a script")))
+        },
+    }
+}
+
+/// Consumes the remaining `ingredient, quantity` pairs of a recipe.
+fn recipe_lines(dish: &str, args: &mut Arguments, conn: &Connection) -> Result<()> {
+    while let Some(ingredient) = args.next_value() {
+        let quantity = match args.next_value() {
+            Some(quantity) => quantity,
+            None => return Err(Error::Validation(format!("Missing quantity for \"{ingredient}\""))),
+        };
+        let quantity = match validate::quantity_text(&quantity) {
+            Ok(quantity) => quantity,
+            Err(e) => return Err(Error::Validation(format!("{e} for \"{ingredient}\""))),
+        };
+        insert::recipe_row(dish, &ingredient, quantity, None, conn)?;
+    }
+
+    Ok(())
+}
+
+fn required(args: &mut Arguments, name: &str) -> Result<String> {
+    match args.next_value() {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(Error::Validation(format!("Missing {name}"))),
+    }
+}
+
+fn no_more(args: &mut Arguments) -> Result<()> {
+    let extra = args.take_all();
+    if extra.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Validation(format!("Unexpected arguments: {}", extra.join(", "))))
+    }
+}
@@ -1,80 +1,122 @@
 use bimap::BiMap;
-use rusqlite::Result;
 use database::show;
-use crate::database::cloud::{backup, fetch, has_internet_access, sync, Database};
-use crate::database::{self, delete, insert, modify};
+use crate::database::cloud::{fetch, fetch_replacing_changes, finish_sync, require_internet_access, sync, sync_replacing_remote, Database};
+use crate::database::{self, backups, csv_io, delete, delete_transaction, diff, doctor, history, insert, modify, snapshots, web_recipe, write_transaction};
+use crate::error::{Error, Result};
 use crate::helper::flush;
 use std::io::stdin;
-use super::commands::Command;
+use super::commands::{get_command_aliases, Command};
+use super::output::{set_output_format, OutputFormat};
+use super::parser::{Arguments, ParsedCommand};
 
 
-pub fn to_command_enum(user_input: String, command_bimap: &BiMap<Command, String>) -> Command {
-    if let Some(command_enum) = command_bimap.get_by_right(&user_input) {
-        command_enum.clone()
-    } else {
-        Command::Unknown
-    }
-}
-
-pub async fn match_commands(command_enum: Command, command_bimap: &BiMap<Command, String>) -> Result<()> {
-    match command_enum {
-        Command::NewIngredient => insert::ingredient().await,
-        Command::AddPrice => insert::price().await,
-        Command::NewDish => insert::dish().await,
-        Command::AddRecipe => insert::recipe(None).await,
-        Command::ListAllDishes => show::all_dish_names(),
-        Command::ListAllIngredients => show::all_ingredients(),
-        Command::IHave => show::dish_by_ingredients::get_dishes(),
-        Command::RecipeOf => show::recipe_by_dish_name(),
-        Command::DeleteIngredientFromRecipe => delete::ingredient_from_recipe().await,
-        Command::DeleteDish => delete::dish().await,
-        Command::DeleteIngredient => delete::ingredient().await,
+pub async fn match_commands(parsed_command: ParsedCommand, command_bimap: &BiMap<Command, String>) -> Result<()> {
+    let ParsedCommand { command, mut args } = parsed_command;
+    let command_name = command_bimap.get_by_left(&command).cloned().unwrap_or_default();
+    match command {
+        Command::NewIngredient => write_transaction(&command_name, |conn| insert::ingredient(&mut args, conn)).await?,
+        Command::AddPrice => write_transaction(&command_name, |conn| insert::price(&mut args, conn)).await?,
+        Command::NewDish => write_transaction(&command_name, |conn| insert::dish(&mut args, conn)).await?,
+        Command::AddRecipe => write_transaction(&command_name, |conn| insert::recipe(None, &mut args, conn)).await?,
+        Command::ListAllDishes => show::all_dish_names()?,
+        Command::ListAllIngredients => show::all_ingredients(&mut args)?,
+        Command::IHave => show::dish_by_ingredients::get_dishes(&mut args)?,
+        Command::RecipeOf => show::recipe_by_dish_name(&mut args)?,
+        Command::DeleteIngredientFromRecipe => delete_transaction(&command_name, |conn| delete::ingredient_from_recipe(&mut args, conn)).await?,
+        Command::DeleteDish => delete_transaction(&command_name, |conn| delete::dish(&mut args, conn)).await?,
+        Command::DeleteIngredient => delete_transaction(&command_name, |conn| delete::ingredient(&mut args, conn)).await?,
         Command::FetchDatabase => {
-            if has_internet_access().await {
-                fetch(Database::Main).await.expect("Error fetching database");
+            require_internet_access("fetch database from cloud").await?;
+            // `fetch preview` shows what the fetch replaces before asking
+            if is_preview(&mut args) {
+                if !diff::confirm("local", "remote", "Replace the local database with the one in Dropbox?").await? {
+                    println!("Fetch canceled");
+                    return Ok(());
+                }
+                fetch_replacing_changes().await?;
             } else {
-                eprintln!("Internet access is required to fetch database from cloud");
+                fetch(Database::Main).await?;
             }
-            Ok(())
         },
         Command::SyncDatabase => {
-            if has_internet_access().await {
-                sync().await.expect("Error syncing database");
+            require_internet_access("sync database to cloud").await?;
+            // `sync preview` shows what the sync replaces before asking, and then replaces it even when another device changed it
+            if is_preview(&mut args) {
+                if !diff::confirm("remote", "local", "Replace the database in Dropbox with the local one?").await? {
+                    println!("Sync canceled");
+                    return Ok(());
+                }
+                sync_replacing_remote().await?;
             } else {
-                eprintln!("Internet access is required to sync database to cloud");
+                sync().await?;
             }
-            Ok(())
         },
+        Command::Diff => diff::run(&mut args).await?,
         Command::BackupDatabase => {
-            if has_internet_access().await {
-                backup().await.expect("Error backing up database");
-            } else {
-                eprintln!("Internet access is required to backup database to cloud");
-            }
-            Ok(())
+            require_internet_access("backup database to cloud").await?;
+            backups::create().await?;
         },
-        Command::Help => {
-            list_all_commands(command_bimap);
-            Ok(())
+        Command::BackupList => {
+            require_internet_access("list the backups in the cloud").await?;
+            backups::list().await?;
         },
-        Command::Quit => std::process::exit(0),
-        Command::Unknown => {
-            eprintln!("Unknown command");
-            Ok(())
-        }
+        Command::RestoreBackup => {
+            require_internet_access("restore a backup from the cloud").await?;
+            backups::restore(&mut args).await?;
+        },
+        Command::Snapshots => snapshots::list()?,
+        Command::SnapshotsDiff => snapshots::diff(&mut args)?,
+        Command::SnapshotsRestore => snapshots::restore(&mut args).await?,
+        Command::Help => list_all_commands(command_bimap),
+        Command::Quit => {
+            finish_sync().await;
+            std::process::exit(0)
+        },
+        Command::Unknown => return Err(Error::Validation("Unknown command".to_string())),
         Command::UpdateIngredient => {
-            modify::ingredient().await
+            write_transaction(&command_name, |conn| modify::ingredient(&mut args, conn)).await?
         }
         Command::UpdateDishName => {
-            modify::dish_name().await
+            write_transaction(&command_name, |conn| modify::dish_name(&mut args, conn)).await?
         }
+        Command::Import => csv_io::import(&mut args).await?,
+        Command::Export => csv_io::export(&mut args)?,
+        Command::ImportWebRecipe => web_recipe::import(&mut args).await?,
+        Command::ExportRecipe => show::recipe_export::export(&mut args)?,
+        Command::PricesOf => show::prices_of(&mut args)?,
+        Command::OutputFormat => {
+            let format_name = args.next_or_prompt("Format (table, json, ndjson, csv)");
+            match OutputFormat::from_name(&format_name) {
+                Some(format) => set_output_format(format),
+                None => return Err(Error::Validation(format!("Unknown format \"{format_name}\", expected table, json, ndjson or csv"))),
+            }
+        },
+        Command::Undo => write_transaction(&command_name, |conn| Ok(history::undo(conn)?)).await?,
+        Command::Redo => write_transaction(&command_name, |conn| Ok(history::redo(conn)?)).await?,
+        Command::History => history::show(&mut args)?,
+        Command::Doctor => doctor::run(&command_name, &mut args).await?,
     }
+
+    Ok(())
+}
+
+/// Whether `fetch` or `sync` was typed with `preview`, which shows the row changes and asks before going ahead.
+fn is_preview(args: &mut Arguments) -> bool {
+    args.next_value().is_some_and(|value| value == "preview")
 }
 
 fn list_all_commands(command_bimap: &BiMap<Command, String>) {
     let mut commands: Vec<_> = command_bimap.right_values().cloned().collect();
     commands.sort();
     commands.iter().for_each(|s| println!("-- {s}"));
+
+    println!("Aliases:");
+    get_command_aliases().iter().for_each(|(alias, command)| {
+        if let Some(name) = command_bimap.get_by_left(command) {
+            println!("-- {alias} ({name})");
+        }
+    });
+    println!("Commands can be abbreviated (e.g. \"rec of\") and take their values inline, separated by commas (e.g. \"new ingredient tomato, vegetable, 7d\")");
 }
 
 pub fn separate_by(separator: &str, user_input: String) -> Vec<String>{
@@ -85,14 +127,19 @@ pub fn separate_by(separator: &str, user_input: String) -> Vec<String>{
 }
 
 pub fn prompt(prompt: &str) -> String {
+    prompt_raw(prompt).to_lowercase()
+}
+
+/// Same as `prompt` but keeps the case of the input, for values such as file paths.
+pub fn prompt_raw(prompt: &str) -> String {
     let mut user_input = String::new();
     print!("{}> ", prompt);
     flush();
     match stdin().read_line(&mut user_input) {
-        Ok(_) => return user_input.trim().to_lowercase().to_string(),
+        Ok(_) => user_input.trim().to_string(),
         Err(e) => {
             eprint!("{e}");
-            return user_input;
+            user_input
         },
     }
 }
\ No newline at end of file
@@ -0,0 +1,283 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+
+const CONFIG_FILE: &str = "config.json";
+const PROFILE_ENV: &str = "ARINO_PROFILE";
+const DEFAULT_PROFILE: &str = "default";
+const DEFAULT_REMOTE: &str = "/database.db";
+const DEFAULT_CREDENTIALS: &str = "credentials.enc";
+const DEFAULT_KEY: &str = "key.json";
+const DEFAULT_AUTHORIZE_URL: &str = "https://www.dropbox.com/oauth2/authorize";
+const DEFAULT_API_URL: &str = "https://api.dropboxapi.com";
+const DEFAULT_CONTENT_URL: &str = "https://content.dropboxapi.com";
+const DEFAULT_SNAPSHOTS: usize = 10;
+const DEFAULT_KEEP_LAST: usize = 3;
+const DEFAULT_KEEP_DAILY: usize = 7;
+const DEFAULT_KEEP_WEEKLY: usize = 4;
+
+/// `config.json` in the config dir, for example
+/// `{"default_profile": "home", "profiles": {"home": {"db": "~/recipes/home.db"}, "test": {"db": "test.db", "remote": "/test/database.db"}}}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct Config {
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, ProfileEntry>,
+}
+
+/// A profile as written in the config file; missing fields use the defaults.
+/// Relative paths are relative to the config dir.
+#[derive(Debug, Default, Deserialize)]
+pub struct ProfileEntry {
+    pub db: Option<String>,
+    /// Path of the database in Dropbox.
+    pub remote: Option<String>,
+    /// Encrypted store of the Dropbox credentials and access token.
+    pub credentials: Option<String>,
+    /// Plain Dropbox app credentials of older versions, moved into the store on first use.
+    pub key: Option<String>,
+    /// Dropbox app key used by `auth login`.
+    pub app_key: Option<String>,
+    /// Dropbox endpoints, changed to test against a local server.
+    pub authorize_url: Option<String>,
+    pub api_url: Option<String>,
+    pub content_url: Option<String>,
+    /// How many of the newest backups are kept, 3 by default.
+    pub keep_last: Option<usize>,
+    /// How many days and weeks keep their newest backup, 7 and 4 by default.
+    pub keep_daily: Option<usize>,
+    pub keep_weekly: Option<usize>,
+    /// How many local snapshots are kept, 10 by default; 0 turns them off.
+    pub snapshots: Option<usize>,
+    /// Encrypts the database before it is uploaded to Dropbox, off by default.
+    pub encrypt: Option<bool>,
+}
+
+/// Where the Dropbox OAuth, API and file requests are sent.
+#[derive(Debug, Clone)]
+pub struct Endpoints {
+    pub authorize: String,
+    pub api: String,
+    pub content: String,
+}
+
+/// Which cloud backups survive a new backup: the `last` newest ones, and the newest one of each of the last `daily` days
+/// and of each of the last `weekly` weeks that have a backup.
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    pub last: usize,
+    pub daily: usize,
+    pub weekly: usize,
+}
+
+/// The database and remote target that every command works with.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub db: PathBuf,
+    pub remote: String,
+    pub credentials: PathBuf,
+    pub key: PathBuf,
+    pub app_key: Option<String>,
+    pub endpoints: Endpoints,
+    pub retention: Retention,
+    pub snapshot_count: usize,
+    pub encrypt: bool,
+}
+
+impl Profile {
+    fn resolve(name: &str, entry: &ProfileEntry) -> Profile {
+        let db = match &entry.db {
+            Some(db) => config_relative(db),
+            None if name == DEFAULT_PROFILE => data_dir().join("database.db"),
+            None => data_dir().join(format!("{name}.db")),
+        };
+        Profile {
+            name: name.to_string(),
+            db,
+            remote: entry.remote.clone().unwrap_or_else(|| DEFAULT_REMOTE.to_string()),
+            credentials: entry.credentials.as_deref().map(config_relative).unwrap_or_else(|| config_dir().join(DEFAULT_CREDENTIALS)),
+            key: entry.key.as_deref().map(config_relative).unwrap_or_else(default_key),
+            app_key: entry.app_key.clone(),
+            endpoints: Endpoints {
+                authorize: endpoint(&entry.authorize_url, DEFAULT_AUTHORIZE_URL),
+                api: endpoint(&entry.api_url, DEFAULT_API_URL),
+                content: endpoint(&entry.content_url, DEFAULT_CONTENT_URL),
+            },
+            retention: Retention {
+                last: entry.keep_last.unwrap_or(DEFAULT_KEEP_LAST),
+                daily: entry.keep_daily.unwrap_or(DEFAULT_KEEP_DAILY),
+                weekly: entry.keep_weekly.unwrap_or(DEFAULT_KEEP_WEEKLY),
+            },
+            snapshot_count: entry.snapshots.unwrap_or(DEFAULT_SNAPSHOTS),
+            encrypt: entry.encrypt.unwrap_or(false),
+        }
+    }
+
+    /// Creates the folder of the database, so that a new profile can fetch its database.
+    pub fn create_db_dir(&self) -> Result<()> {
+        if let Some(parent) = self.db.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        Ok(())
+    }
+
+    /// Folder of the local snapshots, next to the database: `~/.local/share/arino/database.db` is snapshotted into
+    /// `~/.local/share/arino/snapshots/database`, so that a `--db` file keeps its own snapshots.
+    pub fn snapshot_dir(&self) -> PathBuf {
+        let stem = self.db.file_stem().map(|stem| stem.to_os_string()).unwrap_or_else(|| "database".into());
+        self.db.with_file_name("snapshots").join(stem)
+    }
+
+    /// Remembers which Dropbox revision the database was last fetched or synced at, next to the database:
+    /// `database.db` has `database.sync.json`.
+    pub fn sync_state_path(&self) -> PathBuf {
+        self.db.with_extension("sync.json")
+    }
+
+    /// Folder of the backups in Dropbox, next to the database: `/database.db` is backed up into `/database_backups`.
+    pub fn backup_dir(&self) -> String {
+        match self.remote.rsplit_once('.') {
+            Some((stem, extension)) if !extension.contains('/') => format!("{stem}_backups"),
+            _ => format!("{}_backups", self.remote),
+        }
+    }
+}
+
+static ACTIVE_PROFILE: Mutex<Option<Profile>> = Mutex::new(None);
+
+/// The profile selected at start, or the default profile.
+pub fn active_profile() -> Profile {
+    let active = ACTIVE_PROFILE.lock().expect("profile lock poisoned");
+    match &*active {
+        Some(profile) => profile.clone(),
+        None => Profile::resolve(DEFAULT_PROFILE, &ProfileEntry::default()),
+    }
+}
+
+pub fn set_active_profile(profile: Profile) {
+    *ACTIVE_PROFILE.lock().expect("profile lock poisoned") = Some(profile);
+}
+
+/// `$XDG_CONFIG_HOME/arino`, or `~/.config/arino`.
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_DATA_HOME/arino`, or `~/.local/share/arino`, where databases without a configured path are kept.
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+pub fn config_path() -> PathBuf {
+    config_dir().join(CONFIG_FILE)
+}
+
+fn xdg_dir(variable: &str, fallback: &str) -> PathBuf {
+    let base = match env::var_os(variable) {
+        Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir),
+        _ => home_dir().join(fallback),
+    };
+    base.join("arino")
+}
+
+fn home_dir() -> PathBuf {
+    env::var_os("HOME").map(PathBuf::from).unwrap_or_else(|| PathBuf::from("."))
+}
+
+fn endpoint(configured: &Option<String>, default: &str) -> String {
+    configured.as_deref().unwrap_or(default).trim_end_matches('/').to_string()
+}
+
+/// The `key.json` of older versions, which read it from the directory Arino was started in before profiles moved it to the config dir.
+fn default_key() -> PathBuf {
+    let key = config_dir().join(DEFAULT_KEY);
+    match key.exists() {
+        true => key,
+        false => env::current_dir().map(|dir| dir.join(DEFAULT_KEY)).ok().filter(|key| key.exists()).unwrap_or(key),
+    }
+}
+
+/// Expands `~` and makes relative paths relative to the config dir, so that they do not depend on where Arino is started.
+fn config_relative(path: &str) -> PathBuf {
+    let path = match path.strip_prefix("~/") {
+        Some(rest) => home_dir().join(rest),
+        None => PathBuf::from(path),
+    };
+    if path.is_absolute() {
+        path
+    } else {
+        config_dir().join(path)
+    }
+}
+
+/// Reads the config file; without one every command uses the default profile.
+pub fn load() -> Result<Config> {
+    let path = config_path();
+    let json_string = match fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(e) => return Err(Error::from(e).in_file(&path.to_string_lossy())),
+    };
+
+    serde_json::from_str(&json_string).map_err(|e| Error::Validation(format!("{}: {e}", path.display())))
+}
+
+/// Picks the profile named by `--profile`, then `ARINO_PROFILE`, then `default_profile`,
+/// and points it at the `--db` file when one is given.
+pub fn select(config: &Config, profile_name: Option<&str>, db: Option<&str>) -> Result<Profile> {
+    let name = match profile_name {
+        Some(name) => Some(name.to_string()),
+        None => env::var(PROFILE_ENV).ok().filter(|name| !name.is_empty()).or_else(|| config.default_profile.clone()),
+    };
+
+    let name = name.unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+
+    let mut profile = match config.profiles.get(&name) {
+        Some(entry) => Profile::resolve(&name, entry),
+        None if name == DEFAULT_PROFILE => Profile::resolve(DEFAULT_PROFILE, &ProfileEntry::default()),
+        None => return Err(Error::NotFound(format!("Unknown profile \"{name}\", {}", known_profiles(config)))),
+    };
+
+    // A path given on the command line is relative to where Arino is started
+    if let Some(db) = db {
+        profile.db = env::current_dir()?.join(db);
+    }
+
+    Ok(profile)
+}
+
+fn known_profiles(config: &Config) -> String {
+    if config.profiles.is_empty() {
+        format!("no profiles are defined in {}", config_path().display())
+    } else {
+        let names: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+        format!("the profiles in {} are: {}", config_path().display(), names.join(", "))
+    }
+}
+
+/// `arino profiles` lists the configured profiles, marking the active one.
+pub fn print_profiles(config: &Config) {
+    let active = active_profile();
+    println!("Config file: {}", config_path().display());
+
+    let mut names: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+    if !names.contains(&DEFAULT_PROFILE) {
+        names.insert(0, DEFAULT_PROFILE);
+    }
+    for name in names {
+        // the active profile is shown as selected, including a `--db` override
+        let (marker, profile) = match config.profiles.get(name) {
+            _ if name == active.name => ("*", active.clone()),
+            Some(entry) => (" ", Profile::resolve(name, entry)),
+            None => (" ", Profile::resolve(name, &ProfileEntry::default())),
+        };
+        println!("{marker} {name}: {} -> Dropbox {}", profile.db.display(), profile.remote);
+    }
+}
@@ -0,0 +1,444 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Profile;
+use crate::error::{Error, Result};
+
+/// Unlocks the store without a prompt, for scripts and servers.
+pub const PASSPHRASE_ENV: &str = "ARINO_PASSPHRASE";
+const STORE_VERSION: u32 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+/// Dropbox access tokens live for four hours when the answer does not say otherwise.
+const DEFAULT_TOKEN_LIFETIME_SECS: u64 = 4 * 60 * 60;
+/// A token is refreshed this long before it expires, so that it does not expire in the middle of a command.
+const EXPIRY_MARGIN_SECS: u64 = 60;
+
+/// The Dropbox app credentials and the current access token.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Credentials {
+    pub client_id: String,
+    /// Not needed by apps authorized with PKCE.
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    pub refresh_token: String,
+    #[serde(default)]
+    pub access_token: Option<String>,
+    /// Unix time at which the access token expires.
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+}
+
+impl Credentials {
+    /// The access token, unless it is missing or about to expire.
+    pub fn valid_access_token(&self) -> Option<&str> {
+        match (&self.access_token, self.expires_at) {
+            (Some(access_token), Some(expires_at)) if now() + EXPIRY_MARGIN_SECS < expires_at => Some(access_token),
+            _ => None,
+        }
+    }
+
+    /// Seconds until the access token expires, 0 once it has.
+    pub fn seconds_left(&self) -> Option<u64> {
+        self.expires_at.map(|expires_at| expires_at.saturating_sub(now()))
+    }
+
+    pub fn set_access_token(&mut self, access_token: String, expires_in: Option<u64>) {
+        self.access_token = Some(access_token);
+        self.expires_at = Some(now() + expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME_SECS));
+    }
+}
+
+/// The plain `key.json` of older versions, imported into the store on first use.
+#[derive(Deserialize)]
+struct LegacyKey {
+    client_id: String,
+    client_secret: String,
+    refresh_token: String,
+}
+
+/// The store as written to disk: the credentials encrypted with XChaCha20-Poly1305,
+/// under a key derived from the passphrase with Argon2id.
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// The key of the store that was last unlocked, so that the passphrase is asked once per run.
+struct UnlockedStore {
+    path: PathBuf,
+    salt: Vec<u8>,
+    key: [u8; 32],
+}
+
+static UNLOCKED_STORE: Mutex<Option<UnlockedStore>> = Mutex::new(None);
+
+/// Reads the credentials of the profile, importing its `key.json` when the store does not exist yet.
+pub fn load(profile: &Profile) -> Result<Credentials> {
+    if !profile.credentials.exists() {
+        return import_legacy_key(profile);
+    }
+
+    read_store(profile)
+}
+
+/// Same as `load`, but a `key.json` is only read, for `auth status`, which changes nothing.
+pub fn read(profile: &Profile) -> Result<Credentials> {
+    if !profile.credentials.exists() {
+        return read_legacy_key(profile);
+    }
+
+    read_store(profile)
+}
+
+fn read_store(profile: &Profile) -> Result<Credentials> {
+    check_permissions(&profile.credentials)?;
+
+    let envelope: Envelope = serde_json::from_str(&fs::read_to_string(&profile.credentials)?)
+        .map_err(|e| Error::Auth(format!("{} cannot be read: {e}", profile.credentials.display())))?;
+    // A damaged store is reported before the passphrase is asked
+    let (salt, _, _) = parts(&envelope, &profile.credentials)?;
+
+    let key = match cached_key(&profile.credentials, &salt) {
+        Some(key) => key,
+        None => derive_key(&passphrase(&format!("Passphrase for {}", profile.credentials.display()))?, &salt)?,
+    };
+    let credentials = open(&envelope, &key, &profile.credentials)?;
+
+    cache_key(&profile.credentials, salt, key);
+
+    Ok(credentials)
+}
+
+/// Encrypts the credentials into the profile's store, readable only by the current user.
+/// A new store asks for a new passphrase.
+pub fn save(profile: &Profile, credentials: &Credentials) -> Result<()> {
+    // An existing store keeps its passphrase, which unlocking it checks
+    if profile.credentials.exists() {
+        load(profile)?;
+    }
+    let (salt, key) = match stored_salt(&profile.credentials).and_then(|salt| Some((salt.clone(), cached_key(&profile.credentials, &salt)?))) {
+        Some((salt, key)) => (salt, key),
+        None => {
+            let mut salt = vec![0u8; SALT_LENGTH];
+            OsRng.fill_bytes(&mut salt);
+            let key = derive_key(&new_passphrase(&profile.credentials)?, &salt)?;
+            (salt, key)
+        },
+    };
+
+    let envelope = seal(credentials, &salt, &key)?;
+    let json_string = serde_json::to_string_pretty(&envelope).map_err(|e| Error::Auth(e.to_string()))?;
+    write_private(&profile.credentials, json_string.as_bytes())?;
+
+    cache_key(&profile.credentials, salt, key);
+
+    Ok(())
+}
+
+/// Deletes the profile's store and the `key.json` it would otherwise be imported from again.
+/// Returns whether there was anything to delete.
+pub fn remove(profile: &Profile) -> Result<bool> {
+    let mut removed = false;
+    for path in [&profile.credentials, &profile.key] {
+        if path.exists() {
+            fs::remove_file(path)?;
+            removed = true;
+        }
+    }
+    *UNLOCKED_STORE.lock().expect("credential lock poisoned") = None;
+
+    Ok(removed)
+}
+
+fn import_legacy_key(profile: &Profile) -> Result<Credentials> {
+    let credentials = read_legacy_key(profile)?;
+    println!("Moving the credentials of {} into the encrypted store {}", profile.key.display(), profile.credentials.display());
+    save(profile, &credentials)?;
+    println!("{} is no longer needed and can be deleted", profile.key.display());
+
+    Ok(credentials)
+}
+
+fn read_legacy_key(profile: &Profile) -> Result<Credentials> {
+    let json_string = match fs::read_to_string(&profile.key) {
+        Ok(s) => s,
+        Err(_) => return Err(Error::Auth(format!("No Dropbox credentials found in {} or {}", profile.credentials.display(), profile.key.display()))),
+    };
+    let legacy_key: LegacyKey = serde_json::from_str(&json_string)
+        .map_err(|e| Error::Auth(format!("{} cannot be read: {e}", profile.key.display())))?;
+
+    Ok(Credentials {
+        client_id: legacy_key.client_id,
+        client_secret: Some(legacy_key.client_secret),
+        refresh_token: legacy_key.refresh_token,
+        access_token: None,
+        expires_at: None,
+    })
+}
+
+/// Encrypts the credentials with a fresh nonce.
+fn seal(credentials: &Credentials, salt: &[u8], key: &[u8; 32]) -> Result<Envelope> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let plaintext = serde_json::to_vec(credentials).map_err(|e| Error::Auth(e.to_string()))?;
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| Error::Auth("The credentials could not be encrypted".to_string()))?;
+
+    Ok(Envelope {
+        version: STORE_VERSION,
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+    })
+}
+
+/// Decrypts the credentials written by `seal`.
+fn open(envelope: &Envelope, key: &[u8; 32], path: &Path) -> Result<Credentials> {
+    let (_, nonce, ciphertext) = parts(envelope, path)?;
+    let plaintext = XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| Error::Auth(format!("Wrong passphrase for {}", path.display())))?;
+
+    serde_json::from_slice(&plaintext).map_err(|e| Error::Auth(format!("{} cannot be read: {e}", path.display())))
+}
+
+/// The salt, nonce and ciphertext of a store, once they are known to be usable.
+fn parts(envelope: &Envelope, path: &Path) -> Result<(Vec<u8>, Vec<u8>, Vec<u8>)> {
+    if envelope.version != STORE_VERSION {
+        return Err(Error::Auth(format!("{} was written by a newer version of Arino", path.display())));
+    }
+    let salt = decode(&envelope.salt, path)?;
+    let nonce = decode(&envelope.nonce, path)?;
+    let ciphertext = decode(&envelope.ciphertext, path)?;
+    if nonce.len() != NONCE_LENGTH {
+        return Err(Error::Auth(format!("{} cannot be read: the nonce is {} bytes instead of {NONCE_LENGTH}", path.display(), nonce.len())));
+    }
+
+    Ok((salt, nonce, ciphertext))
+}
+
+/// The store holds secrets, so it is refused when other users could read or change it.
+#[cfg(unix)]
+fn check_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mode = fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        return Err(Error::Auth(format!(
+            "{} can be accessed by other users (mode {:o}), restrict it with `chmod 600 {}`",
+            path.display(),
+            mode & 0o777,
+            path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// Replaces the file through a temporary file created with owner-only permissions.
+fn write_private(path: &Path, content: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temporary_path = path.with_extension("tmp");
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temporary_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&temporary_path, path)?;
+
+    Ok(())
+}
+
+fn passphrase(prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    let passphrase = rpassword::prompt_password(format!("{prompt}> "))?;
+    if passphrase.is_empty() {
+        return Err(Error::Auth("A passphrase is required to unlock the credentials".to_string()));
+    }
+
+    Ok(passphrase)
+}
+
+fn new_passphrase(path: &Path) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    println!("Choose a passphrase to encrypt {}", path.display());
+    let passphrase = passphrase("New passphrase")?;
+    if passphrase != self::passphrase("Repeat passphrase")? {
+        return Err(Error::Auth("The passphrases do not match".to_string()));
+    }
+
+    Ok(passphrase)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::Auth(format!("The passphrase cannot be used: {e}")))?;
+
+    Ok(key)
+}
+
+fn cached_key(path: &Path, salt: &[u8]) -> Option<[u8; 32]> {
+    let unlocked_store = UNLOCKED_STORE.lock().expect("credential lock poisoned");
+    unlocked_store
+        .as_ref()
+        .filter(|store| store.path == path && store.salt == salt)
+        .map(|store| store.key)
+}
+
+fn cache_key(path: &Path, salt: Vec<u8>, key: [u8; 32]) {
+    *UNLOCKED_STORE.lock().expect("credential lock poisoned") = Some(UnlockedStore { path: path.to_path_buf(), salt, key });
+}
+
+/// The salt of an existing store, so that rewriting it keeps the passphrase.
+fn stored_salt(path: &Path) -> Option<Vec<u8>> {
+    let envelope: Envelope = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+    BASE64.decode(envelope.salt).ok()
+}
+
+fn decode(text: &str, path: &Path) -> Result<Vec<u8>> {
+    BASE64
+        .decode(text)
+        .map_err(|e| Error::Auth(format!("{} is damaged: {e}", path.display())))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{self, Config, ProfileEntry};
+
+    fn credentials() -> Credentials {
+        Credentials {
+            client_id: "app-key".to_string(),
+            client_secret: None,
+            refresh_token: "refresh".to_string(),
+            access_token: Some("access".to_string()),
+            expires_at: Some(1_900_000_000),
+        }
+    }
+
+    /// A profile whose store is `name` in a fresh temporary directory.
+    fn temporary_profile(name: &str) -> Profile {
+        let dir = std::env::temp_dir().join(format!("arino-credentials-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let entry = ProfileEntry {
+            credentials: Some(dir.join("credentials.enc").to_string_lossy().into_owned()),
+            key: Some(dir.join("key.json").to_string_lossy().into_owned()),
+            ..ProfileEntry::default()
+        };
+        let config = Config { default_profile: None, profiles: [("test".to_string(), entry)].into() };
+
+        config::select(&config, Some("test"), None).unwrap()
+    }
+
+    #[test]
+    fn opens_what_it_sealed() {
+        let salt = [7u8; SALT_LENGTH];
+        let key = derive_key("correct horse", &salt).unwrap();
+
+        let envelope = seal(&credentials(), &salt, &key).unwrap();
+        let opened = open(&envelope, &key, Path::new("credentials.enc")).unwrap();
+
+        assert_eq!(envelope.salt, BASE64.encode(salt));
+        assert_eq!(opened.client_id, "app-key");
+        assert_eq!(opened.refresh_token, "refresh");
+        assert_eq!(opened.access_token.as_deref(), Some("access"));
+        assert_eq!(opened.expires_at, Some(1_900_000_000));
+    }
+
+    #[test]
+    fn rejects_a_wrong_passphrase() {
+        let salt = [7u8; SALT_LENGTH];
+        let envelope = seal(&credentials(), &salt, &derive_key("correct horse", &salt).unwrap()).unwrap();
+
+        match open(&envelope, &derive_key("battery staple", &salt).unwrap(), Path::new("credentials.enc")) {
+            Err(Error::Auth(message)) => assert_eq!(message, "Wrong passphrase for credentials.enc"),
+            other => panic!("expected an auth error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn reports_a_damaged_store() {
+        let salt = [7u8; SALT_LENGTH];
+        let key = derive_key("correct horse", &salt).unwrap();
+        let path = Path::new("credentials.enc");
+
+        let mut short_nonce = seal(&credentials(), &salt, &key).unwrap();
+        short_nonce.nonce = BASE64.encode([0u8; 12]);
+        assert!(matches!(open(&short_nonce, &key, path), Err(Error::Auth(message)) if message.contains("cannot be read")));
+
+        let mut not_base64 = seal(&credentials(), &salt, &key).unwrap();
+        not_base64.ciphertext = "not base64!".to_string();
+        assert!(matches!(open(&not_base64, &key, path), Err(Error::Auth(message)) if message.contains("is damaged")));
+
+        let mut changed = seal(&credentials(), &salt, &key).unwrap();
+        let mut ciphertext = BASE64.decode(&changed.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        changed.ciphertext = BASE64.encode(ciphertext);
+        assert!(open(&changed, &key, path).is_err());
+
+        let mut newer = seal(&credentials(), &salt, &key).unwrap();
+        newer.version = STORE_VERSION + 1;
+        assert!(matches!(open(&newer, &key, path), Err(Error::Auth(message)) if message.contains("newer version")));
+    }
+
+    #[test]
+    fn load_reports_a_damaged_nonce_without_asking_for_the_passphrase() {
+        let profile = temporary_profile("damaged");
+        let salt = [7u8; SALT_LENGTH];
+        let mut envelope = seal(&credentials(), &salt, &derive_key("correct horse", &salt).unwrap()).unwrap();
+        envelope.nonce = BASE64.encode([0u8; 3]);
+        write_private(&profile.credentials, serde_json::to_string(&envelope).unwrap().as_bytes()).unwrap();
+
+        assert!(matches!(load(&profile), Err(Error::Auth(message)) if message.contains("cannot be read")));
+    }
+
+    #[test]
+    fn read_leaves_a_legacy_key_in_place() {
+        let profile = temporary_profile("legacy");
+        write_private(&profile.key, br#"{"client_id": "app-key", "client_secret": "secret", "refresh_token": "refresh"}"#).unwrap();
+
+        let credentials = read(&profile).unwrap();
+
+        assert_eq!(credentials.client_secret.as_deref(), Some("secret"));
+        assert!(profile.key.exists());
+        assert!(!profile.credentials.exists());
+    }
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, IsoWeek, Datelike};
+use serde::Serialize;
+
+use crate::cli_operations::{output::print_records, parser::Arguments, user_input::prompt};
+use crate::config::{self, Retention};
+use crate::error::{Error, Result};
+
+use super::cloud::{self, fetch_before_change, fetch_to, request_sync, Database, BACKUP_ID_FORMAT};
+use super::{diff, get_connection, snapshots};
+
+/// A backup in Dropbox.
+struct Backup {
+    id: String,
+    path: String,
+    created: DateTime<Local>,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct BackupRow {
+    id: String,
+    created: String,
+    size: u64,
+    /// Why the retention keeps the backup: last, daily or weekly.
+    kept_as: String,
+}
+
+/// `backup database` uploads a new backup, then deletes the backups that the profile's retention no longer keeps.
+pub async fn create() -> Result<()> {
+    cloud::backup().await?;
+
+    let profile = config::active_profile();
+    let backups = remote_backups().await?;
+    let mut removed = 0;
+    for (backup, kept_as) in backups.iter().zip(kept_as(&backups, profile.retention)) {
+        if kept_as.is_none() {
+            cloud::delete(&backup.path).await?;
+            removed += 1;
+        }
+    }
+    if removed > 0 {
+        println!(
+            "Removed {removed} old backups, keeping the last {}, {} daily and {} weekly backups",
+            profile.retention.last, profile.retention.daily, profile.retention.weekly
+        );
+    }
+
+    Ok(())
+}
+
+/// `backup list` shows the backups from newest to oldest.
+pub async fn list() -> Result<()> {
+    let profile = config::active_profile();
+    let backups = remote_backups().await?;
+    if backups.is_empty() {
+        println!("No backups in {} yet, make one with `backup database`", profile.backup_dir());
+        return Ok(());
+    }
+
+    let rows: Vec<BackupRow> = backups
+        .iter()
+        .zip(kept_as(&backups, profile.retention))
+        .map(|(backup, kept_as)| BackupRow {
+            id: backup.id.clone(),
+            created: backup.created.format("%Y-%m-%d %H:%M:%S").to_string(),
+            size: backup.size,
+            kept_as: kept_as.unwrap_or("expired").to_string(),
+        })
+        .collect();
+    print_records(&rows, &["Id", "Created", "Size", "Kept as"], |row| {
+        vec![row.id.clone(), row.created.clone(), format_size(row.size), row.kept_as.clone()]
+    });
+
+    Ok(())
+}
+
+/// `restore backup <id>` replaces the database with a backup, `latest` being the newest one,
+/// after showing what the backup changes and asking for confirmation. The restored database is synced.
+pub async fn restore(args: &mut Arguments) -> Result<()> {
+    let backup = find(&args.next_raw_or_prompt("Backup id (see `backup list`) or latest")).await?;
+    let backup = &backup;
+
+    // Compared with the latest database, which is what the restore replaces; changes that are not synced yet are never fetched over
+    let profile = config::active_profile();
+    fetch_before_change("restore a backup").await?;
+    let restore_path = restore_path(&profile.db);
+    fetch_to(Database::Backup(backup.id.clone()), &restore_path).await?;
+
+    match confirm_restore(backup, &restore_path) {
+        Ok(true) => {},
+        Ok(false) => {
+            fs::remove_file(&restore_path)?;
+            println!("Restore canceled");
+            return Ok(());
+        },
+        Err(e) => {
+            fs::remove_file(&restore_path)?;
+            return Err(e);
+        },
+    }
+
+    snapshots::take("restore backup")?;
+    fs::rename(&restore_path, &profile.db)?;
+    println!("Backup {} restored", backup.id);
+
+    request_sync().await
+}
+
+/// The id of a backup, resolving `latest` to the newest one, for `diff backup latest`.
+pub async fn id(backup_id: &str) -> Result<String> {
+    Ok(find(backup_id).await?.id)
+}
+
+async fn find(backup_id: &str) -> Result<Backup> {
+    let backups = remote_backups().await?;
+    let backup = match backup_id {
+        "latest" => backups.into_iter().next(),
+        backup_id => backups.into_iter().find(|backup| backup.id == backup_id),
+    };
+
+    backup.ok_or_else(|| Error::NotFound(format!("No backup \"{backup_id}\", `backup list` shows the backups")))
+}
+
+fn confirm_restore(backup: &Backup, restore_path: &Path) -> Result<bool> {
+    // A backup made by an older version is compared as upgraded, which the database is once restored
+    let changes = diff::between(&get_connection()?, &diff::load(restore_path)?)?;
+
+    println!("Restoring backup {} from {} changes the database:", backup.id, backup.created.format("%Y-%m-%d %H:%M"));
+    diff::print(&changes);
+
+    println!("Replace the database with this backup?");
+    Ok(prompt("[Y/N]") == "y")
+}
+
+/// The backups of the active profile, newest first. Files in the backup folder that are not named by `backup database` are left out.
+async fn remote_backups() -> Result<Vec<Backup>> {
+    let profile = config::active_profile();
+    let mut backups: Vec<Backup> = cloud::list_folder(&profile.backup_dir())
+        .await?
+        .into_iter()
+        .filter_map(|file| {
+            let id = file.name.strip_suffix(".db")?.to_string();
+            let created = NaiveDateTime::parse_from_str(&id, BACKUP_ID_FORMAT).ok()?.and_utc().with_timezone(&Local);
+            Some(Backup { id, path: file.path_display, created, size: file.size })
+        })
+        .collect();
+    backups.sort_by(|a, b| b.id.cmp(&a.id));
+
+    Ok(backups)
+}
+
+/// Why each backup is kept, in the order of `backups` (newest first): the `retention.last` newest backups and
+/// the newest backup of each of the last `retention.daily` days and `retention.weekly` weeks that have one.
+/// The newest backup is always kept.
+/// `None` marks a backup that is no longer kept.
+fn kept_as(backups: &[Backup], retention: Retention) -> Vec<Option<&'static str>> {
+    let mut days: Vec<NaiveDate> = Vec::new();
+    let mut weeks: Vec<IsoWeek> = Vec::new();
+
+    backups
+        .iter()
+        .enumerate()
+        .map(|(index, backup)| {
+            let mut kept_as = None;
+            let week = backup.created.iso_week();
+            if !weeks.contains(&week) && weeks.len() < retention.weekly {
+                weeks.push(week);
+                kept_as = Some("weekly");
+            }
+            let day = backup.created.date_naive();
+            if !days.contains(&day) && days.len() < retention.daily {
+                days.push(day);
+                kept_as = Some("daily");
+            }
+            if index < retention.last.max(1) {
+                kept_as = Some("last");
+            }
+            kept_as
+        })
+        .collect()
+}
+
+/// The downloaded backup is kept next to the database until it replaces it.
+fn restore_path(db: &Path) -> PathBuf {
+    let mut file_name = db.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".restore");
+    db.with_file_name(file_name)
+}
+
+fn format_size(size: u64) -> String {
+    match size {
+        size if size < 1024 => format!("{size} B"),
+        size if size < 1024 * 1024 => format!("{:.1} KB", size as f64 / 1024.0),
+        size => format!("{:.1} MB", size as f64 / (1024.0 * 1024.0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    /// Backups made at noon on the given days, newest first.
+    fn backups(days: &[(i32, u32, u32)]) -> Vec<Backup> {
+        days.iter()
+            .map(|&(year, month, day)| {
+                let created = Local.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap();
+                Backup { id: created.format(BACKUP_ID_FORMAT).to_string(), path: String::new(), created, size: 0 }
+            })
+            .collect()
+    }
+
+    fn retention(last: usize, daily: usize, weekly: usize) -> Retention {
+        Retention { last, daily, weekly }
+    }
+
+    #[test]
+    fn keeps_the_last_backups() {
+        let backups = backups(&[(2026, 10, 19), (2026, 10, 19), (2026, 10, 19), (2026, 10, 19), (2026, 10, 19)]);
+
+        assert_eq!(kept_as(&backups, retention(3, 0, 0)), [Some("last"), Some("last"), Some("last"), None, None]);
+    }
+
+    #[test]
+    fn keeps_the_newest_backup_of_each_day() {
+        let backups = backups(&[(2026, 10, 19), (2026, 10, 19), (2026, 10, 18), (2026, 10, 18), (2026, 10, 17), (2026, 10, 16)]);
+
+        assert_eq!(kept_as(&backups, retention(1, 3, 0)), [Some("last"), None, Some("daily"), None, Some("daily"), None]);
+    }
+
+    #[test]
+    fn keeps_the_newest_backup_of_each_week() {
+        // Monday the 19th starts a new ISO week, so Sunday the 18th is the newest backup of the week before
+        let backups = backups(&[(2026, 10, 19), (2026, 10, 18), (2026, 10, 12), (2026, 10, 5), (2026, 9, 28)]);
+
+        assert_eq!(kept_as(&backups, retention(1, 1, 3)), [Some("last"), Some("weekly"), None, Some("weekly"), None]);
+    }
+
+    #[test]
+    fn always_keeps_the_newest_backup() {
+        let backups = backups(&[(2026, 10, 19), (2026, 10, 1)]);
+
+        assert_eq!(kept_as(&backups, retention(0, 0, 0)), [Some("last"), None]);
+    }
+}
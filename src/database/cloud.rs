@@ -1,144 +1,407 @@
 use reqwest::Client;
-use std::fs;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::Read;
-use std::io;
 use std::io::Write;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
-use serde::Deserialize;
-
-use crate::helper::flush;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use chrono::Utc;
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::config::{self, Profile};
+use crate::credentials::{self, Credentials};
+use crate::database::{encryption, first_start, snapshots};
+use crate::error::{self, Error, Result};
+
+/// How long a reachability check is reused, and how long it waits for Dropbox.
+const REACHABILITY_TTL: Duration = Duration::from_secs(30);
+const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(3);
+/// How long batched uploads wait for further changes.
+const SYNC_DELAY: Duration = Duration::from_secs(10);
+/// Backups are named after the time they were made in UTC, such as `20261019-101500`.
+pub const BACKUP_ID_FORMAT: &str = "%Y%m%d-%H%M%S";
 
 pub enum Database {
     Main,
-    Backup
+    /// A backup by its id.
+    Backup(String),
+}
+
+/// A file in a Dropbox folder.
+#[derive(Deserialize, Debug)]
+pub struct RemoteFile {
+    pub name: String,
+    pub path_display: String,
+    pub size: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListFolderResponse {
+    entries: Vec<ListFolderEntry>,
+    cursor: String,
+    has_more: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = ".tag", rename_all = "lowercase")]
+enum ListFolderEntry {
+    File(RemoteFile),
+    /// Folders and deleted files
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize, Debug)]
+struct FileMetadata {
+    rev: String,
 }
 
+/// What the local database is based on, kept next to it.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct SyncState {
+    /// Revision of the Dropbox database the local one was last fetched from or synced to.
+    rev: Option<String>,
+    /// The local database has changes that are not synced yet.
+    #[serde(default)]
+    pending: bool,
+}
+
+/// Serializes fetches and syncs, so that a batched sync does not run in the middle of a fetch.
+static SYNC_LOCK: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+static SYNC_REQUESTS: Mutex<Option<mpsc::UnboundedSender<()>>> = Mutex::new(None);
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+/// Set by the terminal UI, whose screen the sync messages would break.
+static SYNC_AT_EXIT: AtomicBool = AtomicBool::new(false);
+/// When Dropbox was last checked and whether it could be reached.
+static REACHABILITY: Mutex<Option<(Instant, bool)>> = Mutex::new(None);
+
 #[derive(Deserialize, Debug)]
 struct TokenResponse {
     access_token: String,
+    /// Lifetime of the token in seconds.
+    expires_in: Option<u64>,
 }
 
-#[derive(Deserialize)]
-struct Creditials {
-    client_id: String,
-    client_secret: String,
-    refresh_token: String,
+
+/// Uploads the database and remembers the new revision. The upload only replaces the revision the local database
+/// was last fetched from or synced to, so that it fails with a conflict when another device changed the database in Dropbox since.
+pub async fn sync() -> Result<()> {
+    sync_replacing(false).await
 }
 
+/// Same as `sync`, but the upload replaces the database in Dropbox even when another device changed it,
+/// for `sync preview` once the user has seen what it replaces.
+pub async fn sync_replacing_remote() -> Result<()> {
+    sync_replacing(true).await
+}
 
-pub async fn sync() -> Result<(), Box<dyn std::error::Error>> {
-    // Check if the access token is valid, and refresh it if necessary
-    if !check_access_token_validity().await? {
-        request_access_token().await?;
-    }
+async fn sync_replacing(replace_remote: bool) -> Result<()> {
+    let _sync_guard = SYNC_LOCK.lock().await;
 
-    // Retrieve the access token
-    let access_token = match retrieve_access_token() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    };
+    // The stored access token, refreshed when it is about to expire
+    let access_token = access_token().await?;
 
-    let file_path = "database.db";
-    let destination_path = "/database.db";
+    let profile = config::active_profile();
+    let file_path = &profile.db;
+    let destination_path = &profile.remote;
 
     // Read the file content
     let mut file = File::open(file_path)?;
     let mut file_content = Vec::new();
     file.read_to_end(&mut file_content)?;
+    let file_content = upload_content(&profile, file_content).await?;
+
+    // Dropbox rejects an update of a revision that is no longer the latest, and an add of a file that already exists
+    let mode = match (replace_remote, sync_state().rev) {
+        (true, _) => serde_json::json!("overwrite"),
+        (false, Some(rev)) => serde_json::json!({ ".tag": "update", "update": rev }),
+        (false, None) => serde_json::json!("add"),
+    };
+    let upload_arg = serde_json::json!({ "path": destination_path, "mode": mode, "autorename": false, "mute": false, "strict_conflict": true });
 
     // Set up the request client
     let client = Client::new();
 
-    // Send the file to Dropbox with overwrite mode
+    // Send the file to Dropbox
     let response = client
-        .post("https://content.dropboxapi.com/2/files/upload")
+        .post(format!("{}/2/files/upload", profile.endpoints.content))
         .header("Authorization", format!("Bearer {}", access_token))
-        .header("Dropbox-API-Arg", format!(r#"{{"path": "{}","mode": "overwrite","autorename": false,"mute": false}}"#, destination_path))
+        .header("Dropbox-API-Arg", upload_arg.to_string())
         .header("Content-Type", "application/octet-stream")
         .body(file_content)
         .send()
         .await?;
 
     // Check if the upload was successful
-    if response.status().is_success() {
-        println!("Database synced successfully");
-    } else {
-        let error_message = response.text().await?;
-        eprintln!("Failed to sync database: {}", error_message);
+    if response.status() == reqwest::StatusCode::CONFLICT {
+        let error_message = response.text().await.unwrap_or_default();
+        if error_message.contains("conflict") {
+            return Err(Error::Conflict("The database in Dropbox was changed by another device, the local changes were not synced".to_string()));
+        }
+        return Err(Error::Network(format!("Failed to sync database: {error_message}")));
+    }
+    if !response.status().is_success() {
+        return Err(response_error("Failed to sync database", response).await);
     }
+    let metadata: FileMetadata = response.json().await?;
+    save_sync_state(&SyncState { rev: Some(metadata.rev), pending: false })?;
+    println!("Database synced successfully");
 
     Ok(())
 }
 
-pub async fn backup() -> Result<(), Box<dyn std::error::Error>> {
-    // Check if the access token is valid, and refresh it if necessary
-    if !check_access_token_validity().await? {
-        request_access_token().await?;
+/// Uploads the database after a change. Once the REPL or the API server has called `start_sync_batching`,
+/// the upload waits until no change has been made for a few seconds, so that a burst of changes is synced once;
+/// after `sync_at_exit` the change is only marked, and synced when the terminal UI exits.
+pub async fn request_sync() -> Result<()> {
+    mark_changed()?;
+    if SYNC_AT_EXIT.load(Ordering::Relaxed) {
+        return Ok(());
     }
 
-    let access_token = match retrieve_access_token() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
+    let sync_requests = SYNC_REQUESTS.lock().expect("sync request lock poisoned").clone();
+    match sync_requests {
+        Some(sync_requests) => {
+            unlock_upload_key().await?;
+            let _ = sync_requests.send(());
+            Ok(())
         },
-    };
-    
+        None if is_offline() => {
+            println!("Working offline, the change waits for a run without --offline to sync it");
+            Ok(())
+        },
+        None => sync().await,
+    }
+}
+
+/// Remembers that the local database has changes that are not in Dropbox yet,
+/// so that they are neither replaced by the next fetch nor forgotten when Arino exits before syncing them.
+pub fn mark_changed() -> Result<()> {
+    let mut sync_state = sync_state();
+    sync_state.pending = true;
+    save_sync_state(&sync_state)
+}
+
+/// Makes `request_sync` batch uploads, and syncs changes left over from an earlier run.
+pub async fn start_sync_batching() {
+    let (sync_requests, sync_receiver) = mpsc::unbounded_channel();
+    if sync_state().pending {
+        match unlock_upload_key().await {
+            Ok(()) => {
+                let _ = sync_requests.send(());
+            },
+            Err(e) => error::report(&e),
+        }
+    }
+    *SYNC_REQUESTS.lock().expect("sync request lock poisoned") = Some(sync_requests);
+    tokio::spawn(sync_in_batches(sync_receiver));
+}
+
+/// Makes `request_sync` leave the upload to the end of the run.
+pub fn sync_at_exit() {
+    SYNC_AT_EXIT.store(true, Ordering::Relaxed);
+}
+
+/// Batched uploads cannot ask for the passphrase of an encrypted database in the middle of the next command,
+/// so it is asked before they are queued.
+async fn unlock_upload_key() -> Result<()> {
+    if config::active_profile().encrypt && !is_offline() {
+        unlock_remote_key().await?;
+        encryption::unlock()?;
+    }
+
+    Ok(())
+}
+
+/// Before the first encrypted upload of a run, an encrypted database in Dropbox is decrypted, which checks the passphrase:
+/// a new one is only asked (twice) when Dropbox has no encrypted database yet, so that a typo cannot encrypt it
+/// under a key the other devices do not have.
+async fn unlock_remote_key() -> Result<()> {
+    if encryption::is_unlocked() || remote_rev(&config::active_profile().remote).await?.is_none() {
+        return Ok(());
+    }
+    download(Database::Main).await?;
+
+    Ok(())
+}
+
+async fn sync_in_batches(mut sync_receiver: mpsc::UnboundedReceiver<()>) {
+    while sync_receiver.recv().await.is_some() {
+        // Every further change restarts the wait
+        while let Ok(Some(_)) = tokio::time::timeout(SYNC_DELAY, sync_receiver.recv()).await {}
+
+        if !sync_state().pending || !has_internet_access().await {
+            continue;
+        }
+        if let Err(e) = sync().await {
+            error::report(&e);
+        }
+    }
+}
+
+/// Syncs the changes that are still waiting for an upload, before Arino exits.
+pub async fn finish_sync() {
+    if !sync_state().pending {
+        return;
+    }
+    if is_offline() {
+        println!("Working offline, the changes wait for a run without --offline to sync them");
+        return;
+    }
+    println!("Syncing the last changes...");
+    if let Err(e) = sync().await {
+        error::report(&e);
+        if !matches!(e, Error::Conflict(_)) {
+            eprintln!("The changes stay in the local database until a later sync");
+        }
+    }
+}
+
+/// Uploads the database as a new backup named after the current time, and returns the backup's id.
+pub async fn backup() -> Result<String> {
+    // The stored access token, refreshed when it is about to expire
+    let access_token = access_token().await?;
+
     // The file you want to upload
-    let file_path = "database.db";
-    let destination_path = "/database_backup.db"; // Where to upload in Dropbox
+    let profile = config::active_profile();
+    let file_path = &profile.db;
 
     // Read the file content
     let mut file = File::open(file_path)?;
     let mut file_content = Vec::new();
     file.read_to_end(&mut file_content)?;
+    let file_content = upload_content(&profile, file_content).await?;
 
     // Set up the request client
     let client = Client::new();
 
-    // Send the file to Dropbox
-    let response = client
-        .post("https://content.dropboxapi.com/2/files/upload")
-        .header("Authorization", format!("Bearer {}", access_token))
-        .header("Dropbox-API-Arg", format!(r#"{{"path": "{}","mode": "add","autorename": true,"mute": false,"strict_conflict": false}}"#, destination_path))
-        .header("Content-Type", "application/octet-stream")
-        .body(file_content)
-        .send()
-        .await?;
+    // Send the file to Dropbox, never replacing an existing backup
+    let mut retried = false;
+    let (backup_id, response) = loop {
+        let backup_id = Utc::now().format(BACKUP_ID_FORMAT).to_string();
+        let upload_arg = serde_json::json!({ "path": backup_path(&profile, &backup_id), "mode": "add", "autorename": false, "mute": false, "strict_conflict": false });
+        let response = client
+            .post(format!("{}/2/files/upload", profile.endpoints.content))
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Dropbox-API-Arg", upload_arg.to_string())
+            .header("Content-Type", "application/octet-stream")
+            .body(file_content.clone())
+            .send()
+            .await?;
+
+        // A backup made in the same second has the same id, so the new one waits for the next second
+        if response.status() == reqwest::StatusCode::CONFLICT && !retried {
+            retried = true;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+        break (backup_id, response);
+    };
 
     // Check if the upload was successful
-    if response.status().is_success() {
-        println!("Database backed up successfully");
-    } else {
-        let error_message = response.text().await?;
-        println!("Database backup failed: {}", error_message);
+    if !response.status().is_success() {
+        return Err(response_error("Database backup failed", response).await);
     }
+    println!("Database backed up as {backup_id}");
 
-    Ok(())
+    Ok(backup_id)
 }
 
 
-pub async fn fetch(source: Database) -> Result<(), Box<dyn std::error::Error>> {
-    // Your Dropbox access token
-    if !check_access_token_validity().await? {
-        request_access_token().await?;
+/// Downloads the database or one of its backups over the profile's database.
+/// The database is only downloaded when its revision in Dropbox differs from the one fetched or synced last,
+/// and the replaced database is snapshotted first when the download differs from it.
+/// Changes that are not synced yet are never replaced: they are kept while the database in Dropbox is unchanged,
+/// and the fetch fails with a conflict otherwise.
+pub async fn fetch(source: Database) -> Result<()> {
+    fetch_replacing(source, false, None).await
+}
+
+/// Same as `fetch`, but the changes that are not synced yet are replaced,
+/// for `fetch preview` once the user has seen them.
+pub async fn fetch_replacing_changes() -> Result<()> {
+    fetch_replacing(Database::Main, true, None).await
+}
+
+/// Writes the download over the database file, or through `conn` when one is given.
+async fn fetch_replacing(source: Database, replace_changes: bool, conn: Option<&mut Connection>) -> Result<()> {
+    let _sync_guard = SYNC_LOCK.lock().await;
+    let profile = config::active_profile();
+    let sync_state = sync_state();
+
+    if matches!(source, Database::Main) && sync_state.pending && !replace_changes && !first_start()? {
+        if remote_rev(&profile.remote).await? == sync_state.rev {
+            println!("Database is up to date, the changes that are not synced yet are kept");
+            return Ok(());
+        }
+        return Err(Error::Conflict("The database in Dropbox was changed by another device, fetching it would replace the changes that are not synced yet".to_string()));
     }
-    let access_token = match retrieve_access_token() {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("{e}");
+
+    if let (Database::Main, Some(rev)) = (&source, &sync_state.rev) {
+        let replaces_changes = replace_changes && sync_state.pending;
+        if !first_start()? && !replaces_changes && remote_rev(&profile.remote).await?.as_ref() == Some(rev) {
+            println!("Database is up to date");
             return Ok(());
+        }
+    }
+
+    let is_main = matches!(source, Database::Main);
+    let (file_content, rev) = download(source).await?;
+    if fs::read(&profile.db).map(|local_content| local_content != file_content).unwrap_or(false) {
+        snapshots::take("fetch")?;
+        if sync_state.pending {
+            println!("Changes that were not synced yet are replaced by the database in Dropbox, `snapshots` keeps them");
+        }
+    }
+    match conn {
+        Some(conn) => restore_into(conn, &profile, &file_content)?,
+        None => {
+            let mut file = File::create(&profile.db)?;
+            file.write_all(&file_content)?;
         },
-    };
+    }
+    // A backup is a change to the database in Dropbox, which the next sync uploads
+    save_sync_state(&SyncState { rev: if is_main { rev } else { sync_state.rev }, pending: !is_main })?;
+    println!("Database fetched successfully");
+
+    Ok(())
+}
+
+/// Copies the downloaded database into the open connection with SQLite's online backup API, as a snapshot is restored,
+/// so that the connection reads the fetched database instead of a file replaced under it.
+fn restore_into(conn: &mut Connection, profile: &Profile, file_content: &[u8]) -> Result<()> {
+    let download_path = profile.db.with_extension("download.db");
+    fs::write(&download_path, file_content)?;
+    let restored = conn.restore(DatabaseName::Main, &download_path, None::<fn(Progress)>);
+    let _ = fs::remove_file(&download_path);
+    restored?;
+
+    Ok(())
+}
+
+/// Downloads the database or one of its backups into `destination`.
+pub async fn fetch_to(source: Database, destination: &Path) -> Result<()> {
+    let (file_content, _) = download(source).await?;
+    let mut file = File::create(destination)?;
+    file.write_all(&file_content)?;
+
+    Ok(())
+}
 
+/// The file and its revision.
+async fn download(source: Database) -> Result<(Vec<u8>, Option<String>)> {
+    // The stored access token, refreshed when it is about to expire
+    let access_token = access_token().await?;
+
+    let profile = config::active_profile();
     let dropbox_path = match source {
-        Database::Main => "/database.db",
-        Database::Backup => "/database_backup.db"
+        Database::Main => profile.remote.clone(),
+        Database::Backup(backup_id) => backup_path(&profile, &backup_id),
     };
 
     // Set up the request client
@@ -146,167 +409,281 @@ pub async fn fetch(source: Database) -> Result<(), Box<dyn std::error::Error>> {
 
     // Send the download request
     let response = client
-        .post("https://content.dropboxapi.com/2/files/download")
+        .post(format!("{}/2/files/download", profile.endpoints.content))
         .header("Authorization", format!("Bearer {}", access_token))
-        .header("Dropbox-API-Arg", format!(r#"{{"path": "{}"}}"#, dropbox_path))
+        .header("Dropbox-API-Arg", serde_json::json!({ "path": dropbox_path }).to_string())
         .send()
         .await?;
 
     // Check if the download was successful
-    if response.status().is_success() {
-        let file_content = response.bytes().await?;
-        let mut file = File::create("database.db")?;
-        file.write_all(&file_content)?;
-        println!("Database fetched successfully");
-    } else {
-        let error_message = response.text().await?;
-        println!("Failed to fetch the database: {}", error_message);
+    if !response.status().is_success() {
+        return Err(response_error("Failed to fetch the database", response).await);
     }
+    // The metadata of a download comes in a header
+    let rev = response
+        .headers()
+        .get("Dropbox-API-Result")
+        .and_then(|value| serde_json::from_slice::<FileMetadata>(value.as_bytes()).ok())
+        .map(|metadata| metadata.rev);
+
+    let file_content = response.bytes().await?.to_vec();
+    // Any encrypted upload is decrypted, even once the profile no longer encrypts
+    let file_content = if encryption::is_encrypted(&file_content) { encryption::decrypt(&file_content)? } else { file_content };
+
+    Ok((file_content, rev))
+}
 
-    Ok(())
+/// The database as uploaded: encrypted when the profile asks for it, so that Dropbox never sees its plaintext.
+async fn upload_content(profile: &Profile, file_content: Vec<u8>) -> Result<Vec<u8>> {
+    if profile.encrypt {
+        unlock_remote_key().await?;
+        encryption::encrypt(&file_content)
+    } else {
+        Ok(file_content)
+    }
 }
 
-pub async fn has_internet_access() -> bool {
-    let client = Client::new();
-    let url = "https://www.google.com";
+/// The revision of a file in Dropbox, none when the file does not exist.
+async fn remote_rev(dropbox_path: &str) -> Result<Option<String>> {
+    let access_token = access_token().await?;
+    let profile = config::active_profile();
 
-    match client.get(url).timeout(Duration::from_secs(5)).send().await {
-        Ok(response) => response.status().is_success(),
-        Err(_) => {
-            println!("Error: Internet access is needed");
-            false
-        },
+    let response = Client::new()
+        .post(format!("{}/2/files/get_metadata", profile.endpoints.api))
+        .bearer_auth(&access_token)
+        .json(&serde_json::json!({ "path": dropbox_path }))
+        .send()
+        .await?;
+    if response.status() == reqwest::StatusCode::CONFLICT {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(response_error(&format!("Failed to check {dropbox_path}"), response).await);
     }
+    let metadata: FileMetadata = response.json().await?;
+
+    Ok(Some(metadata.rev))
 }
 
+fn sync_state() -> SyncState {
+    fs::read_to_string(config::active_profile().sync_state_path())
+        .ok()
+        .and_then(|json_string| serde_json::from_str(&json_string).ok())
+        .unwrap_or_default()
+}
 
+fn save_sync_state(sync_state: &SyncState) -> Result<()> {
+    let path = config::active_profile().sync_state_path();
+    let json_string = serde_json::to_string(sync_state).map_err(|e| Error::Validation(e.to_string()))?;
+    fs::write(&path, json_string).map_err(|e| Error::from(e).in_file(&path.to_string_lossy()))
+}
 
-async fn request_access_token() -> Result<(), Box<dyn std::error::Error>> {
-    let creditials = match get_creditials() {
-        Ok(c) => c,
-        Err(e) => {
-            eprint!("Creditial not found: {e}");
-            return Ok(())
-        },
-    };
+/// The files in a Dropbox folder, none when the folder does not exist yet.
+pub async fn list_folder(folder: &str) -> Result<Vec<RemoteFile>> {
+    let access_token = access_token().await?;
+    let profile = config::active_profile();
+    let client = Client::new();
 
-    let client_id = creditials.client_id;
-    let client_secret = creditials.client_secret;
-    let refresh_token = creditials.refresh_token;
-    
-    // Dropbox token endpoint
-    let token_url = "https://api.dropboxapi.com/oauth2/token";
+    let mut files = Vec::new();
+    let mut response = client
+        .post(format!("{}/2/files/list_folder", profile.endpoints.api))
+        .bearer_auth(&access_token)
+        .json(&serde_json::json!({ "path": folder }))
+        .send()
+        .await?;
+    loop {
+        if response.status() == reqwest::StatusCode::CONFLICT {
+            let error_message = response.text().await.unwrap_or_default();
+            if error_message.contains("not_found") {
+                return Ok(files);
+            }
+            return Err(Error::Network(format!("Failed to list {folder}: {error_message}")));
+        }
+        if !response.status().is_success() {
+            return Err(response_error(&format!("Failed to list {folder}"), response).await);
+        }
 
-    // Prepare form data for the request
-    let mut params = HashMap::new();
-    params.insert("refresh_token", refresh_token);
-    params.insert("grant_type", "refresh_token".to_string());
-    params.insert("client_id", client_id);
-    params.insert("client_secret", client_secret);
+        let page: ListFolderResponse = response.json().await?;
+        files.extend(page.entries.into_iter().filter_map(|entry| match entry {
+            ListFolderEntry::File(file) => Some(file),
+            ListFolderEntry::Other => None,
+        }));
+        if !page.has_more {
+            return Ok(files);
+        }
 
-    // Create an HTTP client
-    let client = Client::new();
+        response = client
+            .post(format!("{}/2/files/list_folder/continue", profile.endpoints.api))
+            .bearer_auth(&access_token)
+            .json(&serde_json::json!({ "cursor": page.cursor }))
+            .send()
+            .await?;
+    }
+}
 
-    // Send the request to Dropbox API
-    let response = client
-        .post(token_url)
-        .form(&params)
+pub async fn delete(dropbox_path: &str) -> Result<()> {
+    let access_token = access_token().await?;
+    let profile = config::active_profile();
+
+    let response = Client::new()
+        .post(format!("{}/2/files/delete_v2", profile.endpoints.api))
+        .bearer_auth(&access_token)
+        .json(&serde_json::json!({ "path": dropbox_path }))
         .send()
         .await?;
+    if !response.status().is_success() {
+        return Err(response_error(&format!("Failed to delete {dropbox_path}"), response).await);
+    }
 
-    // Handle response based on status code
-    let status = response.status();
-    if status.is_success() {
-        let token_response: TokenResponse = response.json().await?;
-        store_access_token(token_response.access_token);
+    Ok(())
+}
+
+fn backup_path(profile: &Profile, backup_id: &str) -> String {
+    format!("{}/{backup_id}.db", profile.backup_dir())
+}
+
+/// Makes every command work on the local database only, for `--offline`.
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Whether the profile's Dropbox API can be reached, always false with `--offline`.
+/// The answer is reused for a while, so that a burst of commands checks once.
+pub async fn has_internet_access() -> bool {
+    if is_offline() {
+        return false;
+    }
+    if let Some(reachable) = recent_reachability() {
+        return reachable;
+    }
+
+    // Any answer, even an error status, shows that the server can be reached
+    let api_url = config::active_profile().endpoints.api;
+    let reachable = Client::new().head(&api_url).timeout(REACHABILITY_TIMEOUT).send().await.is_ok();
+    *REACHABILITY.lock().expect("reachability lock poisoned") = Some((Instant::now(), reachable));
+
+    reachable
+}
+
+/// The last reachability check while it is recent enough to be reused.
+fn recent_reachability() -> Option<bool> {
+    let reachability = REACHABILITY.lock().expect("reachability lock poisoned");
+    reachability
+        .filter(|(checked_at, _)| checked_at.elapsed() < REACHABILITY_TTL)
+        .map(|(_, reachable)| reachable)
+}
+
+/// Fails with a network error naming what needed Dropbox, such as "sync database to cloud".
+pub async fn require_internet_access(action: &str) -> Result<()> {
+    if is_offline() {
+        return Err(Error::Network(format!("Arino was started with --offline, Dropbox is needed to {action}")));
+    }
+    if has_internet_access().await {
+        Ok(())
     } else {
-        // Get the error text from the response
-        let error_text = response.text().await?;
-        eprintln!("Failed to request access token: {}", error_text);
+        let api_url = config::active_profile().endpoints.api;
+        Err(Error::Network(format!("Dropbox ({api_url}) cannot be reached to {action}")))
     }
-    Ok(())
 }
 
-fn store_access_token(access_token: String) {
-    let json_string = match serde_json::to_string(&access_token) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error storing parsing access token to json: {e}");
-            return;
-        }
-    };
+/// Fetches the latest database before a change. With `--offline` the change is made to the local database instead
+/// and synced by a later run, unless the database in Dropbox was changed meanwhile, which that run reports as a conflict.
+pub async fn fetch_before_change(action: &str) -> Result<()> {
+    fetch_before_change_through(None, action).await
+}
 
-    match std::fs::write("access_token.json", json_string) {
-        Ok(_) => {},
-        Err(e) => eprintln!("Error storing access token: {e}"),
+/// Same as `fetch_before_change` for a connection that stays open, such as the server's:
+/// the fetched database is written through it, so the caller holds it for the whole change.
+pub async fn fetch_before_change_into(conn: &mut Connection, action: &str) -> Result<()> {
+    fetch_before_change_through(Some(conn), action).await
+}
+
+async fn fetch_before_change_through(conn: Option<&mut Connection>, action: &str) -> Result<()> {
+    if is_offline() {
+        return Ok(());
     }
+    require_internet_access(action).await?;
+    fetch_replacing(Database::Main, false, conn).await
 }
 
-fn retrieve_access_token() -> Result<String, Box<dyn std::error::Error>> {
-    let json_string = fs::read_to_string("access_token.json");
-    let json_string = match json_string {
-        Ok(s) => s,
-        Err(_) => {
-            return Err(Box::new(io::Error::new(io::ErrorKind::NotFound, "stored access token not found")));
-        },
+/// Shows whether Dropbox can be reached and whether changes wait for a sync, such as "online" or "offline, unsynced",
+/// for the REPL prompt.
+pub async fn status() -> String {
+    let connection = match (is_offline(), has_internet_access().await) {
+        (true, _) => "offline mode",
+        (false, true) => "online",
+        (false, false) => "offline",
     };
-    let parsed: String = serde_json::from_str(&json_string).unwrap();
+    match sync_state().pending {
+        true => format!("{connection}, unsynced"),
+        false => connection.to_string(),
+    }
+}
+
+/// Turns an unsuccessful Dropbox response into an error, an auth error when the access was refused.
+async fn response_error(context: &str, response: reqwest::Response) -> Error {
+    let status = response.status();
+    let error_message = response.text().await.unwrap_or_default();
 
-    Ok(parsed)
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        Error::Auth(format!("{context}: Dropbox refused the access token ({status}) {error_message}"))
+    } else {
+        Error::Network(format!("{context}: {status} {error_message}"))
+    }
 }
 
-async fn check_access_token_validity() -> Result<bool, Box<dyn std::error::Error>> {
-    let access_token = match retrieve_access_token() {
-        Ok(s) => s,
-        Err(_) => {
-            eprint!("Using new access token...");
-            flush();
-            print!("\r");
-            return Ok(false);
-        }
-    };
 
-    // Dropbox check endpoint (this is a lightweight request)
-    let url = "https://api.dropboxapi.com/2/users/get_current_account";
+
+/// Returns the stored access token while it is valid, and otherwise requests a new one with the refresh token.
+/// The expiry is tracked locally, so a valid token needs no request to Dropbox.
+async fn access_token() -> Result<String> {
+    let profile = config::active_profile();
+    let mut credentials = credentials::load(&profile)?;
+    if let Some(access_token) = credentials.valid_access_token() {
+        return Ok(access_token.to_string());
+    }
+
+    let token_response = request_access_token(&credentials).await?;
+    credentials.set_access_token(token_response.access_token.clone(), token_response.expires_in);
+    credentials::save(&profile, &credentials)?;
+
+    Ok(token_response.access_token)
+}
+
+async fn request_access_token(credentials: &Credentials) -> Result<TokenResponse> {
+    // Dropbox token endpoint
+    let token_url = format!("{}/oauth2/token", config::active_profile().endpoints.api);
+
+    // Prepare form data for the request
+    let mut params = HashMap::new();
+    params.insert("refresh_token", credentials.refresh_token.clone());
+    params.insert("grant_type", "refresh_token".to_string());
+    params.insert("client_id", credentials.client_id.clone());
+    if let Some(client_secret) = &credentials.client_secret {
+        params.insert("client_secret", client_secret.clone());
+    }
 
     // Create an HTTP client
     let client = Client::new();
 
-    // Send the request with the access token
+    // Send the request to Dropbox API
     let response = client
-        .post(url)
-        .bearer_auth(access_token)  // Use the access token for authorization
+        .post(token_url)
+        .form(&params)
         .send()
         .await?;
 
-    // Check if the response status is successful (2xx)
-    if response.status().is_success() {
-        eprint!("Using old access token...");
-        flush();
-        print!("\r");
-        Ok(true)  // Token is valid
-    } else if response.status().as_u16() == 401 {
-        eprint!("Using new access token...");
-        flush();
-        print!("\r");
-        Ok(false) // Token is invalid or expired
-    } else {
-        eprintln!("Unexpected error");
-        Err(format!("Unexpected error: {}", response.status()).into())
+    // Handle response based on status code
+    let status = response.status();
+    if !status.is_success() {
+        // Get the error text from the response
+        let error_text = response.text().await?;
+        return Err(Error::Auth(format!("Failed to request access token: {error_text}")));
     }
-}
-
-
-fn get_creditials() -> Result<Creditials, Box<dyn std::error::Error>> {
-    let json_string = fs::read_to_string("key.json");
-    let json_string = match json_string {
-        Ok(s) => s,
-        Err(_) => {
-            return Err(Box::new(io::Error::new(io::ErrorKind::NotFound, "key not found")));
-        },
-    };
-    let parsed: Creditials = serde_json::from_str(&json_string).unwrap();
 
-    Ok(parsed)
+    Ok(response.json().await?)
 }
-
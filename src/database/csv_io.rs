@@ -0,0 +1,545 @@
+use std::collections::{HashMap, HashSet};
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::cli_operations::{cancel_prompt, parser::Arguments, user_input::prompt};
+use crate::error::Result;
+
+use super::cloud::fetch_before_change;
+use super::{commit_change, get, get_connection, insert, validate, Outcome};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvTable {
+    Ingredients,
+    Dishes,
+    Recipes,
+    Prices,
+}
+
+impl CsvTable {
+    pub fn from_name(name: &str) -> Option<CsvTable> {
+        match name {
+            "ingredients" | "ingredient" => Some(CsvTable::Ingredients),
+            "dishes" | "dish" => Some(CsvTable::Dishes),
+            "recipes" | "recipe" => Some(CsvTable::Recipes),
+            "prices" | "price" => Some(CsvTable::Prices),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct IngredientRecord {
+    name: String,
+    category: String,
+    #[serde(default)]
+    lifespan: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DishRecord {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecipeRecord {
+    dish: String,
+    ingredient: String,
+    quantity: String,
+    #[serde(default)]
+    note: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PriceRecord {
+    ingredient: String,
+    price: String,
+}
+
+/// A row that passed validation, with its references still given by name.
+enum PendingRow {
+    Ingredient { name: String, category: String, lifespan: String },
+    Dish { name: String },
+    Recipe { dish: String, ingredient: String, quantity: u32, note: Option<String> },
+    Price { ingredient: String, price: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ProblemKind {
+    Duplicate,
+    UnknownReference,
+    Invalid,
+}
+
+struct Problem {
+    line: u64,
+    kind: ProblemKind,
+    message: String,
+}
+
+#[derive(Default)]
+struct ImportPlan {
+    rows: Vec<PendingRow>,
+    problems: Vec<Problem>,
+}
+
+impl ImportPlan {
+    fn problem(&mut self, line: u64, kind: ProblemKind, message: String) {
+        self.problems.push(Problem { line, kind, message });
+    }
+}
+
+pub async fn import(args: &mut Arguments) -> Result<()> {
+    let table = match table_from_args(args) {
+        Some(table) => table,
+        None => {
+            cancel_prompt();
+            return Ok(());
+        },
+    };
+
+    let path = args.next_raw_or_prompt("CSV file");
+    if path.is_empty() {
+        cancel_prompt();
+        return Ok(());
+    }
+
+    fetch_before_change("import into the database").await?;
+
+    let mut conn = get_connection()?;
+
+    let plan = plan_import(table, &path, &conn).map_err(|e| e.in_file(&path))?;
+
+    print_problems(&plan.problems);
+
+    if plan.rows.is_empty() {
+        println!("Nothing to import");
+        return Ok(());
+    }
+
+    if plan.problems.is_empty() {
+        println!("Import {} rows?", plan.rows.len());
+    } else {
+        println!("Import the {} valid rows and skip the {} rows above?", plan.rows.len(), plan.problems.len());
+    }
+    if prompt("[Y/N]") != "y" {
+        println!("Import aborted");
+        return Ok(());
+    }
+
+    commit_change(&mut conn, &format!("import {path}"), |tx| {
+        for row in &plan.rows {
+            insert_row(row, tx)?;
+        }
+        Ok(Outcome::Commit)
+    }).await?;
+
+    println!("Imported {} rows from {path}", plan.rows.len());
+
+    Ok(())
+}
+
+pub fn export(args: &mut Arguments) -> Result<()> {
+    let table = match table_from_args(args) {
+        Some(table) => table,
+        None => {
+            cancel_prompt();
+            return Ok(());
+        },
+    };
+
+    let path = args.next_raw_or_prompt("CSV file");
+    if path.is_empty() {
+        cancel_prompt();
+        return Ok(());
+    }
+
+    let conn = get_connection()?;
+
+    let count = write_table(table, &path, &conn).map_err(|e| e.in_file(&path))?;
+    println!("Exported {count} rows to {path}");
+
+    Ok(())
+}
+
+fn table_from_args(args: &mut Arguments) -> Option<CsvTable> {
+    loop {
+        let input_table = args.next_or_prompt("Table (ingredients, dishes, recipes, prices)");
+        if input_table.is_empty() {
+            return None;
+        }
+
+        match CsvTable::from_name(&input_table) {
+            Some(table) => return Some(table),
+            None => eprintln!("Invalid table"),
+        }
+    }
+}
+
+fn write_table(table: CsvTable, path: &str, conn: &Connection) -> Result<usize> {
+    let mut writer = csv::Writer::from_path(path)?;
+    let mut count = 0;
+
+    match table {
+        CsvTable::Ingredients => {
+            let mut stmt = conn.prepare(
+                "SELECT ingredients.name, categories.name, ingredients.lifespan FROM ingredients
+                LEFT JOIN categories ON categories.id = ingredients.category_id ORDER BY ingredients.name;",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(IngredientRecord {
+                    name: row.get(0)?,
+                    category: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    lifespan: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                })
+            })?;
+            for row in rows {
+                writer.serialize(row?)?;
+                count += 1;
+            }
+        },
+        CsvTable::Dishes => {
+            let mut stmt = conn.prepare("SELECT name FROM dishes ORDER BY name;")?;
+            let rows = stmt.query_map([], |row| Ok(DishRecord { name: row.get(0)? }))?;
+            for row in rows {
+                writer.serialize(row?)?;
+                count += 1;
+            }
+        },
+        CsvTable::Recipes => {
+            let mut stmt = conn.prepare(
+                "SELECT dishes.name, ingredients.name, recipes.quantity, recipes.note FROM recipes
+                JOIN dishes ON dishes.id = recipes.dish_id
+                JOIN ingredients ON ingredients.id = recipes.ingredient_id
+                ORDER BY dishes.name, ingredients.name;",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(RecipeRecord {
+                    dish: row.get(0)?,
+                    ingredient: row.get(1)?,
+                    quantity: row.get::<_, Option<u32>>(2)?.map(|q| q.to_string()).unwrap_or_default(),
+                    note: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                })
+            })?;
+            for row in rows {
+                writer.serialize(row?)?;
+                count += 1;
+            }
+        },
+        CsvTable::Prices => {
+            let mut stmt = conn.prepare(
+                "SELECT ingredients.name, prices.price FROM prices
+                JOIN ingredients ON ingredients.id = prices.ingredient_id
+                ORDER BY ingredients.name, prices.id;",
+            )?;
+            let rows = stmt.query_map([], |row| {
+                Ok(PriceRecord {
+                    ingredient: row.get(0)?,
+                    price: price_text(row.get(1)?),
+                })
+            })?;
+            for row in rows {
+                writer.serialize(row?)?;
+                count += 1;
+            }
+        },
+    }
+
+    writer.flush()?;
+
+    Ok(count)
+}
+
+/// Older versions stored prices as text such as "$4.50", which are exported as they are so that the import can read them.
+fn price_text(price: Value) -> String {
+    match price {
+        Value::Integer(price) => format!("{price}.00"),
+        Value::Real(price) => format!("{price:.2}"),
+        Value::Text(text) => text.trim().to_string(),
+        Value::Null | Value::Blob(_) => String::new(),
+    }
+}
+
+/// Reads the whole file and checks every row against the database and the rows before it,
+/// so that all problems can be reported before anything is written.
+fn plan_import(table: CsvTable, path: &str, conn: &Connection) -> Result<ImportPlan> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)?;
+    let headers = reader.headers()?.clone();
+
+    let mut plan = ImportPlan::default();
+    // Natural key of each accepted row, mapped to the line it came from
+    let mut seen: HashMap<String, u64> = HashMap::new();
+    let existing_recipe_lines = existing_recipe_lines(conn)?;
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                let line = e.position().map(|p| p.line()).unwrap_or(0);
+                plan.problem(line, ProblemKind::Invalid, e.to_string());
+                continue;
+            },
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or(0);
+
+        match table {
+            CsvTable::Ingredients => {
+                let row: IngredientRecord = match record.deserialize(Some(&headers)) {
+                    Ok(row) => row,
+                    Err(e) => {
+                        plan.problem(line, ProblemKind::Invalid, e.to_string());
+                        continue;
+                    },
+                };
+                let name = row.name.to_lowercase();
+                let category = row.category.to_lowercase();
+
+                if name.is_empty() {
+                    plan.problem(line, ProblemKind::Invalid, "Ingredient name is empty".to_string());
+                } else if let Some(first_line) = seen.get(&name) {
+                    plan.problem(line, ProblemKind::Duplicate, format!("Ingredient \"{name}\" repeats line {first_line}"));
+                } else if let Some(existing) = validate::existing_ingredient(&name, None, conn)? {
+                    plan.problem(line, ProblemKind::Duplicate, format!("Ingredient \"{name}\" is already in the database as \"{existing}\""));
+                } else if let Err(e) = validate::ingredient_name(&name, None, conn) {
+                    plan.problem(line, ProblemKind::Invalid, e.to_string());
+                } else if let Err(e) = get::category_id_by_name(&category, conn) {
+                    plan.problem(line, ProblemKind::UnknownReference, e.to_string());
+                } else if let Err(e) = validate::lifespan(&row.lifespan) {
+                    plan.problem(line, ProblemKind::Invalid, e.to_string());
+                } else {
+                    seen.insert(name.clone(), line);
+                    plan.rows.push(PendingRow::Ingredient { name, category, lifespan: row.lifespan.to_lowercase() });
+                }
+            },
+            CsvTable::Dishes => {
+                let row: DishRecord = match record.deserialize(Some(&headers)) {
+                    Ok(row) => row,
+                    Err(e) => {
+                        plan.problem(line, ProblemKind::Invalid, e.to_string());
+                        continue;
+                    },
+                };
+                let name = row.name.to_lowercase();
+
+                if name.is_empty() {
+                    plan.problem(line, ProblemKind::Invalid, "Dish name is empty".to_string());
+                } else if let Some(first_line) = seen.get(&name) {
+                    plan.problem(line, ProblemKind::Duplicate, format!("Dish \"{name}\" repeats line {first_line}"));
+                } else if let Some(existing) = validate::existing_dish(&name, None, conn)? {
+                    plan.problem(line, ProblemKind::Duplicate, format!("Dish \"{name}\" is already in the database as \"{existing}\""));
+                } else if let Err(e) = validate::dish_name(&name, None, conn) {
+                    plan.problem(line, ProblemKind::Invalid, e.to_string());
+                } else {
+                    seen.insert(name.clone(), line);
+                    plan.rows.push(PendingRow::Dish { name });
+                }
+            },
+            CsvTable::Recipes => {
+                let row: RecipeRecord = match record.deserialize(Some(&headers)) {
+                    Ok(row) => row,
+                    Err(e) => {
+                        plan.problem(line, ProblemKind::Invalid, e.to_string());
+                        continue;
+                    },
+                };
+                let dish = row.dish.to_lowercase();
+                let ingredient = row.ingredient.to_lowercase();
+                let key = format!("{dish}\u{0}{ingredient}");
+
+                let ids = get::dish_id_by_name(&dish, conn)
+                    .and_then(|dish_id| Ok((dish_id, get::ingredient_id_by_name(&ingredient, conn)?)));
+                let (dish_id, ingredient_id) = match ids {
+                    Ok(ids) => ids,
+                    Err(e) => {
+                        plan.problem(line, ProblemKind::UnknownReference, e.to_string());
+                        continue;
+                    },
+                };
+
+                if let Some(first_line) = seen.get(&key) {
+                    plan.problem(line, ProblemKind::Duplicate, format!("\"{ingredient}\" in \"{dish}\" repeats line {first_line}"));
+                } else if existing_recipe_lines.contains(&(dish_id, ingredient_id)) {
+                    plan.problem(line, ProblemKind::Duplicate, format!("\"{ingredient}\" is already in the recipe of \"{dish}\""));
+                } else {
+                    match validate::quantity_text(&row.quantity) {
+                        Ok(quantity) => {
+                            seen.insert(key, line);
+                            let note = Some(row.note).filter(|note| !note.is_empty());
+                            plan.rows.push(PendingRow::Recipe { dish, ingredient, quantity, note });
+                        },
+                        Err(e) => plan.problem(line, ProblemKind::Invalid, e.to_string()),
+                    }
+                }
+            },
+            CsvTable::Prices => {
+                let row: PriceRecord = match record.deserialize(Some(&headers)) {
+                    Ok(row) => row,
+                    Err(e) => {
+                        plan.problem(line, ProblemKind::Invalid, e.to_string());
+                        continue;
+                    },
+                };
+                let ingredient = row.ingredient.to_lowercase();
+
+                if let Err(e) = get::ingredient_id_by_name(&ingredient, conn) {
+                    plan.problem(line, ProblemKind::UnknownReference, e.to_string());
+                    continue;
+                }
+
+                match validate::price_text(&row.price) {
+                    Ok(price) => plan.rows.push(PendingRow::Price { ingredient, price }),
+                    Err(e) => plan.problem(line, ProblemKind::Invalid, e.to_string()),
+                }
+            },
+        }
+    }
+
+    Ok(plan)
+}
+
+fn existing_recipe_lines(conn: &Connection) -> rusqlite::Result<HashSet<(u32, u32)>> {
+    let mut stmt = conn.prepare("SELECT dish_id, ingredient_id FROM recipes;")?;
+    let lines = stmt
+        .query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?)))?
+        .collect::<rusqlite::Result<HashSet<(u32, u32)>>>()?;
+
+    Ok(lines)
+}
+
+fn insert_row(row: &PendingRow, conn: &Connection) -> Result<()> {
+    match row {
+        PendingRow::Ingredient { name, category, lifespan } => insert::ingredient_row(name, category, lifespan, conn),
+        PendingRow::Dish { name } => insert::dish_row(name, conn),
+        PendingRow::Recipe { dish, ingredient, quantity, note } => insert::recipe_row(dish, ingredient, *quantity, note.as_deref(), conn),
+        PendingRow::Price { ingredient, price } => insert::price_row(ingredient, *price, conn),
+    }
+}
+
+fn print_problems(problems: &[Problem]) {
+    let sections = [
+        (ProblemKind::Duplicate, "Duplicates"),
+        (ProblemKind::UnknownReference, "Unknown references"),
+        (ProblemKind::Invalid, "Invalid rows"),
+    ];
+
+    for (kind, title) in sections {
+        let matching: Vec<&Problem> = problems.iter().filter(|problem| problem.kind == kind).collect();
+        if matching.is_empty() {
+            continue;
+        }
+
+        eprintln!("{title}:");
+        matching.iter().for_each(|problem| eprintln!("-- line {}: {}", problem.line, problem.message));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use super::*;
+    use crate::database::schema;
+
+    fn database() -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        schema::migrate(&mut conn).unwrap();
+        conn
+    }
+
+    fn csv_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("arino-csv-{}-{name}.csv", std::process::id()))
+    }
+
+    /// Imports the file like `import` does once the rows are confirmed, returning the problems found.
+    fn import_file(table: CsvTable, path: &str, conn: &Connection) -> Vec<String> {
+        let plan = plan_import(table, path, conn).unwrap();
+        plan.rows.iter().for_each(|row| insert_row(row, conn).unwrap());
+        plan.problems.into_iter().map(|problem| problem.message).collect()
+    }
+
+    fn export_and_import(table: CsvTable, from: &Connection, into: &Connection, name: &str) -> String {
+        let path = csv_path(name);
+        let path = path.to_str().unwrap();
+        write_table(table, path, from).unwrap();
+        let exported = fs::read_to_string(path).unwrap();
+
+        assert_eq!(import_file(table, path, into), Vec::<String>::new());
+        fs::remove_file(path).unwrap();
+        exported
+    }
+
+    #[test]
+    fn an_export_imports_into_an_empty_database() {
+        let from = database();
+        from.execute_batch(
+            "INSERT INTO dishes (name) VALUES ('leek soup');
+            INSERT INTO ingredients (name, category_id, lifespan) VALUES ('leek', 1, '2w'), ('cream', 3, NULL);
+            INSERT INTO recipes (dish_id, ingredient_id, quantity, note) VALUES (1, 1, 300, 'sliced'), (1, 2, 100, NULL);
+            INSERT INTO prices (ingredient_id, price) VALUES (1, 4.5), (2, 7);"
+        ).unwrap();
+        let into = database();
+
+        let mut exported = Vec::new();
+        for (table, name) in [(CsvTable::Ingredients, "ingredients"), (CsvTable::Dishes, "dishes"), (CsvTable::Recipes, "recipes"), (CsvTable::Prices, "prices")] {
+            exported.push(export_and_import(table, &from, &into, name));
+        }
+
+        assert_eq!(exported, vec![
+            "name,category,lifespan\ncream,dairy,\nleek,vegetable,2w\n",
+            "name\nleek soup\n",
+            "dish,ingredient,quantity,note\nleek soup,cream,100,\nleek soup,leek,300,sliced\n",
+            "ingredient,price\ncream,7.00\nleek,4.50\n",
+        ]);
+        for (table, name) in [(CsvTable::Ingredients, "again-ingredients"), (CsvTable::Recipes, "again-recipes"), (CsvTable::Prices, "again-prices")] {
+            let path = csv_path(name);
+            write_table(table, path.to_str().unwrap(), &into).unwrap();
+            assert!(exported.contains(&fs::read_to_string(&path).unwrap()));
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn exports_prices_stored_as_text() {
+        let from = database();
+        from.execute_batch(
+            "INSERT INTO ingredients (name, category_id) VALUES ('leek', 1);
+            INSERT INTO prices (ingredient_id, price) VALUES (1, '$2.50'), (1, ' 3 '), (1, NULL);"
+        ).unwrap();
+        let path = csv_path("text-prices");
+        let path = path.to_str().unwrap();
+
+        write_table(CsvTable::Prices, path, &from).unwrap();
+
+        assert_eq!(fs::read_to_string(path).unwrap(), "ingredient,price\nleek,$2.50\nleek,3.00\nleek,\n");
+        let into = database();
+        into.execute("INSERT INTO ingredients (name, category_id) VALUES ('leek', 1);", []).unwrap();
+        assert_eq!(import_file(CsvTable::Prices, path, &into), vec!["Invalid price \"\", expected a number such as 4.50".to_string()]);
+        let prices: Vec<f64> = into.prepare("SELECT price FROM prices ORDER BY id;").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<Vec<f64>>>().unwrap();
+        assert_eq!(prices, vec![2.5, 3.0]);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reports_duplicates_and_unknown_references() {
+        let conn = database();
+        conn.execute("INSERT INTO ingredients (name, category_id) VALUES ('leek', 1);", []).unwrap();
+        let path = csv_path("problems");
+        fs::write(&path, "name,category,lifespan\nLeek,vegetable,\ncarrot,vegetable,1w\ncarrot,vegetable,\nplum,stone fruit,\n").unwrap();
+
+        let problems = import_file(CsvTable::Ingredients, path.to_str().unwrap(), &conn);
+
+        assert_eq!(problems, vec![
+            "Ingredient \"leek\" is already in the database as \"leek\"".to_string(),
+            "Ingredient \"carrot\" repeats line 3".to_string(),
+            "Unknown category \"stone fruit\"".to_string(),
+        ]);
+        let names: Vec<String> = conn.prepare("SELECT name FROM ingredients ORDER BY id;").unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>().unwrap();
+        assert_eq!(names, vec!["leek", "carrot"]);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,133 +1,203 @@
-use rusqlite::Result;
-
-use crate::{cli_operations::user_input::prompt, database::{cloud::sync, get}};
-
-use super::{cloud::{fetch, has_internet_access, Database}, get_connection};
+use rusqlite::Connection;
+
+use crate::{cli_operations::{parser::Arguments, user_input::prompt}, database::{get, Outcome}, error::{Error, Result}};
+
+/// What happens to the recipes that use an ingredient being deleted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InUsePolicy {
+    /// The ingredient is not deleted.
+    Block,
+    /// The ingredient is removed from the recipes as well.
+    Cascade,
+    /// The recipes use the named ingredient instead.
+    Replace(String),
+}
 
-pub async fn ingredient_from_recipe() -> Result<()> {
-    if !has_internet_access().await {
-        return Ok(());
-    }
-    
-    match fetch(Database::Main).await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
+impl InUsePolicy {
+    /// `block`, `cascade` or `replace`; `replacement` is the ingredient used by `replace`.
+    pub fn from_name(name: &str, replacement: &str) -> Option<InUsePolicy> {
+        match name {
+            "" | "block" => Some(InUsePolicy::Block),
+            "cascade" => Some(InUsePolicy::Cascade),
+            "replace" if !replacement.is_empty() => Some(InUsePolicy::Replace(replacement.to_string())),
+            _ => None,
+        }
     }
+}
 
-    let conn = get_connection();
+pub fn ingredient_from_recipe(args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
 
-    let dish_id = match get::dish_id(&conn) {
+    let dish_id = match get::dish_id(conn, args) {
         Some(id) => id,
-        None => return Ok(()),
+        None => return Ok(Outcome::Rollback),
     };
 
-    let ingredient_id = match get::ingredient_id(&conn) {
+    let ingredient_id = match get::ingredient_id(conn, args) {
         Some(id) => id,
-        None => return Ok(()),
+        None => return Ok(Outcome::Rollback),
     };
 
     println!("Are you sure you want to delete this ingredient from this recipe?");
     if prompt("[Y/N]") != "y" {
         println!("Deletion aborted");
-        return Ok(());
+        return Ok(Outcome::Rollback);
     }
     
     let mut stmt = conn.prepare("DELETE FROM recipes WHERE dish_id = ?1 AND ingredient_id = ?2;")?;
     stmt.execute((&dish_id, &ingredient_id))?;
 
-    match sync().await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
-
-    Ok(())
+    Ok(Outcome::Commit)
 }
 
-pub async fn dish() -> Result<()> {
-    if !has_internet_access().await {
-        return Ok(());
-    }
-    
-    match fetch(Database::Main).await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
-
-    let conn = get_connection();
+pub fn dish(args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
 
-    let dish_id = match get::dish_id(&conn) {
+    let dish_id = match get::dish_id(conn, args) {
         Some(id) => id,
-        None => return Ok(()),
+        None => return Ok(Outcome::Rollback),
     };
 
     println!("Are you sure you want to delete this dish along with its recipe?");
     if prompt("[Y/N]") != "y" {
         println!("Deletion aborted");
-        return Ok(());
+        return Ok(Outcome::Rollback);
     }
 
     let mut delete_recipe_stmt = conn.prepare("DELETE FROM recipes WHERE dish_id = ?1")?;
     delete_recipe_stmt.execute([dish_id])?;
 
+    let mut delete_steps_stmt = conn.prepare("DELETE FROM recipe_steps WHERE dish_id = ?1")?;
+    delete_steps_stmt.execute([dish_id])?;
+
     let mut delete_dish_stmt = conn.prepare("DELETE FROM dishes WHERE id = ?1")?;
     delete_dish_stmt.execute([dish_id])?;
 
-    match sync().await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
+    Ok(Outcome::Commit)
+}
+
+pub fn ingredient(args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
+
+    let ingredient_id = match get::ingredient_id(conn, args) {
+        Some(id) => id,
+        None => return Ok(Outcome::Rollback),
+    };
+
+    let dishes = dishes_using(ingredient_id, conn)?;
+    let policy = if dishes.is_empty() {
+        println!("Are you sure you want to delete this ingredient from the database?");
+        if prompt("[Y/N]") != "y" {
+            println!("Deletion aborted");
+            return Ok(Outcome::Rollback);
+        }
+        InUsePolicy::Block
+    } else {
+        println!("This ingredient is used by: {}", dishes.join(", "));
+        let choice = args.next_or_prompt("Block, cascade (remove it from these recipes) or replace (with another ingredient)");
+        match choice.as_str() {
+            "c" | "cascade" => InUsePolicy::Cascade,
+            "r" | "replace" => {
+                let replacement = match get::ingredient_id(conn, args).and_then(|id| get::ingredient_name(id, conn)) {
+                    Some(name) => name,
+                    None => return Ok(Outcome::Rollback),
+                };
+                InUsePolicy::Replace(replacement)
+            },
+            _ => {
+                println!("Deletion aborted");
+                return Ok(Outcome::Rollback);
+            },
+        }
+    };
+
+    remove_ingredient(ingredient_id, &policy, conn)?;
+
+    Ok(Outcome::Commit)
+}
+
+pub fn recipe_row(dish_name: &str, ingredient_name: &str, conn: &Connection) -> Result<()> {
+    let dish_id = get::dish_id_by_name(dish_name, conn)?;
+    let ingredient_id = get::ingredient_id_by_name(ingredient_name, conn)?;
+
+    let deleted = conn.execute("DELETE FROM recipes WHERE dish_id = ?1 AND ingredient_id = ?2;", (dish_id, ingredient_id))?;
+    if deleted == 0 {
+        return Err(Error::NotFound(format!("\"{ingredient_name}\" is not in the recipe of \"{dish_name}\"")));
     }
 
     Ok(())
 }
 
-pub async fn ingredient() -> Result<()> {
-    if !has_internet_access().await {
-        return Ok(());
-    }
-    
-    match fetch(Database::Main).await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
+pub fn dish_row(dish_name: &str, conn: &Connection) -> Result<()> {
+    let dish_id = get::dish_id_by_name(dish_name, conn)?;
 
-    let conn = get_connection();
+    conn.execute("DELETE FROM recipes WHERE dish_id = ?1", [dish_id])?;
+    conn.execute("DELETE FROM recipe_steps WHERE dish_id = ?1", [dish_id])?;
+    conn.execute("DELETE FROM dishes WHERE id = ?1", [dish_id])?;
 
-    let ingredient_id = match get::ingredient_id(&conn) {
-        Some(id) => id,
-        None => return Ok(()),
-    };
+    Ok(())
+}
 
-    println!("Are you sure you want to delete this ingredient from the database?");
-    if prompt("[Y/N]") != "y" {
-        println!("Deletion aborted");
-        return Ok(());
-    }
-    
-    let mut stmt = conn.prepare("DELETE FROM ingredients WHERE id = ?1;")?;
-    stmt.execute([&ingredient_id])?;
-
-    match sync().await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
+pub fn ingredient_row(ingredient_name: &str, policy: &InUsePolicy, conn: &Connection) -> Result<()> {
+    let ingredient_id = get::ingredient_id_by_name(ingredient_name, conn)?;
+
+    remove_ingredient(ingredient_id, policy, conn)
+}
+
+/// Names of the dishes whose recipe uses the ingredient.
+pub fn dishes_using(ingredient_id: u32, conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    conn.prepare(
+        "SELECT DISTINCT dishes.name FROM recipes
+        JOIN dishes ON dishes.id = recipes.dish_id
+        WHERE recipes.ingredient_id = ?1
+        ORDER BY dishes.name;"
+    )?
+    .query_map([ingredient_id], |row| row.get(0))?
+    .collect()
+}
+
+/// Deletes the ingredient and its prices, handling the recipes that use it according to `policy`.
+fn remove_ingredient(ingredient_id: u32, policy: &InUsePolicy, conn: &Connection) -> Result<()> {
+    let dishes = dishes_using(ingredient_id, conn)?;
+
+    if !dishes.is_empty() {
+        match policy {
+            InUsePolicy::Block => {
+                return Err(Error::Validation(format!("The ingredient is used by {}; remove it from these recipes or replace it first", dishes.join(", "))));
+            },
+            InUsePolicy::Cascade => {
+                conn.execute("DELETE FROM recipes WHERE ingredient_id = ?1;", [ingredient_id])?;
+            },
+            InUsePolicy::Replace(replacement_name) => {
+                let replacement_id = get::ingredient_id_by_name(replacement_name, conn)?;
+                if replacement_id == ingredient_id {
+                    return Err(Error::Validation("An ingredient cannot replace itself".to_string()));
+                }
+
+                replace_in_recipes(ingredient_id, replacement_id, conn)?;
+            },
+        }
     }
 
+    conn.execute("DELETE FROM prices WHERE ingredient_id = ?1;", [ingredient_id])?;
+    conn.execute("DELETE FROM ingredients WHERE id = ?1;", [ingredient_id])?;
+
+    Ok(())
+}
+
+/// Makes the recipes use `replacement_id` instead of `ingredient_id`.
+/// Recipes that already use the replacement keep a single line with both quantities.
+pub fn replace_in_recipes(ingredient_id: u32, replacement_id: u32, conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "UPDATE recipes SET quantity = COALESCE(quantity, 0) + COALESCE((
+            SELECT replaced.quantity FROM recipes AS replaced
+            WHERE replaced.dish_id = recipes.dish_id AND replaced.ingredient_id = ?1
+        ), 0)
+        WHERE ingredient_id = ?2 AND dish_id IN (SELECT dish_id FROM recipes WHERE ingredient_id = ?1);",
+        (ingredient_id, replacement_id),
+    )?;
+    conn.execute(
+        "DELETE FROM recipes WHERE ingredient_id = ?1 AND dish_id IN (SELECT dish_id FROM recipes WHERE ingredient_id = ?2);",
+        (ingredient_id, replacement_id),
+    )?;
+    conn.execute("UPDATE recipes SET ingredient_id = ?2 WHERE ingredient_id = ?1;", (ingredient_id, replacement_id))?;
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName};
+use serde::Serialize;
+
+use crate::cli_operations::{output::{is_table_output, print_records}, parser::Arguments, user_input::prompt};
+use crate::config;
+use crate::error::{Error, Result};
+
+use super::cloud::{fetch_to, require_internet_access, Database};
+use super::{backups, schema, snapshots};
+
+/// The compared tables, each as a query of its rows' natural key and the rest of the row as text.
+/// Rows are matched by key: a key in only one database is an added or removed row, a key with another value a modified one.
+const COMPARED_TABLES: [(&str, &str); 5] = [
+    ("dishes", "SELECT name, '' FROM dishes;"),
+    (
+        "ingredients",
+        "SELECT ingredients.name, IFNULL(categories.name, '?') || ', ' || IFNULL(ingredients.lifespan, '')
+        FROM ingredients
+        LEFT JOIN categories ON categories.id = ingredients.category_id;",
+    ),
+    (
+        "recipes",
+        "SELECT dishes.name || ': ' || ingredients.name, IFNULL(recipes.quantity, '?') || ' g' || IFNULL(', ' || recipes.note, '')
+        FROM recipes
+        JOIN dishes ON dishes.id = recipes.dish_id
+        JOIN ingredients ON ingredients.id = recipes.ingredient_id;",
+    ),
+    // the same price can be recorded more than once, so prices are matched by price and compared by count
+    (
+        "prices",
+        "SELECT ingredients.name || ' ' || printf('%.2f', prices.price), COUNT(*) || 'x'
+        FROM prices
+        JOIN ingredients ON ingredients.id = prices.ingredient_id
+        GROUP BY prices.ingredient_id, printf('%.2f', prices.price);",
+    ),
+    (
+        "recipe steps",
+        "SELECT dishes.name || ' step ' || recipe_steps.position, recipe_steps.text
+        FROM recipe_steps
+        JOIN dishes ON dishes.id = recipe_steps.dish_id;",
+    ),
+];
+
+/// One row that differs between two databases.
+#[derive(Debug, Serialize)]
+pub struct Change {
+    pub table: &'static str,
+    /// added, removed or modified
+    pub change: &'static str,
+    pub key: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// The rows that differ between `from` and `to`, table by table: "added" rows are only in `to`.
+pub fn between(from: &Connection, to: &Connection) -> Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    for (table, query) in COMPARED_TABLES {
+        let before = rows(from, query)?;
+        let after = rows(to, query)?;
+
+        for (key, before_value) in &before {
+            match after.get(key) {
+                None => changes.push(Change { table, change: "removed", key: key.clone(), before: before_value.clone(), after: String::new() }),
+                Some(after_value) if after_value != before_value => changes.push(Change {
+                    table,
+                    change: "modified",
+                    key: key.clone(),
+                    before: before_value.clone(),
+                    after: after_value.clone(),
+                }),
+                Some(_) => {},
+            }
+        }
+        for (key, after_value) in &after {
+            if !before.contains_key(key) {
+                changes.push(Change { table, change: "added", key: key.clone(), before: String::new(), after: after_value.clone() });
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+fn rows(conn: &Connection, query: &str) -> Result<BTreeMap<String, String>> {
+    let rows = conn
+        .prepare(query)?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<rusqlite::Result<BTreeMap<String, String>>>()?;
+
+    Ok(rows)
+}
+
+/// Prints the changes grouped by table, `+` for added, `-` for removed and `~` for modified rows,
+/// or as records in the other output formats.
+pub fn print(changes: &[Change]) {
+    if !is_table_output() {
+        print_records(changes, &[], |_| Vec::new());
+        return;
+    }
+    if changes.is_empty() {
+        println!("  no differences");
+        return;
+    }
+
+    let mut current_table = "";
+    for change in changes {
+        if change.table != current_table {
+            println!("  {}:", change.table);
+            current_table = change.table;
+        }
+        let value = |value: &str| if value.is_empty() { String::new() } else { format!(" ({value})") };
+        match change.change {
+            "added" => println!("    + {}{}", change.key, value(&change.after)),
+            "removed" => println!("    - {}{}", change.key, value(&change.before)),
+            _ => println!("    ~ {}: {} -> {}", change.key, change.before, change.after),
+        }
+    }
+}
+
+/// `diff <other>` shows what differs between the local database and `other`;
+/// `diff <from>, <to>` compares any two: `local`, `remote` (the database in Dropbox), `backup <id>`, `snapshot <number>` or a file.
+pub async fn run(args: &mut Arguments) -> Result<()> {
+    let first = args.next_raw_or_prompt("Compare the local database with (remote, backup ID, snapshot N or a file)");
+    let (from, to) = match args.next_raw_value() {
+        Some(second) => (first, second),
+        None => ("local".to_string(), first),
+    };
+
+    let from_conn = open(&from).await?;
+    let to_conn = open(&to).await?;
+    let changes = between(&from_conn, &to_conn)?;
+
+    if is_table_output() {
+        println!("From {from} to {to}:");
+    }
+    print(&changes);
+
+    Ok(())
+}
+
+/// Shows what `to` changes compared with `from` and asks whether to go ahead, for `fetch preview` and `sync preview`.
+pub async fn confirm(from: &str, to: &str, question: &str) -> Result<bool> {
+    let changes = between(&open(from).await?, &open(to).await?)?;
+    println!("From {from} to {to}:");
+    print(&changes);
+
+    println!("{question}");
+    Ok(prompt("[Y/N]") == "y")
+}
+
+/// An in-memory copy of a database, upgraded to the current schema so that databases of any version can be compared,
+/// without changing the file it was read from.
+pub fn load(path: &Path) -> Result<Connection> {
+    if !path.is_file() {
+        return Err(Error::NotFound(format!("No database file {}", path.display())));
+    }
+    let mut conn = Connection::open_in_memory()?;
+    conn.restore(DatabaseName::Main, path, None::<fn(Progress)>)
+        .map_err(|e| Error::from(e).in_file(&path.to_string_lossy()))?;
+    schema::migrate(&mut conn)?;
+
+    Ok(conn)
+}
+
+async fn open(source: &str) -> Result<Connection> {
+    let (kind, name) = match source.split_once(' ') {
+        Some((kind, name)) => (kind, name.trim()),
+        None => (source, ""),
+    };
+
+    match (kind.to_lowercase().as_str(), name) {
+        ("local", "") => load(&config::active_profile().db),
+        ("remote", "") => {
+            require_internet_access("compare with the database in Dropbox").await?;
+            download(Database::Main).await
+        },
+        ("backup", backup_id) if !backup_id.is_empty() => {
+            require_internet_access("compare with a backup").await?;
+            download(Database::Backup(backups::id(backup_id).await?)).await
+        },
+        ("snapshot", number_or_name) if !number_or_name.is_empty() => load(&snapshots::path(number_or_name)?),
+        _ => load(Path::new(source)),
+    }
+}
+
+async fn download(source: Database) -> Result<Connection> {
+    let path = download_path();
+    fetch_to(source, &path).await?;
+    let conn = load(&path);
+    fs::remove_file(&path)?;
+
+    conn
+}
+
+fn download_path() -> PathBuf {
+    std::env::temp_dir().join(format!("arino-diff-{}.db", std::process::id()))
+}
@@ -0,0 +1,711 @@
+use std::collections::HashMap;
+use rusqlite::types::Value;
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+
+use crate::cli_operations::{output::print_records, parser::Arguments, user_input::prompt};
+use crate::database::cloud::fetch_before_change;
+use crate::database::{commit_change, delete, get, get_connection, snapshots, validate, Outcome};
+use crate::error;
+use crate::lifespan;
+
+/// A problem found in the database, with what `doctor` can do about it.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub description: String,
+    #[serde(skip)]
+    problem: Problem,
+}
+
+#[derive(Debug, PartialEq)]
+enum Problem {
+    OrphanRecipeLine { recipe_id: u32 },
+    OrphanPrice { price_id: u32 },
+    OrphanStep { step_id: u32 },
+    MissingCategory { ingredient_id: u32 },
+    DuplicateDish { kept_id: u32, duplicate_id: u32 },
+    DuplicateIngredient { kept_id: u32, duplicate_id: u32 },
+    DuplicateRecipeLine { kept_id: u32, duplicate_id: u32 },
+    ZeroQuantity { recipe_id: u32 },
+    LifespanAsName { ingredient_id: u32, lifespan: String },
+    Lifespan { ingredient_id: u32, normalized: Option<String> },
+    Price { price_id: u32, value: Option<f32> },
+    EmptyDish { dish_id: u32 },
+}
+
+/// `doctor` reports the problems in the database, then fixes them automatically (`doctor auto`),
+/// one by one with a question each (`doctor guided`), or not at all.
+/// The latest database is only fetched and snapshotted once there is something to fix.
+pub async fn run(command_name: &str, args: &mut Arguments) -> error::Result<()> {
+    let findings = scan(&get_connection()?)?;
+    if findings.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+
+    print_records(&findings, &["Check", "Problem"], |finding| vec![finding.check.to_string(), finding.description.clone()]);
+    println!("{} problem(s) found", findings.len());
+
+    let mode = args.next_or_prompt("Fix automatically (auto), one by one (guided) or leave them (blank)");
+    let guided = match mode.as_str() {
+        "a" | "auto" => false,
+        "g" | "guided" => true,
+        "" => return Ok(()),
+        _ => {
+            eprintln!("Unknown choice \"{mode}\", nothing was fixed");
+            return Ok(());
+        },
+    };
+
+    fetch_before_change("change the database").await?;
+    snapshots::take(command_name)?;
+
+    let mut conn = get_connection()?;
+    let mut fixed = 0;
+    commit_change(&mut conn, command_name, |tx| {
+        fixed = match guided {
+            true => fix_guided(&findings, tx)?,
+            false => fix_automatically(&findings, tx)?,
+        };
+        Ok(if fixed == 0 { Outcome::Rollback } else { Outcome::Commit })
+    }).await?;
+
+    if fixed > 0 {
+        println!("Fixed {fixed} of {} problem(s)", findings.len());
+    }
+
+    Ok(())
+}
+
+/// Runs every check, orphans first since the other fixes rely on rows referencing existing rows.
+pub fn scan(conn: &Connection) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+    findings.extend(orphans(conn)?);
+    findings.extend(duplicate_dishes(conn)?);
+    findings.extend(duplicate_ingredients(conn)?);
+    findings.extend(duplicate_recipe_lines(conn)?);
+    findings.extend(zero_quantities(conn)?);
+    findings.extend(lifespans(conn)?);
+    findings.extend(prices(conn)?);
+    findings.extend(empty_dishes(conn)?);
+
+    Ok(findings)
+}
+
+fn fix_automatically(findings: &[Finding], conn: &Connection) -> Result<u32> {
+    let mut fixed = 0;
+    let mut undecided = 0;
+    for finding in findings {
+        if !is_present(&finding.problem, conn)? {
+            println!("{}: no longer found", finding.description);
+            continue;
+        }
+
+        // A merge removes a dish, an ingredient or a recipe line, so it is confirmed even here
+        if matches!(finding.problem, Problem::DuplicateDish { .. } | Problem::DuplicateIngredient { .. } | Problem::DuplicateRecipeLine { .. }) {
+            println!("{}", finding.description);
+            if !confirm("Merge them?") {
+                println!("Left as is");
+                continue;
+            }
+        }
+
+        if auto_fix(&finding.problem, conn)? {
+            fixed += 1;
+        } else {
+            undecided += 1;
+        }
+    }
+
+    if undecided > 0 {
+        println!("{undecided} problem(s) need a decision, run \"doctor guided\" to go through them");
+    }
+
+    Ok(fixed)
+}
+
+fn fix_guided(findings: &[Finding], conn: &Connection) -> Result<u32> {
+    let mut fixed = 0;
+    for (index, finding) in findings.iter().enumerate() {
+        println!("[{}/{}] {}", index + 1, findings.len(), finding.description);
+        if !is_present(&finding.problem, conn)? {
+            println!("No longer found");
+        } else if guided_fix(&finding.problem, conn)? {
+            fixed += 1;
+        } else {
+            println!("Left as is");
+        }
+    }
+
+    Ok(fixed)
+}
+
+/// Whether the problem is still in the database: the fetch may have brought a fixed database,
+/// and an earlier fix may have fixed it too, such as a merge that removed the row.
+fn is_present(problem: &Problem, conn: &Connection) -> Result<bool> {
+    Ok(scan(conn)?.iter().any(|finding| finding.problem == *problem))
+}
+
+/// Applies the fix that needs no decision, returns false when the problem needs one.
+fn auto_fix(problem: &Problem, conn: &Connection) -> Result<bool> {
+    match problem {
+        Problem::OrphanRecipeLine { recipe_id } | Problem::ZeroQuantity { recipe_id } => {
+            conn.execute("DELETE FROM recipes WHERE id = ?1;", [recipe_id])?;
+        },
+        Problem::OrphanPrice { price_id } | Problem::Price { price_id, value: None } => {
+            conn.execute("DELETE FROM prices WHERE id = ?1;", [price_id])?;
+        },
+        Problem::OrphanStep { step_id } => {
+            conn.execute("DELETE FROM recipe_steps WHERE id = ?1;", [step_id])?;
+        },
+        Problem::MissingCategory { ingredient_id } => {
+            conn.execute("UPDATE ingredients SET category_id = NULL WHERE id = ?1;", [ingredient_id])?;
+        },
+        Problem::DuplicateDish { kept_id, duplicate_id } => merge_dishes(*kept_id, *duplicate_id, conn)?,
+        Problem::DuplicateIngredient { kept_id, duplicate_id } => merge_ingredients(*kept_id, *duplicate_id, conn)?,
+        Problem::DuplicateRecipeLine { kept_id, duplicate_id } => {
+            conn.execute(
+                "UPDATE recipes SET
+                    quantity = COALESCE(quantity, 0) + COALESCE((SELECT quantity FROM recipes WHERE id = ?2), 0),
+                    note = COALESCE(note, (SELECT note FROM recipes WHERE id = ?2))
+                WHERE id = ?1;",
+                (kept_id, duplicate_id),
+            )?;
+            conn.execute("DELETE FROM recipes WHERE id = ?1;", [duplicate_id])?;
+        },
+        Problem::Lifespan { ingredient_id, normalized } => {
+            conn.execute("UPDATE ingredients SET lifespan = ?1 WHERE id = ?2;", (normalized, ingredient_id))?;
+        },
+        Problem::Price { price_id, value: Some(value) } => {
+            conn.execute("UPDATE prices SET price = ?1 WHERE id = ?2;", (validate::stored_price(*value), price_id))?;
+        },
+        Problem::LifespanAsName { .. } | Problem::EmptyDish { .. } => return Ok(false),
+    }
+
+    Ok(true)
+}
+
+/// Asks how to fix the problem, returns false when it was left as is.
+fn guided_fix(problem: &Problem, conn: &Connection) -> Result<bool> {
+    match problem {
+        Problem::OrphanRecipeLine { .. } | Problem::OrphanPrice { .. } | Problem::OrphanStep { .. } => {
+            if !confirm("Delete it?") {
+                return Ok(false);
+            }
+        },
+        Problem::MissingCategory { ingredient_id } => {
+            let category_id = match get::category_name_and_id(conn, &mut Arguments::empty()) {
+                Some((_, category_id)) => category_id,
+                None => return Ok(false),
+            };
+            conn.execute("UPDATE ingredients SET category_id = ?1 WHERE id = ?2;", (category_id, ingredient_id))?;
+            return Ok(true);
+        },
+        Problem::DuplicateDish { .. } | Problem::DuplicateIngredient { .. } | Problem::DuplicateRecipeLine { .. } => {
+            if !confirm("Merge them?") {
+                return Ok(false);
+            }
+        },
+        Problem::ZeroQuantity { recipe_id } => {
+            let quantity = loop {
+                let entered = prompt("Quantity (blank removes the line)");
+                if entered.is_empty() {
+                    conn.execute("DELETE FROM recipes WHERE id = ?1;", [recipe_id])?;
+                    return Ok(true);
+                }
+                match validate::quantity_text(&entered) {
+                    Ok(quantity) => break quantity,
+                    Err(e) => eprintln!("{e}"),
+                }
+            };
+            conn.execute("UPDATE recipes SET quantity = ?1 WHERE id = ?2;", (quantity, recipe_id))?;
+            return Ok(true);
+        },
+        Problem::LifespanAsName { ingredient_id, lifespan } => {
+            // Checked like any new name, as a clash with the UNIQUE constraint would undo every fix made so far
+            let name = loop {
+                let entered = prompt("Name of the ingredient (blank skips)");
+                if entered.is_empty() {
+                    return Ok(false);
+                }
+                match validate::ingredient_name(&entered, Some(*ingredient_id), conn) {
+                    Ok(name) => break name,
+                    Err(e) => eprintln!("{e}"),
+                }
+            };
+            conn.execute("UPDATE ingredients SET name = ?1, lifespan = ?2 WHERE id = ?3;", (name, lifespan, ingredient_id))?;
+            return Ok(true);
+        },
+        Problem::Lifespan { ingredient_id, normalized: None } => {
+            let entered = prompt("Lifespan (in _y_mo_d_h_m_s, blank clears it)");
+            let lifespan = match entered.as_str() {
+                "" => None,
+                entered => match lifespan::normalize(entered) {
+                    Some(lifespan) => Some(lifespan),
+                    None => {
+                        eprintln!("Invalid lifespan \"{entered}\"");
+                        return Ok(false);
+                    },
+                },
+            };
+            conn.execute("UPDATE ingredients SET lifespan = ?1 WHERE id = ?2;", (lifespan, ingredient_id))?;
+            return Ok(true);
+        },
+        Problem::Lifespan { normalized: Some(normalized), .. } => {
+            if !confirm(&format!("Change it to \"{normalized}\"?")) {
+                return Ok(false);
+            }
+        },
+        Problem::Price { price_id, value: None } => {
+            let entered = prompt("Price (blank deletes it)");
+            if entered.is_empty() {
+                conn.execute("DELETE FROM prices WHERE id = ?1;", [price_id])?;
+                return Ok(true);
+            }
+            match validate::price_text(&entered) {
+                Ok(price) => {
+                    conn.execute("UPDATE prices SET price = ?1 WHERE id = ?2;", (validate::stored_price(price), price_id))?;
+                    return Ok(true);
+                },
+                Err(e) => {
+                    eprintln!("{e}");
+                    return Ok(false);
+                },
+            }
+        },
+        Problem::Price { value: Some(value), .. } => {
+            if !confirm(&format!("Store it as {value}?")) {
+                return Ok(false);
+            }
+        },
+        Problem::EmptyDish { dish_id } => {
+            if !confirm("Delete the dish?") {
+                return Ok(false);
+            }
+            conn.execute("DELETE FROM recipe_steps WHERE dish_id = ?1;", [dish_id])?;
+            conn.execute("DELETE FROM dishes WHERE id = ?1;", [dish_id])?;
+            return Ok(true);
+        },
+    }
+
+    auto_fix(problem, conn)
+}
+
+fn confirm(question: &str) -> bool {
+    println!("{question}");
+    prompt("[Y/N]") == "y"
+}
+
+fn orphans(conn: &Connection) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    let mut recipes_stmt = conn.prepare(
+        "SELECT recipes.id, recipes.dish_id, dishes.name, recipes.ingredient_id, ingredients.name FROM recipes
+        LEFT JOIN dishes ON dishes.id = recipes.dish_id
+        LEFT JOIN ingredients ON ingredients.id = recipes.ingredient_id
+        WHERE dishes.id IS NULL OR ingredients.id IS NULL;"
+    )?;
+    let recipe_lines = recipes_stmt.query_map([], |row| {
+        Ok((row.get::<_, u32>(0)?, row.get::<_, Option<u32>>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, Option<u32>>(3)?, row.get::<_, Option<String>>(4)?))
+    })?;
+    for recipe_line in recipe_lines {
+        let (recipe_id, dish_id, dish_name, ingredient_id, ingredient_name) = recipe_line?;
+        let dish = dish_name.map(|name| format!("\"{name}\"")).unwrap_or(format!("missing dish #{}", id_text(dish_id)));
+        let ingredient = ingredient_name.map(|name| format!("\"{name}\"")).unwrap_or(format!("missing ingredient #{}", id_text(ingredient_id)));
+        findings.push(Finding {
+            check: "orphan",
+            description: format!("Recipe line #{recipe_id} links {dish} to {ingredient}"),
+            problem: Problem::OrphanRecipeLine { recipe_id },
+        });
+    }
+
+    let mut prices_stmt = conn.prepare(
+        "SELECT prices.id, prices.ingredient_id FROM prices
+        LEFT JOIN ingredients ON ingredients.id = prices.ingredient_id
+        WHERE ingredients.id IS NULL;"
+    )?;
+    let prices = prices_stmt.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, Option<u32>>(1)?)))?;
+    for price in prices {
+        let (price_id, ingredient_id) = price?;
+        findings.push(Finding {
+            check: "orphan",
+            description: format!("Price #{price_id} belongs to missing ingredient #{}", id_text(ingredient_id)),
+            problem: Problem::OrphanPrice { price_id },
+        });
+    }
+
+    let mut steps_stmt = conn.prepare(
+        "SELECT recipe_steps.id, recipe_steps.dish_id FROM recipe_steps
+        LEFT JOIN dishes ON dishes.id = recipe_steps.dish_id
+        WHERE dishes.id IS NULL;"
+    )?;
+    let steps = steps_stmt.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, Option<u32>>(1)?)))?;
+    for step in steps {
+        let (step_id, dish_id) = step?;
+        findings.push(Finding {
+            check: "orphan",
+            description: format!("Step #{step_id} belongs to missing dish #{}", id_text(dish_id)),
+            problem: Problem::OrphanStep { step_id },
+        });
+    }
+
+    let mut categories_stmt = conn.prepare(
+        "SELECT ingredients.id, ingredients.name, ingredients.category_id FROM ingredients
+        LEFT JOIN categories ON categories.id = ingredients.category_id
+        WHERE ingredients.category_id IS NOT NULL AND categories.id IS NULL;"
+    )?;
+    let ingredients = categories_stmt.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, u32>(2)?)))?;
+    for ingredient in ingredients {
+        let (ingredient_id, name, category_id) = ingredient?;
+        findings.push(Finding {
+            check: "orphan",
+            description: format!("\"{name}\" is in missing category #{category_id}"),
+            problem: Problem::MissingCategory { ingredient_id },
+        });
+    }
+
+    Ok(findings)
+}
+
+fn id_text(id: Option<u32>) -> String {
+    id.map(|id| id.to_string()).unwrap_or("?".to_string())
+}
+
+/// Names that only differ in case or surrounding spaces, as (kept id, kept name, duplicate id, duplicate name).
+/// The oldest row is kept.
+fn duplicate_names(table_query: &str, conn: &Connection) -> Result<Vec<(u32, String, u32, String)>> {
+    let rows = conn
+        .prepare(table_query)?
+        .query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<(u32, String)>>>()?;
+
+    let mut first_by_name: HashMap<String, (u32, String)> = HashMap::new();
+    let mut duplicates = Vec::new();
+    for (id, name) in rows {
+        match first_by_name.get(&name.trim().to_lowercase()) {
+            Some((kept_id, kept_name)) => duplicates.push((*kept_id, kept_name.clone(), id, name)),
+            None => {
+                first_by_name.insert(name.trim().to_lowercase(), (id, name));
+            },
+        }
+    }
+
+    Ok(duplicates)
+}
+
+fn duplicate_dishes(conn: &Connection) -> Result<Vec<Finding>> {
+    let duplicates = duplicate_names("SELECT id, name FROM dishes ORDER BY id;", conn)?;
+
+    Ok(duplicates
+        .into_iter()
+        .map(|(kept_id, kept_name, duplicate_id, duplicate_name)| Finding {
+            check: "duplicate",
+            description: format!("Dish \"{duplicate_name}\" (#{duplicate_id}) duplicates \"{kept_name}\" (#{kept_id})"),
+            problem: Problem::DuplicateDish { kept_id, duplicate_id },
+        })
+        .collect())
+}
+
+fn duplicate_ingredients(conn: &Connection) -> Result<Vec<Finding>> {
+    let duplicates = duplicate_names("SELECT id, name FROM ingredients ORDER BY id;", conn)?;
+
+    Ok(duplicates
+        .into_iter()
+        .map(|(kept_id, kept_name, duplicate_id, duplicate_name)| Finding {
+            check: "duplicate",
+            description: format!("Ingredient \"{duplicate_name}\" (#{duplicate_id}) duplicates \"{kept_name}\" (#{kept_id})"),
+            problem: Problem::DuplicateIngredient { kept_id, duplicate_id },
+        })
+        .collect())
+}
+
+fn duplicate_recipe_lines(conn: &Connection) -> Result<Vec<Finding>> {
+    let mut stmt = conn.prepare(
+        "SELECT kept.id, duplicate.id, dishes.name, ingredients.name FROM recipes AS duplicate
+        JOIN recipes AS kept ON kept.id = (
+            SELECT MIN(id) FROM recipes WHERE dish_id = duplicate.dish_id AND ingredient_id = duplicate.ingredient_id
+        )
+        JOIN dishes ON dishes.id = duplicate.dish_id
+        JOIN ingredients ON ingredients.id = duplicate.ingredient_id
+        WHERE duplicate.id != kept.id;"
+    )?;
+    let recipe_lines = stmt.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, u32>(1)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?)))?;
+
+    let mut findings = Vec::new();
+    for recipe_line in recipe_lines {
+        let (kept_id, duplicate_id, dish, ingredient) = recipe_line?;
+        findings.push(Finding {
+            check: "duplicate",
+            description: format!("\"{ingredient}\" appears more than once in \"{dish}\""),
+            problem: Problem::DuplicateRecipeLine { kept_id, duplicate_id },
+        });
+    }
+
+    Ok(findings)
+}
+
+fn zero_quantities(conn: &Connection) -> Result<Vec<Finding>> {
+    let mut stmt = conn.prepare(
+        "SELECT recipes.id, dishes.name, ingredients.name FROM recipes
+        JOIN dishes ON dishes.id = recipes.dish_id
+        JOIN ingredients ON ingredients.id = recipes.ingredient_id
+        WHERE recipes.quantity IS NULL OR recipes.quantity <= 0;"
+    )?;
+    let recipe_lines = stmt.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?)))?;
+
+    let mut findings = Vec::new();
+    for recipe_line in recipe_lines {
+        let (recipe_id, dish, ingredient) = recipe_line?;
+        findings.push(Finding {
+            check: "quantity",
+            description: format!("\"{ingredient}\" in \"{dish}\" has no quantity"),
+            problem: Problem::ZeroQuantity { recipe_id },
+        });
+    }
+
+    Ok(findings)
+}
+
+fn lifespans(conn: &Connection) -> Result<Vec<Finding>> {
+    let ingredients = conn
+        .prepare("SELECT id, name, lifespan FROM ingredients ORDER BY id;")?
+        .query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?)))?
+        .collect::<Result<Vec<(u32, String, Option<String>)>>>()?;
+
+    let mut findings = Vec::new();
+    for (ingredient_id, name, lifespan) in ingredients {
+        // "update ingredient" used to write the new lifespan into the name
+        if let Some(lifespan) = lifespan::normalize(&name) {
+            findings.push(Finding {
+                check: "lifespan",
+                description: format!("Ingredient #{ingredient_id} is named \"{name}\", which is a lifespan"),
+                problem: Problem::LifespanAsName { ingredient_id, lifespan },
+            });
+        }
+
+        let Some(lifespan) = lifespan.filter(|lifespan| !lifespan.trim().is_empty()) else {
+            continue;
+        };
+        match lifespan::normalize(&lifespan) {
+            Some(normalized) if normalized == lifespan => {},
+            Some(normalized) => findings.push(Finding {
+                check: "lifespan",
+                description: format!("Lifespan \"{lifespan}\" of \"{name}\" should be written \"{normalized}\""),
+                problem: Problem::Lifespan { ingredient_id, normalized: Some(normalized) },
+            }),
+            None => findings.push(Finding {
+                check: "lifespan",
+                description: format!("Lifespan \"{lifespan}\" of \"{name}\" cannot be read"),
+                problem: Problem::Lifespan { ingredient_id, normalized: None },
+            }),
+        }
+    }
+
+    Ok(findings)
+}
+
+fn prices(conn: &Connection) -> Result<Vec<Finding>> {
+    let mut stmt = conn.prepare(
+        "SELECT prices.id, ingredients.name, prices.price FROM prices
+        JOIN ingredients ON ingredients.id = prices.ingredient_id
+        WHERE typeof(prices.price) NOT IN ('integer', 'real');"
+    )?;
+    let prices = stmt.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, Value>(2)?)))?;
+
+    let mut findings = Vec::new();
+    for price in prices {
+        let (price_id, ingredient, price) = price?;
+        let (text, value) = match price {
+            Value::Text(text) => {
+                let value = text.trim().trim_start_matches('$').parse::<f32>().ok();
+                (format!("\"{text}\""), value)
+            },
+            Value::Null => ("empty".to_string(), None),
+            _ => ("not text".to_string(), None),
+        };
+        findings.push(Finding {
+            check: "price",
+            description: format!("Price #{price_id} of \"{ingredient}\" is not a number: {text}"),
+            problem: Problem::Price { price_id, value },
+        });
+    }
+
+    Ok(findings)
+}
+
+fn empty_dishes(conn: &Connection) -> Result<Vec<Finding>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, name FROM dishes
+        WHERE id NOT IN (SELECT dish_id FROM recipes WHERE dish_id IS NOT NULL)
+        ORDER BY id;"
+    )?;
+    let dishes = stmt.query_map([], |row| Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?)))?;
+
+    let mut findings = Vec::new();
+    for dish in dishes {
+        let (dish_id, name) = dish?;
+        findings.push(Finding {
+            check: "recipe",
+            description: format!("Dish \"{name}\" has no recipe"),
+            problem: Problem::EmptyDish { dish_id },
+        });
+    }
+
+    Ok(findings)
+}
+
+/// Moves the recipe and steps of the duplicate dish to the kept one, keeping the kept lines and steps where both have them.
+fn merge_dishes(kept_id: u32, duplicate_id: u32, conn: &Connection) -> Result<()> {
+    conn.execute(
+        "DELETE FROM recipes WHERE dish_id = ?2 AND ingredient_id IN (SELECT ingredient_id FROM recipes WHERE dish_id = ?1);",
+        (kept_id, duplicate_id),
+    )?;
+    conn.execute("UPDATE recipes SET dish_id = ?1 WHERE dish_id = ?2;", (kept_id, duplicate_id))?;
+    conn.execute(
+        "DELETE FROM recipe_steps WHERE dish_id = ?2 AND EXISTS (SELECT 1 FROM recipe_steps WHERE dish_id = ?1);",
+        (kept_id, duplicate_id),
+    )?;
+    conn.execute("UPDATE recipe_steps SET dish_id = ?1 WHERE dish_id = ?2;", (kept_id, duplicate_id))?;
+    conn.execute("DELETE FROM dishes WHERE id = ?1;", [duplicate_id])?;
+
+    Ok(())
+}
+
+/// Moves the recipe lines and prices of the duplicate ingredient to the kept one, filling in its missing category and lifespan.
+fn merge_ingredients(kept_id: u32, duplicate_id: u32, conn: &Connection) -> Result<()> {
+    delete::replace_in_recipes(duplicate_id, kept_id, conn)?;
+    conn.execute("UPDATE prices SET ingredient_id = ?1 WHERE ingredient_id = ?2;", (kept_id, duplicate_id))?;
+    conn.execute(
+        "UPDATE ingredients SET
+            category_id = COALESCE(category_id, (SELECT category_id FROM ingredients WHERE id = ?2)),
+            lifespan = COALESCE(NULLIF(lifespan, ''), (SELECT lifespan FROM ingredients WHERE id = ?2))
+        WHERE id = ?1;",
+        (kept_id, duplicate_id),
+    )?;
+    conn.execute("DELETE FROM ingredients WHERE id = ?1;", [duplicate_id])?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::schema;
+
+    /// A migrated database without foreign keys, so that the tests can write what older versions left behind.
+    fn database(rows: &str) -> Connection {
+        let mut conn = Connection::open_in_memory().unwrap();
+        schema::migrate(&mut conn).unwrap();
+        conn.pragma_update(None, "foreign_keys", false).unwrap();
+        conn.execute_batch(rows).unwrap();
+        conn
+    }
+
+    fn problems(conn: &Connection) -> Vec<Problem> {
+        scan(conn).unwrap().into_iter().map(|finding| finding.problem).collect()
+    }
+
+    #[test]
+    fn finds_nothing_in_a_clean_database() {
+        let conn = database(
+            "INSERT INTO dishes (name) VALUES ('soup');
+            INSERT INTO ingredients (name, category_id, lifespan) VALUES ('leek', 1, '2w');
+            INSERT INTO recipes (dish_id, ingredient_id, quantity) VALUES (1, 1, 2);
+            INSERT INTO prices (ingredient_id, price) VALUES (1, 1.5);"
+        );
+
+        assert_eq!(problems(&conn), vec![]);
+    }
+
+    #[test]
+    fn finds_rows_referencing_missing_rows() {
+        let conn = database(
+            "INSERT INTO dishes (name) VALUES ('soup');
+            INSERT INTO ingredients (name, category_id) VALUES ('leek', 1), ('salt', 42);
+            INSERT INTO recipes (dish_id, ingredient_id, quantity) VALUES (1, 1, 2), (7, 1, 1);
+            INSERT INTO prices (ingredient_id, price) VALUES (9, 1.5);
+            INSERT INTO recipe_steps (dish_id, position, text) VALUES (8, 1, 'Boil');"
+        );
+
+        assert_eq!(problems(&conn), vec![
+            Problem::OrphanRecipeLine { recipe_id: 2 },
+            Problem::OrphanPrice { price_id: 1 },
+            Problem::OrphanStep { step_id: 1 },
+            Problem::MissingCategory { ingredient_id: 2 },
+        ]);
+    }
+
+    #[test]
+    fn finds_names_that_only_differ_in_case_and_spaces() {
+        let conn = database(
+            "INSERT INTO dishes (name) VALUES ('soup'), ('Soup '), ('stew');
+            INSERT INTO ingredients (name, category_id) VALUES ('leek', 1), ('LEEK', 1);
+            INSERT INTO recipes (dish_id, ingredient_id, quantity) VALUES (1, 1, 2), (2, 2, 1), (3, 1, 1), (3, 1, 3);"
+        );
+
+        assert_eq!(problems(&conn), vec![
+            Problem::DuplicateDish { kept_id: 1, duplicate_id: 2 },
+            Problem::DuplicateIngredient { kept_id: 1, duplicate_id: 2 },
+            Problem::DuplicateRecipeLine { kept_id: 3, duplicate_id: 4 },
+        ]);
+    }
+
+    #[test]
+    fn finds_missing_quantities_and_empty_dishes() {
+        let conn = database(
+            "INSERT INTO dishes (name) VALUES ('soup'), ('toast');
+            INSERT INTO ingredients (name, category_id) VALUES ('leek', 1);
+            INSERT INTO recipes (dish_id, ingredient_id, quantity) VALUES (1, 1, 0);"
+        );
+
+        assert_eq!(problems(&conn), vec![
+            Problem::ZeroQuantity { recipe_id: 1 },
+            Problem::EmptyDish { dish_id: 2 },
+        ]);
+    }
+
+    #[test]
+    fn finds_lifespans_in_names_and_unreadable_lifespans() {
+        let conn = database(
+            "INSERT INTO dishes (name) VALUES ('soup');
+            INSERT INTO ingredients (name, category_id, lifespan) VALUES ('3d', 1, NULL), ('leek', 1, '2 W'), ('salt', 1, 'forever');
+            INSERT INTO recipes (dish_id, ingredient_id, quantity) VALUES (1, 2, 1);"
+        );
+
+        assert_eq!(problems(&conn), vec![
+            Problem::LifespanAsName { ingredient_id: 1, lifespan: "3d".to_string() },
+            Problem::Lifespan { ingredient_id: 2, normalized: Some("2w".to_string()) },
+            Problem::Lifespan { ingredient_id: 3, normalized: None },
+        ]);
+    }
+
+    #[test]
+    fn finds_prices_stored_as_text() {
+        let conn = database(
+            "INSERT INTO ingredients (name, category_id) VALUES ('leek', 1);
+            INSERT INTO prices (ingredient_id, price) VALUES (1, '$2.50'), (1, 'cheap'), (1, 3);"
+        );
+
+        assert_eq!(problems(&conn), vec![
+            Problem::Price { price_id: 1, value: Some(2.5) },
+            Problem::Price { price_id: 2, value: None },
+        ]);
+    }
+
+    #[test]
+    fn a_fixed_problem_is_no_longer_present() {
+        let conn = database(
+            "INSERT INTO dishes (name) VALUES ('soup'), ('Soup'), ('SOUP');
+            INSERT INTO ingredients (name, category_id) VALUES ('leek', 1);
+            INSERT INTO recipes (dish_id, ingredient_id, quantity) VALUES (1, 1, 2), (2, 1, 1), (3, 1, 1);"
+        );
+        let first = Problem::DuplicateDish { kept_id: 1, duplicate_id: 2 };
+        let second = Problem::DuplicateDish { kept_id: 1, duplicate_id: 3 };
+
+        assert!(auto_fix(&first, &conn).unwrap());
+
+        assert!(!is_present(&first, &conn).unwrap());
+        assert!(is_present(&second, &conn).unwrap());
+    }
+}
@@ -0,0 +1,198 @@
+use std::sync::Mutex;
+use argon2::Argon2;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use crate::error::{Error, Result};
+
+/// Unlocks the database in Dropbox without a prompt, for scripts.
+/// It is shared by every device of the household, unlike the passphrase of the credentials.
+pub const DATABASE_PASSPHRASE_ENV: &str = "ARINO_DATABASE_PASSPHRASE";
+/// Marks an encrypted upload; a SQLite file starts with `SQLite format 3` instead.
+const MAGIC: &[u8] = b"ARINOENC";
+const FORMAT_VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + SALT_LENGTH;
+
+/// The salt and key that last encrypted or decrypted a database, so that the passphrase is asked once per run.
+static DATABASE_KEY: Mutex<Option<(Vec<u8>, [u8; 32])>> = Mutex::new(None);
+
+/// Whether a downloaded file was encrypted by `encrypt`.
+pub fn is_encrypted(content: &[u8]) -> bool {
+    content.starts_with(MAGIC)
+}
+
+/// Encrypts the database for upload with XChaCha20-Poly1305, under a key derived from the database passphrase with Argon2id.
+/// The file is the header (magic, version and salt), the nonce and the ciphertext; the header is authenticated too.
+pub fn encrypt(plaintext: &[u8]) -> Result<Vec<u8>> {
+    let (salt, key) = unlock()?;
+    seal(plaintext, &salt, &key)
+}
+
+fn seal(plaintext: &[u8], salt: &[u8], key: &[u8; 32]) -> Result<Vec<u8>> {
+    let mut content = MAGIC.to_vec();
+    content.push(FORMAT_VERSION);
+    content.extend_from_slice(salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(&nonce, Payload { msg: plaintext, aad: &content })
+        .map_err(|_| Error::Validation("The database could not be encrypted".to_string()))?;
+    content.extend_from_slice(&nonce);
+    content.extend_from_slice(&ciphertext);
+
+    Ok(content)
+}
+
+/// Whether this run already knows the key of the database, from a download or an earlier upload.
+pub fn is_unlocked() -> bool {
+    DATABASE_KEY.lock().expect("database key lock poisoned").is_some()
+}
+
+/// The salt and key of the next upload, asking for a new passphrase unless this run already knows one.
+/// Batched uploads run in the background, so the REPL unlocks the key before it queues them.
+pub fn unlock() -> Result<(Vec<u8>, [u8; 32])> {
+    if let Some(salt_and_key) = DATABASE_KEY.lock().expect("database key lock poisoned").clone() {
+        return Ok(salt_and_key);
+    }
+
+    let mut salt = vec![0u8; SALT_LENGTH];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(&new_passphrase()?, &salt)?;
+    *DATABASE_KEY.lock().expect("database key lock poisoned") = Some((salt.clone(), key));
+
+    Ok((salt, key))
+}
+
+/// Decrypts a file written by `encrypt`, asking for the passphrase unless this run already knows it.
+pub fn decrypt(content: &[u8]) -> Result<Vec<u8>> {
+    let salt = salt(content)?;
+
+    let cached_key = DATABASE_KEY.lock().expect("database key lock poisoned").clone();
+    let key = match cached_key {
+        Some((cached_salt, key)) if cached_salt == salt => key,
+        _ => derive_key(&passphrase("Passphrase of the database in Dropbox")?, salt)?,
+    };
+    let plaintext = open(content, &key)?;
+
+    *DATABASE_KEY.lock().expect("database key lock poisoned") = Some((salt.to_vec(), key));
+
+    Ok(plaintext)
+}
+
+/// The salt of an encrypted file, checked before any passphrase is asked.
+fn salt(content: &[u8]) -> Result<&[u8]> {
+    if content.len() < HEADER_LENGTH + NONCE_LENGTH || !is_encrypted(content) {
+        return Err(Error::Validation("The encrypted database from Dropbox is damaged".to_string()));
+    }
+    if content[MAGIC.len()] != FORMAT_VERSION {
+        return Err(Error::Validation("The database in Dropbox was encrypted by a newer version of Arino".to_string()));
+    }
+
+    Ok(&content[MAGIC.len() + 1..HEADER_LENGTH])
+}
+
+fn open(content: &[u8], key: &[u8; 32]) -> Result<Vec<u8>> {
+    salt(content)?;
+    let (header, rest) = content.split_at(HEADER_LENGTH);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LENGTH);
+
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: header })
+        .map_err(|_| Error::Validation(format!("Wrong passphrase for the database in Dropbox, or the file is damaged; {DATABASE_PASSPHRASE_ENV} may be outdated")))
+}
+
+fn passphrase(prompt: &str) -> Result<String> {
+    if let Ok(passphrase) = std::env::var(DATABASE_PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    let passphrase = rpassword::prompt_password(format!("{prompt}> "))?;
+    if passphrase.is_empty() {
+        return Err(Error::Validation("A passphrase is required for the encrypted database in Dropbox".to_string()));
+    }
+
+    Ok(passphrase)
+}
+
+/// A passphrase that no download has checked yet is typed twice, as a typo would lock the database in Dropbox.
+fn new_passphrase() -> Result<String> {
+    if let Ok(passphrase) = std::env::var(DATABASE_PASSPHRASE_ENV) {
+        return Ok(passphrase);
+    }
+
+    println!("The database is encrypted before it is uploaded, every device needs the same passphrase to fetch it");
+    let passphrase = passphrase("Passphrase of the database in Dropbox")?;
+    if passphrase != self::passphrase("Repeat passphrase")? {
+        return Err(Error::Validation("The passphrases do not match".to_string()));
+    }
+
+    Ok(passphrase)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| Error::Validation(format!("The passphrase cannot be used: {e}")))?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SALT: [u8; SALT_LENGTH] = [3u8; SALT_LENGTH];
+    const DATABASE: &[u8] = b"SQLite format 3\0 and the rest of the database";
+
+    fn key(passphrase: &str) -> [u8; 32] {
+        derive_key(passphrase, &SALT).unwrap()
+    }
+
+    #[test]
+    fn opens_what_it_sealed() {
+        let content = seal(DATABASE, &SALT, &key("household")).unwrap();
+
+        assert!(is_encrypted(&content));
+        assert_eq!(salt(&content).unwrap(), SALT);
+        assert_eq!(open(&content, &key("household")).unwrap(), DATABASE);
+    }
+
+    #[test]
+    fn rejects_a_wrong_passphrase() {
+        let content = seal(DATABASE, &SALT, &key("household")).unwrap();
+
+        match open(&content, &key("houshold")) {
+            Err(Error::Validation(message)) => assert!(message.starts_with("Wrong passphrase")),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_a_truncated_file() {
+        let content = seal(DATABASE, &SALT, &key("household")).unwrap();
+
+        let header_only = &content[..HEADER_LENGTH + NONCE_LENGTH - 1];
+        assert!(matches!(open(header_only, &key("household")), Err(Error::Validation(message)) if message.contains("damaged")));
+        let cut = &content[..content.len() - 1];
+        assert!(matches!(open(cut, &key("household")), Err(Error::Validation(message)) if message.starts_with("Wrong passphrase")));
+    }
+
+    #[test]
+    fn rejects_another_format_version() {
+        let mut content = seal(DATABASE, &SALT, &key("household")).unwrap();
+        content[MAGIC.len()] = FORMAT_VERSION + 1;
+
+        assert!(matches!(salt(&content), Err(Error::Validation(message)) if message.contains("newer version")));
+    }
+
+    #[test]
+    fn authenticates_the_header() {
+        let mut content = seal(DATABASE, &SALT, &key("household")).unwrap();
+        content[MAGIC.len() + 1] ^= 1;
+
+        assert!(open(&content, &key("household")).is_err());
+    }
+}
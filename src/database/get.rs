@@ -1,29 +1,23 @@
 use rusqlite::Connection;
 
-use crate::{cli_operations::user_input::prompt, helper::calculate_mean};
+use crate::{cli_operations::parser::Arguments, error::{Error, Result}, helper::calculate_mean};
 
-pub fn dish_id(conn: &Connection) -> Option<u32> {
-    let dish_id = loop {
-        let dish_name = prompt("Dish name");
+pub fn dish_id(conn: &Connection, args: &mut Arguments) -> Option<u32> {
+    loop {
+        let dish_name = args.next_or_prompt("Dish name");
         if dish_name.is_empty() {
             return None;
         }
 
-        let retrieved_dish_id: u32 = match conn.query_row("SELECT id FROM dishes WHERE name = ?1;", [&dish_name], |row| row.get(0)) {
-            Ok(id) => id,
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                eprintln!("Invalid dish name");
-                continue;
-            },
+        match dish_id_by_name(&dish_name, conn) {
+            Ok(id) => return Some(id),
+            Err(Error::NotFound(message)) => eprintln!("{message}"),
             Err(e) => {
-                eprintln!("Error: {e}");
-                continue;
-            }
-        };
-        break retrieved_dish_id;
-    };
-
-    Some(dish_id)
+                eprintln!("{e}");
+                return None;
+            },
+        }
+    }
 }
 
 pub fn dish_name(dish_id: u32, conn: &Connection) -> Option<String> {
@@ -42,52 +36,60 @@ pub fn dish_name(dish_id: u32, conn: &Connection) -> Option<String> {
     retrieved_dish_name
 }
 
-pub fn ingredient_id(conn: &Connection) -> Option<u32> {
-    let ingredient_id = loop {
-        let ingredient_name = prompt("Ingredient name");
+pub fn ingredient_id(conn: &Connection, args: &mut Arguments) -> Option<u32> {
+    ingredient_name_and_id(conn, args).map(|(_, id)| id)
+}
+
+/// Asks for an existing ingredient until one is found, returns `None` when the answer is blank.
+pub fn ingredient_name_and_id(conn: &Connection, args: &mut Arguments) -> Option<(String, u32)> {
+    loop {
+        let ingredient_name = args.next_or_prompt("Ingredient name");
         if ingredient_name.is_empty() {
             return None;
         }
 
-        let retrieved_ingredient_id: u32 = match conn.query_row("SELECT id FROM ingredients WHERE name = ?1;", [&ingredient_name], |row| row.get(0)) {
-            Ok(id) => id,
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                eprintln!("Invalid ingredient name");
-                continue;
-            },
+        match ingredient_id_by_name(&ingredient_name, conn) {
+            Ok(id) => return Some((ingredient_name.trim().to_string(), id)),
+            Err(Error::NotFound(message)) => eprintln!("{message}"),
             Err(e) => {
-                eprintln!("Error: {e}");
-                continue;
-            }
-        };
-        break retrieved_ingredient_id;
-    };
+                eprintln!("{e}");
+                return None;
+            },
+        }
+    }
+}
 
-    Some(ingredient_id)
+pub fn ingredient_name(ingredient_id: u32, conn: &Connection) -> Option<String> {
+    match conn.query_row("SELECT name FROM ingredients WHERE id = ?1;", [&ingredient_id], |row| row.get(0)) {
+        Ok(name) => Some(name),
+        Err(rusqlite::Error::QueryReturnedNoRows) => {
+            eprintln!("Invalid ingredient id");
+            None
+        },
+        Err(e) => {
+            eprintln!("Error: {e}");
+            None
+        }
+    }
 }
 
-pub fn category_name_and_id(conn: &Connection) -> Option<(String, u32)> {
-    let (category_name, category_id) = loop {
-        let input_category_name = prompt("Category (vegetable, fruit, dairy, meat, condiment, grain)");
-        if input_category_name.is_empty() {
+/// Asks for an existing category until one is found, returns `None` when the answer is blank.
+pub fn category_name_and_id(conn: &Connection, args: &mut Arguments) -> Option<(String, u32)> {
+    loop {
+        let category_name = args.next_or_prompt("Category (vegetable, fruit, dairy, meat, condiment, grain)");
+        if category_name.is_empty() {
             return None;
         }
-    
-        let retrieved_category_id: u32 = match conn.query_row("SELECT id FROM categories WHERE name = ?1;", [&input_category_name], |row| row.get(0)) {
-            Ok(id) => id,
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                eprintln!("Invalid category");
-                continue;
-            },
-            Err(e) => {
-                eprintln!("Error: {e}");
-                continue;
-            }
-        };
-        break (input_category_name, retrieved_category_id);
-    };
 
-    Some((category_name, category_id))
+        match category_id_by_name(&category_name, conn) {
+            Ok(id) => return Some((category_name.trim().to_lowercase(), id)),
+            Err(Error::NotFound(message)) => eprintln!("{message}"),
+            Err(e) => {
+                eprintln!("{e}");
+                return None;
+            },
+        }
+    }
 }
 
 pub fn price(ingredient_id: u32, conn: &Connection) -> Option<f32> {
@@ -100,7 +102,7 @@ pub fn price(ingredient_id: u32, conn: &Connection) -> Option<f32> {
     };
 
     let prices_iter = match price_query.query_map([ingredient_id], |row| {
-        Ok(row.get::<_, f32>(0)?)  
+        row.get::<_, f32>(0)  
     }) {
         Ok(iter) => iter,  
         Err(e) => {
@@ -123,3 +125,30 @@ pub fn price(ingredient_id: u32, conn: &Connection) -> Option<f32> {
     Some(calculate_mean(prices)) 
 }
 
+
+/// Finds the dish regardless of case and surrounding spaces, like names are compared when they are checked; an exact match wins.
+pub fn dish_id_by_name(dish_name: &str, conn: &Connection) -> Result<u32> {
+    match conn.query_row("SELECT id FROM dishes WHERE lower(trim(name)) = lower(trim(?1)) ORDER BY name != ?1, id;", [dish_name], |row| row.get(0)) {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::NotFound(format!("Unknown dish \"{dish_name}\""))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Finds the ingredient like `dish_id_by_name` finds a dish.
+pub fn ingredient_id_by_name(ingredient_name: &str, conn: &Connection) -> Result<u32> {
+    match conn.query_row("SELECT id FROM ingredients WHERE lower(trim(name)) = lower(trim(?1)) ORDER BY name != ?1, id;", [ingredient_name], |row| row.get(0)) {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::NotFound(format!("Unknown ingredient \"{ingredient_name}\""))),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Finds the category like `dish_id_by_name` finds a dish.
+pub fn category_id_by_name(category_name: &str, conn: &Connection) -> Result<u32> {
+    match conn.query_row("SELECT id FROM categories WHERE lower(trim(name)) = lower(trim(?1)) ORDER BY name != ?1, id;", [category_name], |row| row.get(0)) {
+        Ok(id) => Ok(id),
+        Err(rusqlite::Error::QueryReturnedNoRows) => Err(Error::NotFound(format!("Unknown category \"{category_name}\""))),
+        Err(e) => Err(e.into()),
+    }
+}
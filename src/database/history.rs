@@ -0,0 +1,169 @@
+use rusqlite::{Connection, OptionalExtension, Result};
+use serde::Serialize;
+
+use crate::cli_operations::{output::print_records, parser::Arguments};
+use crate::database::{get_connection, Outcome};
+use crate::error::{self, Error};
+
+const DEFAULT_HISTORY_LENGTH: u32 = 20;
+
+#[derive(Debug, Serialize)]
+pub struct HistoryRow {
+    pub id: u32,
+    pub created_at: String,
+    pub user: String,
+    pub command: String,
+    pub changes: u32,
+    pub undone: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangeRow {
+    pub operation: u32,
+    pub statement: String,
+}
+
+/// Name of the person making changes, shown in the history.
+pub fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or("unknown".to_string())
+}
+
+/// Groups the changes logged by the triggers since the last call into one operation that can be undone.
+/// Must run in the same transaction as the changes. Starting a new operation discards the undone ones, so they can no longer be redone.
+pub fn record(command: &str, conn: &Connection) -> Result<()> {
+    let pending: u32 = conn.query_row("SELECT COUNT(*) FROM operation_log WHERE operation_id IS NULL;", [], |row| row.get(0))?;
+    if pending == 0 {
+        return Ok(());
+    }
+
+    conn.execute("DELETE FROM operation_log WHERE operation_id IN (SELECT id FROM operations WHERE undone = 1);", [])?;
+    conn.execute("DELETE FROM operations WHERE undone = 1;", [])?;
+
+    conn.execute("INSERT INTO operations (command, user) VALUES (?1, ?2);", (command, current_user()))?;
+    conn.execute("UPDATE operation_log SET operation_id = ?1 WHERE operation_id IS NULL;", [conn.last_insert_rowid()])?;
+
+    Ok(())
+}
+
+pub fn undo(conn: &Connection) -> Result<Outcome> {
+    let operation = conn
+        .query_row("SELECT id, command FROM operations WHERE undone = 0 ORDER BY id DESC LIMIT 1;", [], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+        })
+        .optional()?;
+
+    let Some((operation_id, command)) = operation else {
+        println!("Nothing to undo");
+        return Ok(Outcome::Rollback);
+    };
+
+    replay(operation_id, "SELECT undo_sql FROM operation_log WHERE operation_id = ?1 ORDER BY id DESC;", conn)?;
+    conn.execute("UPDATE operations SET undone = 1 WHERE id = ?1;", [operation_id])?;
+    println!("Undid #{operation_id} {command}");
+
+    Ok(Outcome::Commit)
+}
+
+pub fn redo(conn: &Connection) -> Result<Outcome> {
+    let operation = conn
+        .query_row("SELECT id, command FROM operations WHERE undone = 1 ORDER BY id ASC LIMIT 1;", [], |row| {
+            Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+        })
+        .optional()?;
+
+    let Some((operation_id, command)) = operation else {
+        println!("Nothing to redo");
+        return Ok(Outcome::Rollback);
+    };
+
+    replay(operation_id, "SELECT redo_sql FROM operation_log WHERE operation_id = ?1 ORDER BY id ASC;", conn)?;
+    conn.execute("UPDATE operations SET undone = 0 WHERE id = ?1;", [operation_id])?;
+    println!("Redid #{operation_id} {command}");
+
+    Ok(Outcome::Commit)
+}
+
+/// Runs the logged statements of an operation without logging them again.
+fn replay(operation_id: u32, select_statements: &str, conn: &Connection) -> Result<()> {
+    let statements: Vec<String> = conn
+        .prepare(select_statements)?
+        .query_map([operation_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<String>>>()?;
+
+    conn.execute("INSERT INTO operation_replay (active) VALUES (1);", [])?;
+    for statement in statements {
+        conn.execute_batch(&statement)?;
+    }
+    conn.execute("DELETE FROM operation_replay;", [])?;
+
+    Ok(())
+}
+
+/// `history` lists the latest operations, `history <number>` shows the changes of one operation.
+pub fn show(args: &mut Arguments) -> error::Result<()> {
+    let conn = get_connection()?;
+
+    let count = match args.next_value() {
+        Some(value) => match value.parse::<u32>() {
+            Ok(operation_id) => return show_operation(operation_id, &conn),
+            Err(_) => return Err(Error::Validation(format!("Invalid operation number \"{value}\""))),
+        },
+        None => DEFAULT_HISTORY_LENGTH,
+    };
+
+    let mut select_operations_stmt = conn.prepare(
+        "SELECT operations.id, datetime(operations.created_at, 'localtime'), operations.user, operations.command, COUNT(operation_log.id), operations.undone
+        FROM operations
+        LEFT JOIN operation_log ON operation_log.operation_id = operations.id
+        GROUP BY operations.id
+        ORDER BY operations.id DESC
+        LIMIT ?1;"
+    )?;
+    let operations = select_operations_stmt
+        .query_map([count], |row| {
+            Ok(HistoryRow {
+                id: row.get(0)?,
+                created_at: row.get(1)?,
+                user: row.get(2)?,
+                command: row.get(3)?,
+                changes: row.get(4)?,
+                undone: row.get(5)?,
+            })
+        })?
+        .collect::<Result<Vec<HistoryRow>>>()?;
+
+    if operations.is_empty() {
+        println!("No changes recorded yet");
+        return Ok(());
+    }
+
+    print_records(&operations, &["#", "When", "Who", "What", "Changes", ""], |operation| {
+        vec![
+            operation.id.to_string(),
+            operation.created_at.clone(),
+            operation.user.clone(),
+            operation.command.clone(),
+            operation.changes.to_string(),
+            if operation.undone { "undone".to_string() } else { String::new() },
+        ]
+    });
+
+    Ok(())
+}
+
+fn show_operation(operation_id: u32, conn: &Connection) -> error::Result<()> {
+    let changes = conn
+        .prepare("SELECT redo_sql FROM operation_log WHERE operation_id = ?1 ORDER BY id;")?
+        .query_map([operation_id], |row| Ok(ChangeRow { operation: operation_id, statement: row.get(0)? }))?
+        .collect::<Result<Vec<ChangeRow>>>()?;
+
+    if changes.is_empty() {
+        return Err(Error::NotFound(format!("No operation #{operation_id}")));
+    }
+
+    print_records(&changes, &["Change"], |change| vec![change.statement.clone()]);
+
+    Ok(())
+}
@@ -1,227 +1,144 @@
-use rusqlite::Result;
-use crate::{cli_operations::{cancel_prompt, user_input::prompt}, database::{cloud::{fetch, has_internet_access, sync, Database}, get_connection}};
+use rusqlite::Connection;
+use crate::{cli_operations::{cancel_prompt, parser::Arguments, user_input::prompt}, database::{get, validate, Outcome}, error::Result};
+use crate::ingredient_line::{self, closest_ingredient};
 
-pub async fn ingredient() -> Result<()> {
-    if !has_internet_access().await {
-        return Ok(());
-    }
-
-    match fetch(Database::Main).await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
+pub fn ingredient(args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
+    let ingredient_name = loop {
+        let input_ingredient_name = args.next_or_prompt("Name");
+        if input_ingredient_name.is_empty() {
+            cancel_prompt();
+            return Ok(Outcome::Rollback);
+        }
 
-    let conn = get_connection();
+        match validate::ingredient_name(&input_ingredient_name, None, conn) {
+            Ok(name) => break name,
+            Err(e) => eprintln!("{e}"),
+        }
+    };
 
-    let ingredient_name = prompt("Name");
-    if ingredient_name.is_empty() {
+    let Some((category_name, category_id)) = get::category_name_and_id(conn, args) else {
         cancel_prompt();
-        return Ok(());
-    }
+        return Ok(Outcome::Rollback);
+    };
 
-    let (category_name, category_id) = loop {
-        let input_category_name = prompt("Category (vegetable, fruit, dairy, meat, condiment, grain)");
-        if input_category_name.is_empty() {
-            cancel_prompt();
-            return Ok(());
+    let lifespan = loop {
+        let input_lifespan = args.next_or_prompt("Lifespan (in _y_mo_d_h_m_s)");
+        match validate::lifespan(&input_lifespan) {
+            Ok(lifespan) => break lifespan,
+            Err(e) => eprintln!("{e}"),
         }
-
-        let retrieved_category_id: u32 = match conn.query_row("SELECT id FROM categories WHERE name = ?1;", [&input_category_name], |row| row.get(0)) {
-            Ok(id) => id,
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                eprintln!("Invalid category");
-                continue;
-            },
-            Err(e) => {
-                eprintln!("Error: {e}");
-                continue;
-            }
-        };
-        break (input_category_name, retrieved_category_id);
     };
 
-    let lifespan = prompt("Lifespan (in _y_mo_d_h_m_s)");
-
     let mut stmt = conn.prepare("INSERT INTO ingredients (category_id, name, lifespan) VALUES (?1, ?2, ?3);")?;
     stmt.execute((category_id, &ingredient_name, &lifespan))?;
-    println!("Inserted: {} {} {} successfully", ingredient_name, category_name, lifespan);
+    println!("Inserted: {} {} {} successfully", ingredient_name, category_name, lifespan.unwrap_or_default());
 
-    match sync().await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
-
-    Ok(())
+    Ok(Outcome::Commit)
 }
 
-pub async fn price() -> Result<()> {
-    if !has_internet_access().await {
-        return Ok(());
-    }
-
-    match fetch(Database::Main).await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
-
-    let conn = get_connection();
+pub fn price(args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
+    let Some((ingredient_name, ingredient_id)) = get::ingredient_name_and_id(conn, args) else {
+        cancel_prompt();
+        return Ok(Outcome::Rollback);
+    };
 
-    let (ingredient_name, ingredient_id) = loop {
-        let input_ingredient_name = prompt("Ingredient name");
-        if input_ingredient_name.is_empty() {
+    let input_price_float = loop {
+        let input_price = args.next_or_prompt("Price per kg in AUD");
+        if input_price.is_empty() {
             cancel_prompt();
-            return Ok(());
+            return Ok(Outcome::Rollback);
         }
 
-        let retrieved_ingredient_id: u32 = match conn.query_row("SELECT id FROM ingredients WHERE name = ?1;", [&input_ingredient_name], |row| row.get(0)) {
-            Ok(id) => id,
-            Err(rusqlite::Error::QueryReturnedNoRows) => {
-                eprintln!("Invalid category");
-                continue;
-            },
-            Err(e) => {
-                eprintln!("Error: {e}");
-                continue;
-            }
-        };
-        break (input_ingredient_name, retrieved_ingredient_id);
-    };
-
-    let input_price = prompt("Price per kg in AUD");
-    let input_price_float = match input_price.trim().parse::<f32>() {
-        Ok(f) => f,
-        Err(e) => {
-            eprint!("{e}");
-            return Ok(());
-        },
+        match validate::price_text(&input_price) {
+            Ok(price) => break price,
+            Err(e) => eprintln!("{e}"),
+        }
     };
 
     let mut stmt = conn.prepare("INSERT INTO prices (ingredient_id, price) VALUES (?1, ?2);")?;
-    stmt.execute((&ingredient_id, input_price))?;
+    stmt.execute((&ingredient_id, validate::stored_price(input_price_float)))?;
     println!("Inserted: ${:.2} to {} successfully", input_price_float, ingredient_name);
 
-    match sync().await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
-
-    Ok(())
+    Ok(Outcome::Commit)
 }
 
-pub async fn dish() -> Result<()> {
-    if !has_internet_access().await {
-        return Ok(());
-    }
-
-    match fetch(Database::Main).await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
-
-    let conn = get_connection();
-
-    let dish_name = prompt("Dish name");
+pub fn dish(args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
+    let dish_name = loop {
+        let input_dish_name = args.next_or_prompt("Dish name");
+        if input_dish_name.is_empty() {
+            cancel_prompt();
+            return Ok(Outcome::Rollback);
+        }
 
-    if dish_name.is_empty() {
-        cancel_prompt();
-        return Ok(());
-    }
+        match validate::dish_name(&input_dish_name, None, conn) {
+            Ok(name) => break name,
+            Err(e) => eprintln!("{e}"),
+        }
+    };
 
     let mut stmt = conn.prepare("INSERT INTO dishes (name) VALUES (?1);")?;
     
     stmt.execute([&dish_name])?;
 
-    println!("Inserted {dish_name} successfully. Do you want to add recipe now?");
-
-    if prompt("[Y/N]") == "y" {
-        match recipe(Some(dish_name)).await {
-            Ok(_) => {},
-            Err(e) => eprintln!("{e}"),
-        }
+    // Recipe lines typed inline after the dish name are added straight away
+    // The dish and its recipe are written together, so canceling the recipe discards the dish as well
+    if !args.is_empty() {
+        println!("Inserted {dish_name} successfully");
+        return recipe(Some(dish_name), args, conn);
     }
 
-    match sync().await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
+    println!("Inserted {dish_name} successfully. Do you want to add recipe now?");
+    if prompt("[Y/N]") == "y" {
+        return recipe(Some(dish_name), args, conn);
     }
 
-    Ok(())
+    Ok(Outcome::Commit)
 }
 
-pub async fn recipe(dish_name: Option<String>) -> Result<()> {
-    let chained_operation: bool;
-
-    let conn = get_connection();
-
+pub fn recipe(dish_name: Option<String>, args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
     let (dish_name, dish_id) = match dish_name {
         Some(dish_name) => {
-            chained_operation = true;
             let retrieved_dish_id: u32 = conn.query_row("SELECT id FROM dishes WHERE name = ?1;", [&dish_name], |row| row.get(0))?;
 
             (dish_name, retrieved_dish_id)
         },
-        None => {
-            chained_operation = false;
-            if !has_internet_access().await {
-                return Ok(());
-            }
-        
-            match fetch(Database::Main).await {
-                Ok(_) => {},
-                Err(e) => {
-                    eprintln!("{e}");
-                    return Ok(());
-                },
+        None => loop {
+            let input_dish_name = args.next_or_prompt("Dish name");
+            if input_dish_name.is_empty() {
+                cancel_prompt();
+                return Ok(Outcome::Rollback);
             }
 
-            let (dish_name, dish_id) = loop {
-                let input_dish_name = prompt("Dish name");
-                if input_dish_name.is_empty() {
-                    cancel_prompt();
-                    return Ok(());
+            let retrieved_dish_id: u32 = match conn.query_row("SELECT id FROM dishes WHERE name = ?1;", [&input_dish_name], |row| row.get(0)) {
+                Ok(id) => id,
+                Err(rusqlite::Error::QueryReturnedNoRows) => {
+                    eprintln!("Invalid dish");
+                    continue;
+                },
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    continue;
                 }
-        
-                let retrieved_dish_id: u32 = match conn.query_row("SELECT id FROM dishes WHERE name = ?1;", [&input_dish_name], |row| row.get(0)) {
-                    Ok(id) => id,
-                    Err(rusqlite::Error::QueryReturnedNoRows) => {
-                        eprintln!("Invalid dish");
-                        continue;
-                    },
-                    Err(e) => {
-                        eprintln!("Error: {e}");
-                        continue;
-                    }
-                };
-                break (input_dish_name, retrieved_dish_id);
             };
-
-            (dish_name, dish_id)
+            break (input_dish_name, retrieved_dish_id);
         },
     };
 
     let mut ingredients_added_vec: Vec<String> = Vec::new();
 
+    // When the lines were given inline, stop once they run out instead of prompting for more
+    let inline_lines = !args.is_empty();
+    if !inline_lines {
+        println!("Enter ingredients one by one, or paste lines such as \"2 tbsp soy sauce\" followed by an empty line");
+    }
+
     'outer: loop {
+        if inline_lines && args.is_empty() {
+            break 'outer;
+        }
+
         let (ingredient_name, ingredient_id) = 'name_and_id: loop {
-            let input_ingredient_name = prompt("Ingredient name");
+            let input_ingredient_name = args.next_or_prompt("Ingredient name");
             if input_ingredient_name.is_empty() {
                 break 'outer;
             }
@@ -229,7 +146,14 @@ pub async fn recipe(dish_name: Option<String>) -> Result<()> {
             let retrieved_ingredient_id: u32 = match conn.query_row("SELECT id FROM ingredients WHERE name = ?1;", [&input_ingredient_name], |row| row.get(0)) {
                 Ok(id) => id,
                 Err(rusqlite::Error::QueryReturnedNoRows) => {
-                    eprintln!("Invalid dish");
+                    // A line with a quantity starts a pasted block of ingredient lines
+                    if !inline_lines && ingredient_line::parse(&input_ingredient_name).quantity.is_some() {
+                        let mut pasted_lines = vec![input_ingredient_name];
+                        pasted_lines.extend(read_pasted_lines());
+                        ingredients_added_vec.extend(insert_pasted_lines(&pasted_lines, dish_id, conn)?);
+                        break 'outer;
+                    }
+                    eprintln!("Invalid ingredient");
                     continue;
                 },
                 Err(e) => {
@@ -241,24 +165,25 @@ pub async fn recipe(dish_name: Option<String>) -> Result<()> {
         };
 
         let quantity = 'quantity: loop {
-            let user_input = prompt("Quantity (g)");
+            let user_input = args.next_or_prompt("Quantity (g)");
 
             if user_input.is_empty() {
                 cancel_prompt();
-                return Ok(());
+                return Ok(Outcome::Rollback);
             }
 
-            match user_input.parse::<u32>() {
+            match validate::quantity_text(&user_input) {
                 Ok(num) => break num,
-                Err(_) => {
-                    eprintln!("Invalid quantity");
+                Err(e) => {
+                    eprintln!("{e}");
                     continue 'quantity;
                 }
             }
         };
 
-        let mut stmt = conn.prepare("INSERT INTO recipes (dish_id, ingredient_id, quantity) VALUES (?1, ?2, ?3);")?;
-        stmt.execute((&dish_id, &ingredient_id, &quantity))?;
+        if recipe_line(dish_id, ingredient_id, quantity, None, conn)? {
+            println!("{ingredient_name} was already in the recipe, added {quantity} g to it");
+        }
 
         ingredients_added_vec.push(ingredient_name);
     }
@@ -269,15 +194,156 @@ pub async fn recipe(dish_name: Option<String>) -> Result<()> {
         println!("Inserted: {ingredient_added_string} into {dish_name}'s recipe");
     }
     
-    if !chained_operation {
-        match sync().await {
-            Ok(_) => {},
-            Err(e) => {
-                eprintln!("{e}");
-                return Ok(());
+    Ok(Outcome::Commit)
+}
+
+fn read_pasted_lines() -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    loop {
+        let line = prompt("...");
+        if line.is_empty() {
+            break lines;
+        }
+        lines.push(line);
+    }
+}
+
+/// Confirms each parsed line against the existing ingredients before adding it to the recipe.
+/// Returns the names of the ingredients added.
+fn insert_pasted_lines(lines: &[String], dish_id: u32, conn: &Connection) -> Result<Vec<String>> {
+    let known_names: Vec<String> = conn
+        .prepare("SELECT name FROM ingredients;")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    let mut added: Vec<String> = Vec::new();
+
+    for line in lines {
+        let parsed = ingredient_line::parse(line);
+        let grams = parsed.grams();
+        let quantity_text = grams.map(|g| format!("{g} g")).unwrap_or("? g".to_string());
+        let note_text = parsed.note.as_ref().map(|note| format!(" ({note})")).unwrap_or_default();
+
+        let matched_name = match closest_ingredient(&parsed.name, &known_names) {
+            Some(name) => {
+                println!("\"{line}\" -> {name}, {quantity_text}{note_text}");
+                if prompt("[Y/N]") == "y" {
+                    Some(name.clone())
+                } else {
+                    None
+                }
+            },
+            None => {
+                println!("No ingredient matches \"{line}\"");
+                None
+            },
+        };
+
+        let (ingredient_name, ingredient_id) = match matched_name {
+            Some(name) => {
+                let id = conn.query_row("SELECT id FROM ingredients WHERE name = ?1;", [&name], |row| row.get::<_, u32>(0))?;
+                (name, id)
+            },
+            None => match get::ingredient_id(conn, &mut Arguments::empty()) {
+                Some(id) => match get::ingredient_name(id, conn) {
+                    Some(name) => (name, id),
+                    None => continue,
+                },
+                None => {
+                    println!("Skipped \"{line}\"");
+                    continue;
+                },
+            },
+        };
+
+        let quantity = match grams {
+            Some(grams) => grams,
+            None => loop {
+                let user_input = prompt(&format!("Quantity (g) of {ingredient_name}"));
+                if user_input.is_empty() {
+                    break 0;
+                }
+                match validate::quantity_text(&user_input) {
+                    Ok(num) => break num,
+                    Err(e) => eprintln!("{e}"),
+                }
             },
+        };
+        if quantity == 0 {
+            println!("Skipped \"{line}\"");
+            continue;
+        }
+
+        if recipe_line(dish_id, ingredient_id, quantity, parsed.note.as_deref(), conn)? {
+            println!("{ingredient_name} was already in the recipe, added {quantity} g to it");
         }
+
+        added.push(ingredient_name);
+    }
+
+    Ok(added)
+}
+
+pub fn ingredient_row(ingredient_name: &str, category_name: &str, lifespan: &str, conn: &Connection) -> Result<()> {
+    let ingredient_name = validate::ingredient_name(ingredient_name, None, conn)?;
+    let lifespan = validate::lifespan(lifespan)?;
+    let category_id = get::category_id_by_name(category_name, conn)?;
+
+    conn.execute("INSERT INTO ingredients (category_id, name, lifespan) VALUES (?1, ?2, ?3);", (category_id, ingredient_name, lifespan))?;
+
+    Ok(())
+}
+
+pub fn price_row(ingredient_name: &str, price: f32, conn: &Connection) -> Result<()> {
+    let ingredient_id = get::ingredient_id_by_name(ingredient_name, conn)?;
+
+    let price = validate::price_value(price)?;
+
+    conn.execute("INSERT INTO prices (ingredient_id, price) VALUES (?1, ?2);", (ingredient_id, validate::stored_price(price)))?;
+
+    Ok(())
+}
+
+pub fn dish_row(dish_name: &str, conn: &Connection) -> Result<()> {
+    let dish_name = validate::dish_name(dish_name, None, conn)?;
+
+    conn.execute("INSERT INTO dishes (name) VALUES (?1);", [dish_name])?;
+
+    Ok(())
+}
+
+pub fn recipe_row(dish_name: &str, ingredient_name: &str, quantity: u32, note: Option<&str>, conn: &Connection) -> Result<()> {
+    let dish_id = get::dish_id_by_name(dish_name, conn)?;
+    let ingredient_id = get::ingredient_id_by_name(ingredient_name, conn)?;
+
+    let quantity = validate::quantity_value(quantity)?;
+
+    recipe_line(dish_id, ingredient_id, quantity, note, conn)?;
+
+    Ok(())
+}
+
+/// Adds a line to the recipe, or adds the quantity to the existing line when the dish already uses the ingredient.
+/// Returns true when the line was merged into an existing one.
+pub fn recipe_line(dish_id: u32, ingredient_id: u32, quantity: u32, note: Option<&str>, conn: &Connection) -> Result<bool> {
+    let merged = conn.execute(
+        "UPDATE recipes SET quantity = COALESCE(quantity, 0) + ?3, note = COALESCE(note, ?4)
+        WHERE id = (SELECT MIN(id) FROM recipes WHERE dish_id = ?1 AND ingredient_id = ?2);",
+        (dish_id, ingredient_id, quantity, note),
+    )?;
+    if merged > 0 {
+        return Ok(true);
     }
 
+    conn.execute("INSERT INTO recipes (dish_id, ingredient_id, quantity, note) VALUES (?1, ?2, ?3, ?4);", (dish_id, ingredient_id, quantity, note))?;
+
+    Ok(false)
+}
+
+pub fn step_row(dish_name: &str, position: u32, text: &str, conn: &Connection) -> Result<()> {
+    let dish_id = get::dish_id_by_name(dish_name, conn)?;
+
+    conn.execute("INSERT INTO recipe_steps (dish_id, position, text) VALUES (?1, ?2, ?3);", (dish_id, position, text))?;
+
     Ok(())
-}
\ No newline at end of file
+}
@@ -4,26 +4,96 @@ pub mod show;
 pub mod cloud;
 pub mod modify;
 pub mod get;
+pub mod csv_io;
+pub mod web_recipe;
+pub mod schema;
+pub mod history;
+pub mod doctor;
+pub mod validate;
+pub mod backups;
+pub mod diff;
+pub mod encryption;
+pub mod snapshots;
 
-use rusqlite::Connection;
+use rusqlite::{Connection, Transaction};
 use std::fs::metadata;
-use std::path::Path;
 
-pub fn get_connection() -> Connection {
-    let path: String = "database.db".to_string();
-    let conn = Connection::open(path)
-        .expect("Error connecting to database");
+use crate::config;
+use crate::error::{Error, Result};
+use cloud::{fetch_before_change, request_sync};
 
-    conn
+/// How a command that changes the database ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    /// Everything the command wrote is kept.
+    Commit,
+    /// The command was canceled, nothing it wrote is kept.
+    Rollback,
 }
 
-pub fn first_start() -> bool {
-    let path = Path::new("database.db");
-    
+/// Opens the database of the active profile, upgraded to the current schema.
+pub fn get_connection() -> Result<Connection> {
+    let path = config::active_profile().db;
+
+    let mut conn = Connection::open(path)?;
+    schema::migrate(&mut conn)?;
+    // Rows may only reference existing rows; deleting a referenced row fails unless its references are handled first
+    conn.pragma_update(None, "foreign_keys", true)?;
+
+    Ok(conn)
+}
+
+/// Whether the database has not been fetched yet: the file is missing or empty.
+pub fn first_start() -> Result<bool> {
+    let path = config::active_profile().db;
+
     if path.exists() && path.is_file() {
-        let file_metadata = metadata(path).expect("Error checking file");
-        return !(file_metadata.len() > 0);
+        let file_metadata = metadata(&path).map_err(|e| Error::from(e).in_file(&path.to_string_lossy()))?;
+        Ok(file_metadata.len() == 0)
     } else {
-        return true;
+        Ok(true)
     }
 }
+
+/// Fetches the latest database and runs `command` in a single transaction, recorded in the history as `command_name`.
+/// The changes are synced only once they are committed, batched with the following changes in the REPL; an error or a rollback leaves the database untouched.
+pub async fn write_transaction(command_name: &str, command: impl FnOnce(&Connection) -> Result<Outcome>) -> Result<()> {
+    transaction(command_name, false, command).await
+}
+
+/// Same as `write_transaction` for commands that delete rows: the fetched database is snapshotted before the command runs.
+pub async fn delete_transaction(command_name: &str, command: impl FnOnce(&Connection) -> Result<Outcome>) -> Result<()> {
+    transaction(command_name, true, command).await
+}
+
+async fn transaction(command_name: &str, snapshot: bool, command: impl FnOnce(&Connection) -> Result<Outcome>) -> Result<()> {
+    fetch_before_change("change the database").await?;
+    if snapshot {
+        snapshots::take(command_name)?;
+    }
+
+    let mut conn = get_connection()?;
+    commit_change(&mut conn, command_name, |tx| command(tx)).await?;
+
+    Ok(())
+}
+
+/// Runs `change` in a single transaction and records it in the history as `command_name`; a sync is requested only once it is committed.
+/// An error or `Outcome::Rollback` drops the transaction, which rolls back everything the change wrote.
+pub async fn commit_change(conn: &mut Connection, command_name: &str, change: impl FnOnce(&mut Transaction) -> Result<Outcome>) -> Result<Outcome> {
+    // The transaction borrows the connection, so it ends before the sync is awaited
+    {
+        let mut tx = conn.transaction()?;
+        match change(&mut tx)? {
+            Outcome::Commit => {
+                history::record(command_name, &tx)?;
+                tx.commit()?;
+            },
+            Outcome::Rollback => return Ok(Outcome::Rollback),
+        }
+    }
+
+    request_sync().await?;
+
+    Ok(Outcome::Commit)
+}
@@ -1,118 +1,139 @@
-use rusqlite::Result;
+use rusqlite::Connection;
 
-use crate::{cli_operations::{cancel_prompt, user_input::prompt}, database::cloud::sync};
+use crate::{cli_operations::{cancel_prompt, parser::Arguments}, database::{validate, Outcome}, error::{Error, Result}};
 
-use super::{cloud::{fetch, has_internet_access, Database}, get, get_connection, show};
+use super::{get, show};
 
-pub async fn ingredient() -> Result<()> {
-    if !has_internet_access().await {
-        return Ok(());
-    }
-    
-    match fetch(Database::Main).await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
+pub fn ingredient(args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
 
-    let conn = get_connection();
-
-    let ingredient_id = match get::ingredient_id(&conn) {
+    let ingredient_id = match get::ingredient_id(conn, args) {
         Some(id) => id,
-        None => return Ok(()),
+        None => return Ok(Outcome::Rollback),
     };
 
-    let new_name = prompt("New name");
+    let new_name = loop {
+        let input_name = args.next_or_prompt("New name");
+        if input_name.is_empty() {
+            break input_name;
+        }
+
+        match validate::ingredient_name(&input_name, Some(ingredient_id), conn) {
+            Ok(name) => break name,
+            Err(e) => eprintln!("{e}"),
+        }
+    };
 
     if !new_name.is_empty() {
         let mut update_name_stmt = conn.prepare("UPDATE ingredients SET name = ?1 WHERE id = ?2")?;
         update_name_stmt.execute((&new_name, &ingredient_id))?;
     }
 
-    let new_lifespan = prompt("New lifespan");
+    let new_lifespan = loop {
+        let input_lifespan = args.next_or_prompt("New lifespan");
+        match validate::lifespan(&input_lifespan) {
+            Ok(lifespan) => break lifespan,
+            Err(e) => eprintln!("{e}"),
+        }
+    };
 
-    if !new_lifespan.is_empty() {
-        let mut update_lifespan_stmt = conn.prepare("UPDATE ingredients SET name = ?1 WHERE id = ?2")?;
+    if let Some(new_lifespan) = new_lifespan {
+        let mut update_lifespan_stmt = conn.prepare("UPDATE ingredients SET lifespan = ?1 WHERE id = ?2")?;
         update_lifespan_stmt.execute((&new_lifespan, &ingredient_id))?;
     }
 
-    match get::category_name_and_id(&conn) {
-        Some((_, category_id)) => {
-            let mut update_category_stmt = conn.prepare("UPDATE ingredients SET category_id = ?1 WHERE id = ?2")?;
-            update_category_stmt.execute((category_id, &ingredient_id))?;
-        }
-        None => {},
+    if let Some((_, category_id)) = get::category_name_and_id(conn, args) {
+        let mut update_category_stmt = conn.prepare("UPDATE ingredients SET category_id = ?1 WHERE id = ?2")?;
+        update_category_stmt.execute((category_id, &ingredient_id))?;
     }
 
     println!("Ingredient Updated");
-    match show::specific_ingredient(ingredient_id) {
+    match show::specific_ingredient(ingredient_id, conn) {
         Ok(_) => {},
         Err(e) => eprintln!("Error: {e}"),
     }
 
-    match sync().await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
-
-    Ok(())
+    Ok(Outcome::Commit)
 }
 
-pub async fn dish_name() -> Result<()> {
-    if !has_internet_access().await {
-        return Ok(());
-    }
-    
-    match fetch(Database::Main).await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    }
+pub fn dish_name(args: &mut Arguments, conn: &Connection) -> Result<Outcome> {
 
-    let conn = get_connection();
-
-    let dish_id = match get::dish_id(&conn) {
+    let dish_id = match get::dish_id(conn, args) {
         Some(id) => id,
-        None => return Ok(()),
+        None => return Ok(Outcome::Rollback),
     };
 
-    let old_name = match get::dish_name(dish_id, &conn) {
+    let old_name = match get::dish_name(dish_id, conn) {
         Some(name) => name,
-        None => return Ok(()),
+        None => return Ok(Outcome::Rollback),
     };
 
-    let new_name = prompt("New dish name");
-    if new_name.is_empty() {
-        cancel_prompt();
-        return Ok(())
-    }
+    let new_name = loop {
+        let input_name = args.next_or_prompt("New dish name");
+        if input_name.is_empty() {
+            cancel_prompt();
+            return Ok(Outcome::Rollback)
+        }
+
+        match validate::dish_name(&input_name, Some(dish_id), conn) {
+            Ok(name) => break name,
+            Err(e) => eprintln!("{e}"),
+        }
+    };
 
     let mut update_name_stmt = conn.prepare("UPDATE dishes SET name = ?1 WHERE id = ?2")?;
     update_name_stmt.execute((&new_name, dish_id))?;
 
-    let retrieved_new_name = match get::dish_name(dish_id, &conn) {
+    let retrieved_new_name = match get::dish_name(dish_id, conn) {
         Some(name) => name,
         None => {
-            return Ok(())
+            return Ok(Outcome::Rollback)
         }
     };
 
     println!("\"{old_name}\" has been updated to \"{retrieved_new_name}\"");
 
-    match sync().await {
-        Ok(_) => {},
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
+    Ok(Outcome::Commit)
+}
+
+/// Blank values leave the corresponding column unchanged.
+pub fn ingredient_row(ingredient_name: &str, new_name: &str, new_lifespan: &str, category_name: &str, conn: &Connection) -> Result<()> {
+    let ingredient_id = get::ingredient_id_by_name(ingredient_name, conn)?;
+
+    if !new_name.is_empty() {
+        let new_name = validate::ingredient_name(new_name, Some(ingredient_id), conn)?;
+        conn.execute("UPDATE ingredients SET name = ?1 WHERE id = ?2", (new_name, ingredient_id))?;
+    }
+
+    if let Some(new_lifespan) = validate::lifespan(new_lifespan)? {
+        conn.execute("UPDATE ingredients SET lifespan = ?1 WHERE id = ?2", (new_lifespan, ingredient_id))?;
+    }
+
+    if !category_name.is_empty() {
+        let category_id = get::category_id_by_name(category_name, conn)?;
+        conn.execute("UPDATE ingredients SET category_id = ?1 WHERE id = ?2", (category_id, ingredient_id))?;
+    }
+
+    Ok(())
+}
+
+pub fn dish_name_row(dish_name: &str, new_name: &str, conn: &Connection) -> Result<()> {
+    let dish_id = get::dish_id_by_name(dish_name, conn)?;
+    let new_name = validate::dish_name(new_name, Some(dish_id), conn)?;
+
+    conn.execute("UPDATE dishes SET name = ?1 WHERE id = ?2", (new_name, dish_id))?;
+
+    Ok(())
+}
+
+pub fn recipe_quantity_row(dish_name: &str, ingredient_name: &str, quantity: u32, conn: &Connection) -> Result<()> {
+    let dish_id = get::dish_id_by_name(dish_name, conn)?;
+    let ingredient_id = get::ingredient_id_by_name(ingredient_name, conn)?;
+    let quantity = validate::quantity_value(quantity)?;
+
+    let updated = conn.execute("UPDATE recipes SET quantity = ?1 WHERE dish_id = ?2 AND ingredient_id = ?3", (quantity, dish_id, ingredient_id))?;
+    if updated == 0 {
+        return Err(Error::NotFound(format!("\"{ingredient_name}\" is not in the recipe of \"{dish_name}\"")));
     }
 
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,204 @@
+use rusqlite::{Connection, Result};
+
+/// Each entry upgrades the database by one version, tracked in `PRAGMA user_version`.
+/// Entries must never be edited once released, only appended.
+const MIGRATIONS: &[&str] = &[
+    // 1: tables of the original database, so that a new database can be created locally
+    "CREATE TABLE IF NOT EXISTS dishes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE IF NOT EXISTS categories (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE IF NOT EXISTS ingredients (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        category_id INTEGER,
+        name TEXT NOT NULL UNIQUE,
+        lifespan CHAR(20),
+        FOREIGN KEY (category_id) REFERENCES categories(id)
+    );
+    CREATE TABLE IF NOT EXISTS prices (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        ingredient_id INTEGER,
+        price DECIMAL(10, 2),
+        FOREIGN KEY (ingredient_id) REFERENCES ingredients(id)
+    );
+    CREATE TABLE IF NOT EXISTS recipes (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        dish_id INTEGER NOT NULL,
+        ingredient_id INTEGER NOT NULL,
+        quantity INTEGER,
+        FOREIGN KEY (dish_id) REFERENCES dishes(id),
+        FOREIGN KEY (ingredient_id) REFERENCES ingredients(id)
+    );
+    CREATE INDEX IF NOT EXISTS idx_dishes_name ON dishes(name);
+    CREATE INDEX IF NOT EXISTS idx_ingredients_name ON ingredients(name);
+    CREATE INDEX IF NOT EXISTS idx_prices_ingredient ON prices(ingredient_id);
+    CREATE INDEX IF NOT EXISTS idx_recipes_dish ON recipes(dish_id);
+    CREATE INDEX IF NOT EXISTS idx_recipes_ingredient ON recipes(ingredient_id);
+    INSERT OR IGNORE INTO categories (name) VALUES ('vegetable'), ('fruit'), ('dairy'), ('meat'), ('condiment'), ('grain');",
+    // 2: preparation note of a recipe line, such as "rinsed"
+    "ALTER TABLE recipes ADD COLUMN note TEXT;",
+    // 3: cooking steps of a dish, in order
+    "CREATE TABLE recipe_steps (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        dish_id INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        text TEXT NOT NULL,
+        FOREIGN KEY (dish_id) REFERENCES dishes(id)
+    );
+    CREATE INDEX idx_recipe_steps_dish ON recipe_steps(dish_id);",
+    // 4: operation log for undo and redo; triggers record the inverse of every change
+    "CREATE TABLE operations (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        command TEXT NOT NULL,
+        user TEXT NOT NULL,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        undone INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE operation_log (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        operation_id INTEGER,
+        undo_sql TEXT NOT NULL,
+        redo_sql TEXT NOT NULL,
+        FOREIGN KEY (operation_id) REFERENCES operations(id)
+    );
+    CREATE INDEX idx_operation_log_operation ON operation_log(operation_id);
+    CREATE TABLE operation_replay (active INTEGER NOT NULL);
+    CREATE TRIGGER log_dishes_insert AFTER INSERT ON dishes
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'DELETE FROM dishes WHERE id = ' || NEW.id,
+            'INSERT INTO dishes (id, name) VALUES (' || quote(NEW.id) || ', ' || quote(NEW.name) || ')'
+        );
+    END;
+    CREATE TRIGGER log_dishes_delete AFTER DELETE ON dishes
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'INSERT INTO dishes (id, name) VALUES (' || quote(OLD.id) || ', ' || quote(OLD.name) || ')',
+            'DELETE FROM dishes WHERE id = ' || OLD.id
+        );
+    END;
+    CREATE TRIGGER log_dishes_update AFTER UPDATE ON dishes
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'UPDATE dishes SET name = ' || quote(OLD.name) || ' WHERE id = ' || OLD.id,
+            'UPDATE dishes SET name = ' || quote(NEW.name) || ' WHERE id = ' || NEW.id
+        );
+    END;
+    CREATE TRIGGER log_ingredients_insert AFTER INSERT ON ingredients
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'DELETE FROM ingredients WHERE id = ' || NEW.id,
+            'INSERT INTO ingredients (id, category_id, name, lifespan) VALUES (' || quote(NEW.id) || ', ' || quote(NEW.category_id) || ', ' || quote(NEW.name) || ', ' || quote(NEW.lifespan) || ')'
+        );
+    END;
+    CREATE TRIGGER log_ingredients_delete AFTER DELETE ON ingredients
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'INSERT INTO ingredients (id, category_id, name, lifespan) VALUES (' || quote(OLD.id) || ', ' || quote(OLD.category_id) || ', ' || quote(OLD.name) || ', ' || quote(OLD.lifespan) || ')',
+            'DELETE FROM ingredients WHERE id = ' || OLD.id
+        );
+    END;
+    CREATE TRIGGER log_ingredients_update AFTER UPDATE ON ingredients
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'UPDATE ingredients SET category_id = ' || quote(OLD.category_id) || ', name = ' || quote(OLD.name) || ', lifespan = ' || quote(OLD.lifespan) || ' WHERE id = ' || OLD.id,
+            'UPDATE ingredients SET category_id = ' || quote(NEW.category_id) || ', name = ' || quote(NEW.name) || ', lifespan = ' || quote(NEW.lifespan) || ' WHERE id = ' || NEW.id
+        );
+    END;
+    CREATE TRIGGER log_prices_insert AFTER INSERT ON prices
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'DELETE FROM prices WHERE id = ' || NEW.id,
+            'INSERT INTO prices (id, ingredient_id, price) VALUES (' || quote(NEW.id) || ', ' || quote(NEW.ingredient_id) || ', ' || quote(NEW.price) || ')'
+        );
+    END;
+    CREATE TRIGGER log_prices_delete AFTER DELETE ON prices
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'INSERT INTO prices (id, ingredient_id, price) VALUES (' || quote(OLD.id) || ', ' || quote(OLD.ingredient_id) || ', ' || quote(OLD.price) || ')',
+            'DELETE FROM prices WHERE id = ' || OLD.id
+        );
+    END;
+    CREATE TRIGGER log_prices_update AFTER UPDATE ON prices
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'UPDATE prices SET ingredient_id = ' || quote(OLD.ingredient_id) || ', price = ' || quote(OLD.price) || ' WHERE id = ' || OLD.id,
+            'UPDATE prices SET ingredient_id = ' || quote(NEW.ingredient_id) || ', price = ' || quote(NEW.price) || ' WHERE id = ' || NEW.id
+        );
+    END;
+    CREATE TRIGGER log_recipes_insert AFTER INSERT ON recipes
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'DELETE FROM recipes WHERE id = ' || NEW.id,
+            'INSERT INTO recipes (id, dish_id, ingredient_id, quantity, note) VALUES (' || quote(NEW.id) || ', ' || quote(NEW.dish_id) || ', ' || quote(NEW.ingredient_id) || ', ' || quote(NEW.quantity) || ', ' || quote(NEW.note) || ')'
+        );
+    END;
+    CREATE TRIGGER log_recipes_delete AFTER DELETE ON recipes
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'INSERT INTO recipes (id, dish_id, ingredient_id, quantity, note) VALUES (' || quote(OLD.id) || ', ' || quote(OLD.dish_id) || ', ' || quote(OLD.ingredient_id) || ', ' || quote(OLD.quantity) || ', ' || quote(OLD.note) || ')',
+            'DELETE FROM recipes WHERE id = ' || OLD.id
+        );
+    END;
+    CREATE TRIGGER log_recipes_update AFTER UPDATE ON recipes
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'UPDATE recipes SET dish_id = ' || quote(OLD.dish_id) || ', ingredient_id = ' || quote(OLD.ingredient_id) || ', quantity = ' || quote(OLD.quantity) || ', note = ' || quote(OLD.note) || ' WHERE id = ' || OLD.id,
+            'UPDATE recipes SET dish_id = ' || quote(NEW.dish_id) || ', ingredient_id = ' || quote(NEW.ingredient_id) || ', quantity = ' || quote(NEW.quantity) || ', note = ' || quote(NEW.note) || ' WHERE id = ' || NEW.id
+        );
+    END;
+    CREATE TRIGGER log_recipe_steps_insert AFTER INSERT ON recipe_steps
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'DELETE FROM recipe_steps WHERE id = ' || NEW.id,
+            'INSERT INTO recipe_steps (id, dish_id, position, text) VALUES (' || quote(NEW.id) || ', ' || quote(NEW.dish_id) || ', ' || quote(NEW.position) || ', ' || quote(NEW.text) || ')'
+        );
+    END;
+    CREATE TRIGGER log_recipe_steps_delete AFTER DELETE ON recipe_steps
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'INSERT INTO recipe_steps (id, dish_id, position, text) VALUES (' || quote(OLD.id) || ', ' || quote(OLD.dish_id) || ', ' || quote(OLD.position) || ', ' || quote(OLD.text) || ')',
+            'DELETE FROM recipe_steps WHERE id = ' || OLD.id
+        );
+    END;
+    CREATE TRIGGER log_recipe_steps_update AFTER UPDATE ON recipe_steps
+    WHEN NOT EXISTS (SELECT 1 FROM operation_replay)
+    BEGIN
+        INSERT INTO operation_log (undo_sql, redo_sql) VALUES (
+            'UPDATE recipe_steps SET dish_id = ' || quote(OLD.dish_id) || ', position = ' || quote(OLD.position) || ', text = ' || quote(OLD.text) || ' WHERE id = ' || OLD.id,
+            'UPDATE recipe_steps SET dish_id = ' || quote(NEW.dish_id) || ', position = ' || quote(NEW.position) || ', text = ' || quote(NEW.text) || ' WHERE id = ' || NEW.id
+        );
+    END;",
+];
+
+/// Brings the database up to the latest version. Every migration runs in its own transaction.
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version;", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
@@ -1,108 +1,111 @@
 pub mod dish_by_ingredients;
+pub mod recipe_export;
 
-use rusqlite::Result;
-use crate::{cli_operations::user_input::prompt, helper::calculate_mean};
-use prettytable::{Cell, Row, Table};
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+use crate::{cli_operations::{output::{is_table_output, print_records}, parser::Arguments}, error, helper::calculate_mean};
 
 use super::{get, get_connection};
 
-pub fn all_dish_names() -> Result<()> {
-    let conn = get_connection();
-    let mut select_dish_names_stmt = conn.prepare("Select id, name FROM dishes")?;
-    let names_iter = select_dish_names_stmt.query_map([], |row| {
-        Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
-    })?;
+#[derive(Debug, Serialize)]
+pub struct DishRow {
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct IngredientRow {
+    pub id: u32,
+    pub name: String,
+    pub category: Option<String>,
+    pub lifespan: Option<String>,
+    pub mean_price: Option<f32>,
+}
 
-    let mut table: Table = Table::new();
-    table.add_row(Row::new(vec![
-        Cell::new("ID"),
-        Cell::new("Name"),
-    ]));
+#[derive(Debug, Serialize)]
+pub struct RecipeLineRow {
+    pub dish: String,
+    pub ingredient: String,
+    pub quantity: u32,
+    pub note: Option<String>,
+}
 
-    for dish in names_iter {
-        let (id, name) = dish?;
+#[derive(Debug, Serialize)]
+pub struct PriceRow {
+    pub id: u32,
+    pub ingredient: String,
+    pub price: f32,
+}
 
-        table.add_row(Row::new(vec![
-            Cell::new(&id.to_string()),
-            Cell::new(&name),
-        ]));
-    }
+pub fn all_dish_names() -> error::Result<()> {
+    let conn = get_connection()?;
+    let dishes = dish_rows(&conn)?;
 
-    table.printstd();
+    print_records(&dishes, &["ID", "Name"], |dish| vec![dish.id.to_string(), dish.name.clone()]);
 
     Ok(())
 }
 
-pub fn recipe_by_dish_name() -> Result<()> {
-    let conn = get_connection();
+pub fn dish_rows(conn: &Connection) -> Result<Vec<DishRow>> {
+    let mut select_dish_names_stmt = conn.prepare("Select id, name FROM dishes")?;
+    let dishes = select_dish_names_stmt
+        .query_map([], |row| {
+            Ok(DishRow { id: row.get(0)?, name: row.get(1)? })
+        })?
+        .collect::<Result<Vec<DishRow>>>()?;
+
+    Ok(dishes)
+}
+
+pub fn recipe_by_dish_name(args: &mut Arguments) -> error::Result<()> {
+    let conn = get_connection()?;
 
-    let dish_name = prompt("Dish name");
+    let dish_name = args.next_or_prompt("Dish name");
     
     if dish_name.trim().is_empty() {
         return Ok(());
     }
 
-    let mut select_dish_ids_by_name_stmt = conn.prepare("SELECT id FROM dishes WHERE name = ?1;")?;
-    let dish_id_result: Result<u32> = select_dish_ids_by_name_stmt.query_row([&dish_name], |row| row.get(0));
-
-    let dish_id = match dish_id_result {
-        Ok(id) => id,
-        Err(e) => {
-            eprintln!("{e}");
-            return Ok(());
-        },
-    };
+    let dish_id = get::dish_id_by_name(&dish_name, &conn)?;
     
-    let mut select_recipe_ingredient_ids_stmt = conn.prepare("SELECT ingredient_id FROM recipes WHERE dish_id = ?1;")?;
-    let ingredient_ids_iter = select_recipe_ingredient_ids_stmt.query_map([dish_id], |row| {
-        Ok(row.get::<_, u32>(0)?)
-    })?;
+    let recipe_lines = recipe_lines(dish_id, &dish_name, &conn)?;
 
-    let mut ingredient_names: Vec<String> = Vec::new();
-    let mut ingredient_quantities: Vec<u32> = Vec::new();
-
-    for ingredient_id in ingredient_ids_iter {
-        let ingredient_id = ingredient_id?;
-        let ingredient_name: String = conn.query_row(
-            "SELECT name FROM ingredients WHERE id = ?1;",
-            [ingredient_id],
-            |row| row.get(0),
-        )?;
-
-        ingredient_names.push(ingredient_name);
-
-        let ingredient_quantity: u32 = conn.query_row(
-            "SELECT quantity FROM recipes WHERE dish_id = ?1 AND ingredient_id = ?2;",
-            [dish_id, ingredient_id],
-            |row| row.get(0),
-        )?;
-            
-        ingredient_quantities.push(ingredient_quantity);
+    if is_table_output() {
+        println!("{}", "-".repeat(50));
+        println!("Recipe for {dish_name}:");
     }
-
-    println!("{}", "-".repeat(50));
-    println!("Recipe for {dish_name}:");
-    let mut table = Table::new();
-    table.add_row(Row::new(vec![
-        Cell::new("Ingredient"),
-        Cell::new("Quantity (normally g)"),
-    ]));
-    for (name, quantity) in ingredient_names.iter().zip(ingredient_quantities.iter()) {
-        table.add_row(Row::new(vec![
-            Cell::new(&name),
-            Cell::new(&quantity.to_string()),
-        ]));
-    }
-    table.printstd();
+    print_records(&recipe_lines, &["Ingredient", "Quantity (normally g)"], |line| {
+        vec![line.ingredient.clone(), line.quantity.to_string()]
+    });
 
     Ok(())
 }
 
-pub fn all_ingredients() -> Result<()> {
-    let conn = get_connection();
+pub fn recipe_lines(dish_id: u32, dish_name: &str, conn: &Connection) -> Result<Vec<RecipeLineRow>> {
+    let mut select_recipe_lines_stmt = conn.prepare(
+        "SELECT ingredients.name, recipes.quantity, recipes.note FROM recipes
+        JOIN ingredients ON ingredients.id = recipes.ingredient_id
+        WHERE recipes.dish_id = ?1 ORDER BY recipes.id;"
+    )?;
+    let recipe_lines = select_recipe_lines_stmt
+        .query_map([dish_id], |row| {
+            Ok(RecipeLineRow {
+                dish: dish_name.to_string(),
+                ingredient: row.get(0)?,
+                quantity: row.get(1)?,
+                note: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<RecipeLineRow>>>()?;
+
+    Ok(recipe_lines)
+}
+
+pub fn all_ingredients(args: &mut Arguments) -> error::Result<()> {
+    let conn = get_connection()?;
 
     let (category_name, category_id) = loop {
-        let input_category_name = prompt("Category (all, vegetable, fruit, dairy, meat, condiment, grain)");
+        let input_category_name = args.next_or_prompt("Category (all, vegetable, fruit, dairy, meat, condiment, grain)");
         if input_category_name.is_empty() || input_category_name == "all" {
             break (input_category_name, 0);
         }
@@ -121,125 +124,119 @@ pub fn all_ingredients() -> Result<()> {
         break (input_category_name, retrieved_category_id);
     };
 
-    if category_id > 0 {
-        let mut select_ingredients_stmt = conn.prepare("SELECT * FROM ingredients WHERE category_id = ?1;")?;
-        let ingredients_iter = select_ingredients_stmt.query_map([category_id], |row| {
-            Ok((row.get::<_, i32>(0)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?))
-        })?;
-
-        let mut table = Table::new();
-        table.add_row(Row::new(vec![
-            Cell::new("ID"),
-            Cell::new(&format!("Name ({category_name})")),
-            Cell::new("Lifespan"),
-            Cell::new("Price"),
-        ]));
-    
-        for ingredient in ingredients_iter {
-            let (id, name, lifespan) = ingredient?;
-            let mut price_query = conn.prepare("SELECT price from prices where ingredient_id = ?1;")?;
-            let prices_iter = price_query.query_map([id], |row| {
-                Ok(row.get::<_, f32>(0)?)
-            })?;
-    
-            let mut prices: Vec<f32> = Vec::new();
-    
-            for price in prices_iter {
-                prices.push(price?);
-            }
-    
-            let mean_price = calculate_mean(prices);
-    
-            table.add_row(Row::new(vec![
-                Cell::new(&id.to_string()),
-                Cell::new(&name),
-                Cell::new(&lifespan),
-                Cell::new(&format!("${mean_price:.2}")),
-            ]));
-        }
-    
-        table.printstd();
+    let ingredients = ingredient_rows(category_id, &conn)?;
+
+    let name_header = if category_id > 0 { format!("Name ({category_name})") } else { "Name".to_string() };
+    print_records(&ingredients, &["ID", &name_header, "Lifespan", "Price"], |ingredient| {
+        vec![
+            ingredient.id.to_string(),
+            ingredient.name.clone(),
+            ingredient.lifespan.clone().unwrap_or_default(),
+            price_text(ingredient.mean_price),
+        ]
+    });
+
+    Ok(())
+}
+
+/// Ingredients with their category and mean price. A `category_id` of 0 selects every category.
+pub fn ingredient_rows(category_id: u32, conn: &Connection) -> Result<Vec<IngredientRow>> {
+    let mut select_ingredients_stmt = conn.prepare(
+        "SELECT ingredients.id, ingredients.name, categories.name, ingredients.lifespan FROM ingredients
+        LEFT JOIN categories ON categories.id = ingredients.category_id
+        WHERE ?1 = 0 OR ingredients.category_id = ?1
+        ORDER BY ingredients.id;"
+    )?;
+    let ingredients_iter = select_ingredients_stmt.query_map([category_id], |row| {
+        Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?, row.get::<_, Option<String>>(2)?, row.get::<_, Option<String>>(3)?))
+    })?;
+
+    let mut ingredients = Vec::new();
+    for ingredient in ingredients_iter {
+        let (id, name, category, lifespan) = ingredient?;
+        ingredients.push(IngredientRow { id, name, category, lifespan, mean_price: mean_price(id, conn)? });
     }
-    else {
-        let mut select_ingredients_stmt = conn.prepare("SELECT * FROM ingredients")?;
-        let ingredients_iter = select_ingredients_stmt.query_map([], |row| {
-            Ok((row.get::<_, i32>(0)?, row.get::<_, String>(2)?, row.get::<_, String>(3)?))
-        })?;
-
-        let mut table = Table::new();
-        table.add_row(Row::new(vec![
-            Cell::new("ID"),
-            Cell::new("Name"),
-            Cell::new("Lifespan"),
-            Cell::new("Price"),
-        ]));
-    
-        for ingredient in ingredients_iter {
-            let (id, name, lifespan) = ingredient?;
-            let mut price_query = conn.prepare("SELECT price from prices where ingredient_id = ?1;")?;
-            let prices_iter = price_query.query_map([id], |row| {
-                Ok(row.get::<_, f32>(0)?)
-            })?;
-    
-            let mut prices: Vec<f32> = Vec::new();
-    
-            for price in prices_iter {
-                prices.push(price?);
-            }
-    
-            let mean_price = calculate_mean(prices);
-    
-            table.add_row(Row::new(vec![
-                Cell::new(&id.to_string()),
-                Cell::new(&name),
-                Cell::new(&lifespan),
-                Cell::new(&format!("${mean_price:.2}")),
-            ]));
-        }
-    
-        table.printstd();
+
+    Ok(ingredients)
+}
+
+fn mean_price(ingredient_id: u32, conn: &Connection) -> Result<Option<f32>> {
+    let mut price_query = conn.prepare("SELECT price from prices where ingredient_id = ?1;")?;
+    let prices = price_query
+        .query_map([ingredient_id], |row| row.get::<_, f32>(0))?
+        .collect::<Result<Vec<f32>>>()?;
+
+    if prices.is_empty() {
+        return Ok(None);
     }
 
-    Ok(())
+    Ok(Some(calculate_mean(prices)))
 }
 
-pub fn specific_ingredient(ingredient_id: u32) -> Result<()> {
-    let conn = get_connection();
-
-    let mut stmt = conn.prepare("SELECT id, category_id, name, lifespan FROM ingredients WHERE id = ?1")?;
-    stmt.query_row([ingredient_id], |row| {
-        let id: u32 = row.get(0)?;  
-        let category_id: u32 = row.get(1)?;  
-        let name: String = row.get(2)?;  
-        let lifespan: String = row.get(3)?; 
-        let price = match get::price(ingredient_id, &conn) {
-            Some(num) => num,
-            None => f32::NAN,
-        };
+fn price_text(price: Option<f32>) -> String {
+    match price {
+        Some(price) => format!("${price:.2}"),
+        None => "-".to_string(),
+    }
+}
 
-        let mut table = Table::new();
+pub fn specific_ingredient(ingredient_id: u32, conn: &Connection) -> Result<()> {
+    let ingredient = conn.query_row(
+        "SELECT ingredients.id, ingredients.name, categories.name, ingredients.lifespan FROM ingredients
+        LEFT JOIN categories ON categories.id = ingredients.category_id
+        WHERE ingredients.id = ?1",
+        [ingredient_id],
+        |row| {
+            Ok(IngredientRow {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                category: row.get(2)?,
+                lifespan: row.get(3)?,
+                mean_price: get::price(ingredient_id, conn).filter(|price| !price.is_nan()),
+            })
+        },
+    )?;
+
+    print_records(&[ingredient], &["ID", "Category", "Name", "Lifespan", "Mean Price"], |ingredient| {
+        vec![
+            ingredient.id.to_string(),
+            ingredient.category.clone().unwrap_or_default(),
+            ingredient.name.clone(),
+            ingredient.lifespan.clone().unwrap_or_default(),
+            price_text(ingredient.mean_price),
+        ]
+    });
 
-        table.add_row(Row::new(vec![
-            Cell::new("ID"),
-            Cell::new("Category ID"),
-            Cell::new("Name"),
-            Cell::new("Lifespan"),
-            Cell::new("Mean Price"),
-        ]));
+    Ok(())
+}
 
-        table.add_row(Row::new(vec![
-            Cell::new(&id.to_string()),
-            Cell::new(&category_id.to_string()),
-            Cell::new(&name),
-            Cell::new(&lifespan),
-            Cell::new(&format!("${price:.2}")),
-        ]));
+pub fn prices_of(args: &mut Arguments) -> error::Result<()> {
+    let conn = get_connection()?;
 
-        table.printstd();
+    let ingredient_name = args.next_or_prompt("Ingredient name");
+    if ingredient_name.trim().is_empty() {
+        return Ok(());
+    }
 
-        Ok(())
-    })?;
+    let ingredient_id = get::ingredient_id_by_name(&ingredient_name, &conn)?;
+
+    let prices = price_rows(ingredient_id, &ingredient_name, &conn)?;
+
+    print_records(&prices, &["ID", "Price"], |price| vec![price.id.to_string(), format!("${:.2}", price.price)]);
+    if is_table_output() {
+        println!("Mean price of {ingredient_name}: {}", price_text(mean_price(ingredient_id, &conn)?));
+    }
 
     Ok(())
 }
 
+pub fn price_rows(ingredient_id: u32, ingredient_name: &str, conn: &Connection) -> Result<Vec<PriceRow>> {
+    let mut select_prices_stmt = conn.prepare("SELECT id, price FROM prices WHERE ingredient_id = ?1 ORDER BY id;")?;
+    let prices = select_prices_stmt
+        .query_map([ingredient_id], |row| {
+            Ok(PriceRow { id: row.get(0)?, ingredient: ingredient_name.to_string(), price: row.get(1)? })
+        })?
+        .collect::<Result<Vec<PriceRow>>>()?;
+
+    Ok(prices)
+}
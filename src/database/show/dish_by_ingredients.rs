@@ -1,94 +1,81 @@
 use std::collections::{HashMap, HashSet};
 use rusqlite::{Connection, Result};
-use prettytable::{Cell, Row, Table};
+use serde::Serialize;
 
-use crate::{cli_operations::user_input::{prompt, separate_by}, database::get_connection};
+use crate::{cli_operations::{output::{is_table_output, print_records}, parser::Arguments, user_input::{prompt, separate_by}}, database::{get, get_connection}, error::{self, Error}};
 
-pub fn get_dishes() -> Result<()> {
-    let conn = get_connection();
+#[derive(Debug, Serialize)]
+pub struct AvailableDish {
+    pub id: u32,
+    pub name: String,
+}
 
-    let input_ingredients = prompt("Ingredients (separated by comma)");
+pub fn get_dishes(args: &mut Arguments) -> error::Result<()> {
+    let conn = get_connection()?;
 
-    let input_ingredients_vec = separate_by(",", input_ingredients);
+    let input_ingredients_vec = if args.is_empty() {
+        let input_ingredients = prompt("Ingredients (separated by comma)");
+        separate_by(",", input_ingredients)
+    } else {
+        args.take_all()
+    };
 
-    let mut input_ingredient_ids_vec: Vec<u32> = Vec::new(); 
+    if input_ingredients_vec.iter().all(|name| name.is_empty()) {
+        return Err(Error::Validation("No ingredient was input for dish query".to_string()));
+    }
 
-    if input_ingredients_vec.is_empty() {
-        eprintln!("No ingredient was input for dish query");
-        return Ok(());
+    // get input ingredient id
+    let input_ingredient_ids_set = input_ingredients_vec
+        .iter()
+        .filter(|name| !name.is_empty())
+        .map(|name| get::ingredient_id_by_name(name, &conn))
+        .collect::<error::Result<HashSet<u32>>>()?;
+    let available_dishes = available_dishes(&input_ingredient_ids_set, &conn)?;
+
+    if available_dishes.is_empty() && is_table_output() {
+        println!("No available dishes");
+    } else {
+        print_records(&available_dishes, &["Available Dish"], |dish| vec![dish.name.clone()]);
     }
+    
+    Ok(())
+}
 
+/// Dishes that use at least one of the given ingredients and nothing else.
+pub fn available_dishes(input_ingredient_ids_set: &HashSet<u32>, conn: &Connection) -> Result<Vec<AvailableDish>> {
     // get all ingredient id
     let mut select_ingredient_ids_stmt = conn.prepare("SELECT id FROM ingredients;")?;
     let all_ingredient_ids_set: HashSet<u32> = select_ingredient_ids_stmt
-        .query_map([], |row| Ok(row.get::<_, u32>(0)?))?
-        .map(|result| result.unwrap())
-        .collect();
-
-    // get input ingredient id
-    for name in input_ingredients_vec {
-        let mut select_ingredient_ids_by_name_stmt = conn.prepare("SELECT id FROM ingredients WHERE name = ?1;")?;
-        let ingredient_ids_result: Result<u32> = select_ingredient_ids_by_name_stmt.query_row([&name], |row| row.get(0));
-        match ingredient_ids_result {
-            Ok(id) => {
-                input_ingredient_ids_vec.push(id);
-            },
-            Err(e) => {
-                eprintln!("Ingredient \"{}\" does not exist in database.", name);
-                eprintln!("{}", e);
-                return Ok(());
-            }
-        };
-    }
-
-    let input_ingredient_ids_set: HashSet<u32> = input_ingredient_ids_vec.into_iter().collect();
+        .query_map([], |row| row.get::<_, u32>(0))?
+        .collect::<Result<HashSet<u32>>>()?;
 
-    let all_dish_recipes_map = get_all_recipes_map(&conn)?;
+    let all_dish_recipes_map = get_all_recipes_map(conn)?;
 
-    let filtered_dish_ids_vec = filter_dishes_with_input_ingredients(&input_ingredient_ids_set, &all_ingredient_ids_set, &all_dish_recipes_map);
+    let filtered_dish_ids_vec = filter_dishes_with_input_ingredients(input_ingredient_ids_set, &all_ingredient_ids_set, &all_dish_recipes_map);
     
-    let mut available_dishes: Vec<String> = Vec::new();
+    let mut available_dishes: Vec<AvailableDish> = Vec::new();
 
     for id in filtered_dish_ids_vec {
         let mut select_dish_names_by_id_stmt = conn.prepare("SELECT name FROM dishes WHERE id = ?1;")?;
-        let dish_names: String = select_dish_names_by_id_stmt.query_row([id], |row| row.get(0))?;
-        available_dishes.push(dish_names);
+        let dish_name: String = select_dish_names_by_id_stmt.query_row([id], |row| row.get(0))?;
+        available_dishes.push(AvailableDish { id, name: dish_name });
     }
 
-    if !available_dishes.is_empty() {
-        let mut table: Table = Table::new();
-        table.add_row(Row::new(vec![
-            Cell::new("Available Dish"),
-        ]));
-
-        for dish in available_dishes {
-            table.add_row(Row::new(vec![
-                Cell::new(&dish),
-            ]));
-        }
-
-        table.printstd();
-    } else {
-        println!("No available dishes");
-    }
-    
-    Ok(())
+    Ok(available_dishes)
 }
 
 fn get_all_recipes_map(conn: &Connection) -> Result<HashMap<u32, Vec<u32>>> {
     let mut all_recipes_map: HashMap<u32, Vec<u32>> = HashMap::new();
     let mut select_dish_ids_stmt = conn.prepare("SELECT id FROM dishes;")?;
     let dish_ids_vec: Vec<u32> = select_dish_ids_stmt
-        .query_map([], |row| Ok(row.get::<_, u32>(0)?))?
-        .map(|result| result.unwrap())
-        .collect();
+        .query_map([], |row| row.get::<_, u32>(0))?
+        .collect::<Result<Vec<u32>>>()?;
 
     for dish_id in dish_ids_vec {
         let mut select_recipe_ingredient_ids_stmt = conn.prepare("SELECT ingredient_id FROM recipes WHERE dish_id = ?1;")?;
         let ingredient_ids_vec: Vec<u32> = select_recipe_ingredient_ids_stmt
-            .query_map([dish_id], |row| Ok(row.get::<_, u32>(0)?))?
-            .map(|result| result.unwrap())
-            .collect();
+            .query_map([dish_id], |row| row.get::<_, u32>(0))?
+            .collect::<Result<Vec<u32>>>()?;
         all_recipes_map.insert(dish_id, ingredient_ids_vec);
     }
 
@@ -99,11 +86,10 @@ fn filter_dishes_with_input_ingredients(input_ingredients: &HashSet<u32>, all_in
     let mut filtered: Vec<u32> = Vec::new();
     for (dish_name, ingredients_vec) in dish_recipes {
         for keyword in input_ingredients {
-            if ingredients_vec.contains(keyword) {
-                if filter_dishes_with_other_ingredients(input_ingredients, all_ingredients, ingredients_vec) {
-                    filtered.push(*dish_name);
-                    break;
-                }
+            if ingredients_vec.contains(keyword)
+                && filter_dishes_with_other_ingredients(input_ingredients, all_ingredients, ingredients_vec) {
+                filtered.push(*dish_name);
+                break;
             }
         }
     }
@@ -111,8 +97,8 @@ fn filter_dishes_with_input_ingredients(input_ingredients: &HashSet<u32>, all_in
     filtered
 }
 
-fn filter_dishes_with_other_ingredients(input_ingredients: &HashSet<u32>, all_ingredients: &HashSet<u32>, ingredients_vec: &Vec<u32>) -> bool {
-    let other_ingredients: HashSet<_> = all_ingredients.difference(&input_ingredients).cloned().collect();
+fn filter_dishes_with_other_ingredients(input_ingredients: &HashSet<u32>, all_ingredients: &HashSet<u32>, ingredients_vec: &[u32]) -> bool {
+    let other_ingredients: HashSet<_> = all_ingredients.difference(input_ingredients).cloned().collect();
 
     for ingredient in other_ingredients {
         if ingredients_vec.contains(&ingredient) {
@@ -120,5 +106,5 @@ fn filter_dishes_with_other_ingredients(input_ingredients: &HashSet<u32>, all_in
         }
     }
 
-    return true;
+    true
 }
\ No newline at end of file
@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::fs;
+use rusqlite::{Connection, Result};
+use serde::Serialize;
+
+use crate::cli_operations::{cancel_prompt, parser::Arguments, user_input::{prompt, separate_by}};
+use crate::database::{get, get_connection};
+use crate::error::{self, Error};
+
+const CARD_WIDTH: usize = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipeFormat {
+    Markdown,
+    Html,
+    Card,
+}
+
+impl RecipeFormat {
+    pub fn from_name(name: &str) -> Option<RecipeFormat> {
+        match name {
+            "markdown" | "md" => Some(RecipeFormat::Markdown),
+            "html" => Some(RecipeFormat::Html),
+            "card" | "text" | "txt" => Some(RecipeFormat::Card),
+            _ => None,
+        }
+    }
+}
+
+/// Everything printed about one dish.
+#[derive(Debug, Serialize)]
+pub struct RecipeCard {
+    pub dish: String,
+    pub lines: Vec<CardLine>,
+    pub steps: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CardLine {
+    pub ingredient: String,
+    pub category: String,
+    pub quantity: u32,
+    pub note: Option<String>,
+    /// Cost of the quantity used, from the mean price per kg. `None` when the ingredient has no price.
+    pub cost: Option<f32>,
+}
+
+impl RecipeCard {
+    /// Sum of the known line costs.
+    pub fn cost(&self) -> f32 {
+        self.lines.iter().filter_map(|line| line.cost).sum()
+    }
+
+    pub fn is_fully_priced(&self) -> bool {
+        self.lines.iter().all(|line| line.cost.is_some())
+    }
+
+    fn lines_by_category(&self) -> BTreeMap<&str, Vec<&CardLine>> {
+        let mut categories: BTreeMap<&str, Vec<&CardLine>> = BTreeMap::new();
+        for line in &self.lines {
+            categories.entry(line.category.as_str()).or_default().push(line);
+        }
+
+        categories
+    }
+
+    fn cost_text(&self) -> String {
+        if self.is_fully_priced() {
+            format!("${:.2}", self.cost())
+        } else {
+            format!("${:.2} (some ingredients have no price)", self.cost())
+        }
+    }
+}
+
+impl CardLine {
+    fn quantity_text(&self) -> String {
+        match &self.note {
+            Some(note) => format!("{} g {}, {note}", self.quantity, self.ingredient),
+            None => format!("{} g {}", self.quantity, self.ingredient),
+        }
+    }
+
+    fn cost_text(&self) -> String {
+        match self.cost {
+            Some(cost) => format!("${cost:.2}"),
+            None => "no price".to_string(),
+        }
+    }
+}
+
+/// Renders one dish, or a cookbook of several, to a file or the terminal.
+pub fn export(args: &mut Arguments) -> error::Result<()> {
+    let format = loop {
+        let input_format = args.next_or_prompt("Format (markdown, html, card)");
+        if input_format.is_empty() {
+            cancel_prompt();
+            return Ok(());
+        }
+        match RecipeFormat::from_name(&input_format) {
+            Some(format) => break format,
+            None => eprintln!("Invalid format"),
+        }
+    };
+
+    let path = args.next_raw_or_prompt("File (empty to print)");
+
+    let dish_names = if args.is_empty() {
+        separate_by(",", prompt("Dishes (separated by comma, or all)"))
+    } else {
+        args.take_all()
+    };
+
+    let conn = get_connection()?;
+
+    let dish_ids: Vec<u32> = if dish_names == ["all"] {
+        conn.prepare("SELECT id FROM dishes ORDER BY name;")?
+            .query_map([], |row| row.get::<_, u32>(0))?
+            .collect::<Result<Vec<u32>>>()?
+    } else {
+        dish_names
+            .iter()
+            .filter(|name| !name.is_empty())
+            .map(|dish_name| get::dish_id_by_name(dish_name, &conn))
+            .collect::<error::Result<Vec<u32>>>()?
+    };
+
+    if dish_ids.is_empty() {
+        cancel_prompt();
+        return Ok(());
+    }
+
+    let mut cards: Vec<RecipeCard> = Vec::new();
+    for dish_id in dish_ids {
+        cards.push(recipe_card(dish_id, &conn)?);
+    }
+
+    let rendered = match format {
+        RecipeFormat::Markdown => markdown(&cards),
+        RecipeFormat::Html => html(&cards),
+        RecipeFormat::Card => card(&cards),
+    };
+
+    if path.is_empty() {
+        println!("{rendered}");
+    } else {
+        fs::write(&path, rendered).map_err(|e| Error::from(e).in_file(&path))?;
+        println!("Exported {} recipes to {path}", cards.len());
+    }
+
+    Ok(())
+}
+
+pub fn recipe_card(dish_id: u32, conn: &Connection) -> Result<RecipeCard> {
+    let dish: String = conn.query_row("SELECT name FROM dishes WHERE id = ?1;", [dish_id], |row| row.get(0))?;
+
+    let mut select_lines_stmt = conn.prepare(
+        "SELECT ingredients.id, ingredients.name, categories.name, recipes.quantity, recipes.note FROM recipes
+        JOIN ingredients ON ingredients.id = recipes.ingredient_id
+        LEFT JOIN categories ON categories.id = ingredients.category_id
+        WHERE recipes.dish_id = ?1
+        ORDER BY ingredients.name;",
+    )?;
+    let rows = select_lines_stmt.query_map([dish_id], |row| {
+        Ok((
+            row.get::<_, u32>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<u32>>(3)?,
+            row.get::<_, Option<String>>(4)?,
+        ))
+    })?;
+
+    let mut lines: Vec<CardLine> = Vec::new();
+    for row in rows {
+        let (ingredient_id, ingredient, category, quantity, note) = row?;
+        let quantity = quantity.unwrap_or(0);
+        // Prices are per kg
+        let cost = get::price(ingredient_id, conn)
+            .filter(|price| price.is_finite())
+            .map(|price| price * quantity as f32 / 1000.0);
+
+        lines.push(CardLine {
+            ingredient,
+            category: category.unwrap_or("other".to_string()),
+            quantity,
+            note: note.filter(|note| !note.is_empty()),
+            cost,
+        });
+    }
+
+    let steps: Vec<String> = conn
+        .prepare("SELECT text FROM recipe_steps WHERE dish_id = ?1 ORDER BY position;")?
+        .query_map([dish_id], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<String>>>()?;
+
+    Ok(RecipeCard { dish, lines, steps })
+}
+
+pub fn markdown(cards: &[RecipeCard]) -> String {
+    let mut output = String::new();
+    let heading = if cards.len() > 1 {
+        output.push_str("# Cookbook\n\n");
+        "##"
+    } else {
+        "#"
+    };
+
+    for card in cards {
+        output.push_str(&format!("{heading} {}\n\n", title_case(&card.dish)));
+        output.push_str(&format!("Estimated cost: {}\n\n", card.cost_text()));
+
+        output.push_str(&format!("{heading}# Ingredients\n\n"));
+        for (category, lines) in card.lines_by_category() {
+            output.push_str(&format!("**{}**\n\n", title_case(category)));
+            for line in lines {
+                output.push_str(&format!("- {} ({})\n", line.quantity_text(), line.cost_text()));
+            }
+            output.push('\n');
+        }
+
+        if !card.steps.is_empty() {
+            output.push_str(&format!("{heading}# Steps\n\n"));
+            for (index, step) in card.steps.iter().enumerate() {
+                output.push_str(&format!("{}. {step}\n", index + 1));
+            }
+            output.push('\n');
+        }
+    }
+
+    output
+}
+
+/// A self-contained page, with the styles inline so that it can be opened or printed offline.
+pub fn html(cards: &[RecipeCard]) -> String {
+    let title = if cards.len() == 1 {
+        title_case(&cards[0].dish)
+    } else {
+        "Cookbook".to_string()
+    };
+
+    let mut output = String::new();
+    output.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    output.push_str(&format!("<title>{}</title>\n", escape_html(&title)));
+    output.push_str(
+        "<style>
+body { font-family: Georgia, serif; max-width: 42rem; margin: 2rem auto; padding: 0 1rem; color: #222; }
+article { border-bottom: 1px solid #ccc; padding-bottom: 1.5rem; margin-bottom: 1.5rem; page-break-inside: avoid; }
+h3 { font-size: 1rem; text-transform: uppercase; letter-spacing: 0.05em; color: #666; margin-bottom: 0.25rem; }
+.cost { color: #666; font-style: italic; }
+.price { color: #666; float: right; }
+ul { padding-left: 1.2rem; }
+</style>\n</head>\n<body>\n",
+    );
+    if cards.len() > 1 {
+        output.push_str("<h1>Cookbook</h1>\n");
+    }
+
+    for card in cards {
+        output.push_str("<article>\n");
+        output.push_str(&format!("<h2>{}</h2>\n", escape_html(&title_case(&card.dish))));
+        output.push_str(&format!("<p class=\"cost\">Estimated cost: {}</p>\n", escape_html(&card.cost_text())));
+
+        for (category, lines) in card.lines_by_category() {
+            output.push_str(&format!("<h3>{}</h3>\n<ul>\n", escape_html(&title_case(category))));
+            for line in lines {
+                output.push_str(&format!(
+                    "<li>{} <span class=\"price\">{}</span></li>\n",
+                    escape_html(&line.quantity_text()),
+                    escape_html(&line.cost_text()),
+                ));
+            }
+            output.push_str("</ul>\n");
+        }
+
+        if !card.steps.is_empty() {
+            output.push_str("<h3>Steps</h3>\n<ol>\n");
+            for step in &card.steps {
+                output.push_str(&format!("<li>{}</li>\n", escape_html(step)));
+            }
+            output.push_str("</ol>\n");
+        }
+        output.push_str("</article>\n");
+    }
+
+    output.push_str("</body>\n</html>\n");
+
+    output
+}
+
+/// Plain text cards of fixed width, for printing.
+pub fn card(cards: &[RecipeCard]) -> String {
+    let mut output = String::new();
+    let border = format!("+{}+\n", "-".repeat(CARD_WIDTH - 2));
+
+    for card in cards {
+        output.push_str(&border);
+        for wrapped in wrap(&card.dish.to_uppercase(), CARD_WIDTH - 4) {
+            output.push_str(&card_row(&wrapped));
+        }
+        output.push_str(&card_row(&format!("Estimated cost: {}", card.cost_text())));
+        output.push_str(&border);
+
+        for (category, lines) in card.lines_by_category() {
+            output.push_str(&card_row(&format!("[{}]", title_case(category))));
+            for line in lines {
+                let cost = line.cost_text();
+                let text = format!("  {}", line.quantity_text());
+                let padding = (CARD_WIDTH - 4).saturating_sub(text.chars().count() + cost.chars().count());
+                if padding == 0 {
+                    for wrapped in wrap(&text, CARD_WIDTH - 4) {
+                        output.push_str(&card_row(&wrapped));
+                    }
+                    output.push_str(&card_row(&format!("{cost:>width$}", width = CARD_WIDTH - 4)));
+                } else {
+                    output.push_str(&card_row(&format!("{text}{}{cost}", " ".repeat(padding))));
+                }
+            }
+        }
+
+        if !card.steps.is_empty() {
+            output.push_str(&border);
+            for (index, step) in card.steps.iter().enumerate() {
+                let prefix = format!("{}. ", index + 1);
+                for (line_index, wrapped) in wrap(step, CARD_WIDTH - 4 - prefix.len()).iter().enumerate() {
+                    let indent = if line_index == 0 { prefix.clone() } else { " ".repeat(prefix.len()) };
+                    output.push_str(&card_row(&format!("{indent}{wrapped}")));
+                }
+            }
+        }
+        output.push_str(&border);
+        output.push('\n');
+    }
+
+    output
+}
+
+fn card_row(text: &str) -> String {
+    format!("| {text:<width$} |\n", width = CARD_WIDTH - 4)
+}
+
+/// Splits the text into lines of at most `width` characters at spaces; a word longer than a line is cut.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let words = text.split_whitespace().flat_map(|word| {
+        let chars: Vec<char> = word.chars().collect();
+        chars.chunks(width).map(|chunk| chunk.iter().collect::<String>()).collect::<Vec<String>>()
+    });
+    for word in words {
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
+
+fn title_case(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use chrono::{DateTime, Local};
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName, OpenFlags};
+use serde::Serialize;
+
+use crate::cli_operations::{output::print_records, parser::Arguments, user_input::prompt};
+use crate::config;
+use crate::error::{Error, Result};
+
+use super::cloud::{has_internet_access, mark_changed, request_sync};
+use super::{diff, first_start, get_connection, schema};
+
+/// Snapshots are named after the local time they were taken and the command that made them,
+/// such as `20261019-101500-delete-dish.db`.
+const SNAPSHOT_TIME_FORMAT: &str = "%Y%m%d-%H%M%S";
+
+/// A snapshot in the profile's snapshot dir.
+struct Snapshot {
+    name: String,
+    path: PathBuf,
+    taken: DateTime<Local>,
+    size: u64,
+}
+
+#[derive(Serialize)]
+struct SnapshotRow {
+    number: usize,
+    name: String,
+    taken: String,
+    reason: String,
+    size: u64,
+}
+
+/// Copies the database of the active profile into a new snapshot before `reason` changes it,
+/// then deletes the oldest snapshots beyond the profile's count. A missing or empty database is not snapshotted.
+pub fn take(reason: &str) -> Result<()> {
+    let profile = config::active_profile();
+    if profile.snapshot_count == 0 || first_start()? {
+        return Ok(());
+    }
+
+    let conn = Connection::open_with_flags(&profile.db, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    take_from(&conn, reason)
+}
+
+/// Same as `take`, through a connection that is already open, such as the server's. It must not be inside a transaction.
+pub fn take_from(conn: &Connection, reason: &str) -> Result<()> {
+    let profile = config::active_profile();
+    if profile.snapshot_count == 0 {
+        return Ok(());
+    }
+
+    let snapshot_dir = profile.snapshot_dir();
+    fs::create_dir_all(&snapshot_dir)?;
+    let slug: String = reason
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join("-")
+        .chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-')
+        .collect();
+    let path = snapshot_dir.join(format!("{}-{slug}.db", Local::now().format(SNAPSHOT_TIME_FORMAT)));
+
+    // The online backup API copies a consistent state even while other connections use the database
+    conn.backup(DatabaseName::Main, &path, None)
+        .map_err(|e| Error::from(e).in_file(&path.to_string_lossy()))?;
+
+    for snapshot in snapshots()?.iter().skip(profile.snapshot_count) {
+        fs::remove_file(&snapshot.path)?;
+    }
+
+    Ok(())
+}
+
+/// `snapshots` lists the local snapshots from newest to oldest.
+pub fn list() -> Result<()> {
+    let profile = config::active_profile();
+    let snapshots = snapshots()?;
+    if snapshots.is_empty() {
+        println!("No snapshots in {} yet, one is taken before a fetch replaces the database and before every delete", profile.snapshot_dir().display());
+        return Ok(());
+    }
+
+    let rows: Vec<SnapshotRow> = snapshots
+        .iter()
+        .enumerate()
+        .map(|(index, snapshot)| SnapshotRow {
+            number: index + 1,
+            name: snapshot.name.clone(),
+            taken: snapshot.taken.format("%Y-%m-%d %H:%M:%S").to_string(),
+            reason: reason(&snapshot.name),
+            size: snapshot.size,
+        })
+        .collect();
+    print_records(&rows, &["#", "Taken", "Before", "Size"], |row| {
+        vec![row.number.to_string(), row.taken.clone(), row.reason.clone(), format!("{:.1} KB", row.size as f64 / 1024.0)]
+    });
+
+    Ok(())
+}
+
+/// `snapshots diff <number>` shows how the database would change by restoring a snapshot.
+pub fn diff(args: &mut Arguments) -> Result<()> {
+    let snapshot = find(&args.next_raw_or_prompt("Snapshot number (see `snapshots`)"))?;
+    print_changes(&snapshot)
+}
+
+/// `snapshots restore <number>` replaces the database with a snapshot after a confirmation.
+/// The current database is snapshotted first, so that a restore can be undone by restoring that snapshot.
+pub async fn restore(args: &mut Arguments) -> Result<()> {
+    let snapshot = find(&args.next_raw_or_prompt("Snapshot number (see `snapshots`)"))?;
+    print_changes(&snapshot)?;
+    println!("Replace the database with this snapshot?");
+    if prompt("[Y/N]") != "y" {
+        println!("Restore canceled");
+        return Ok(());
+    }
+
+    // Read first, as the snapshot of the current database may push the restored one out of the count
+    let mut restored = Connection::open_in_memory()?;
+    restored.restore(DatabaseName::Main, &snapshot.path, None::<fn(Progress)>)?;
+    // A snapshot taken by an older version is upgraded like any database
+    schema::migrate(&mut restored)?;
+    take("restore snapshot")?;
+    restored.backup(DatabaseName::Main, config::active_profile().db, None)?;
+    println!("Snapshot {} restored", snapshot.name);
+
+    // Marked as changed, so that the next fetch keeps it over the cloud database
+    if has_internet_access().await {
+        request_sync().await
+    } else {
+        mark_changed()?;
+        println!("The restored database is synced by the next change or `sync database`");
+        Ok(())
+    }
+}
+
+fn print_changes(snapshot: &Snapshot) -> Result<()> {
+    let changes = diff::between(&get_connection()?, &diff::load(&snapshot.path)?)?;
+
+    println!("Restoring snapshot {} from {} changes the database:", snapshot.name, snapshot.taken.format("%Y-%m-%d %H:%M:%S"));
+    diff::print(&changes);
+
+    Ok(())
+}
+
+/// The file of a snapshot by its number in `snapshots` or its name, for `diff snapshot 2`.
+pub fn path(number_or_name: &str) -> Result<PathBuf> {
+    Ok(find(number_or_name)?.path)
+}
+
+/// A snapshot by its number in `snapshots`, 1 being the newest, or by its name.
+fn find(number_or_name: &str) -> Result<Snapshot> {
+    let snapshots = snapshots()?;
+    let found = match number_or_name.parse::<usize>() {
+        Ok(number) if number >= 1 => snapshots.into_iter().nth(number - 1),
+        _ => snapshots.into_iter().find(|snapshot| snapshot.name == number_or_name.trim_end_matches(".db")),
+    };
+
+    found.ok_or_else(|| Error::NotFound(format!("No snapshot \"{number_or_name}\", `snapshots` lists them")))
+}
+
+/// The snapshots of the active profile, newest first.
+fn snapshots() -> Result<Vec<Snapshot>> {
+    let snapshot_dir = config::active_profile().snapshot_dir();
+    let entries = match fs::read_dir(&snapshot_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::from(e).in_file(&snapshot_dir.to_string_lossy())),
+    };
+
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(".db")) else {
+            continue;
+        };
+        let metadata = fs::metadata(&path)?;
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        snapshots.push(Snapshot { name: name.to_string(), taken: modified.into(), size: metadata.len(), path });
+    }
+    snapshots.sort_by(|a, b| b.taken.cmp(&a.taken).then_with(|| b.name.cmp(&a.name)));
+
+    Ok(snapshots)
+}
+
+/// The command a snapshot was taken before, from its name.
+fn reason(name: &str) -> String {
+    // after the date and the time
+    name.splitn(3, '-').nth(2).unwrap_or_default().replace('-', " ")
+}
@@ -0,0 +1,108 @@
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::error::{Error, Result};
+
+use crate::lifespan;
+
+/// Checks a new dish name, or a new name for the dish `dish_id`, and returns it trimmed.
+/// Names are unique regardless of case and surrounding spaces.
+pub fn dish_name(name: &str, dish_id: Option<u32>, conn: &Connection) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::Validation("Dish name cannot be empty".to_string()));
+    }
+
+    if let Some(existing) = existing_dish(name, dish_id, conn)? {
+        return Err(Error::Validation(format!("There is already a dish called \"{existing}\"")));
+    }
+
+    Ok(name.to_string())
+}
+
+/// Checks a new ingredient name, or a new name for the ingredient `ingredient_id`, and returns it trimmed.
+/// Names are unique regardless of case and surrounding spaces.
+pub fn ingredient_name(name: &str, ingredient_id: Option<u32>, conn: &Connection) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(Error::Validation("Ingredient name cannot be empty".to_string()));
+    }
+    if lifespan::normalize(name).is_some() {
+        return Err(Error::Validation(format!("\"{name}\" is a lifespan, not an ingredient name")));
+    }
+
+    if let Some(existing) = existing_ingredient(name, ingredient_id, conn)? {
+        return Err(Error::Validation(format!("There is already an ingredient called \"{existing}\"")));
+    }
+
+    Ok(name.to_string())
+}
+
+/// The name of another dish that `name` would clash with, compared like `dish_name` does.
+pub fn existing_dish(name: &str, dish_id: Option<u32>, conn: &Connection) -> Result<Option<String>> {
+    let existing = conn
+        .query_row("SELECT name FROM dishes WHERE lower(trim(name)) = lower(trim(?1)) AND id IS NOT ?2;", (name, dish_id), |row| row.get(0))
+        .optional()?;
+
+    Ok(existing)
+}
+
+/// The name of another ingredient that `name` would clash with, compared like `ingredient_name` does.
+pub fn existing_ingredient(name: &str, ingredient_id: Option<u32>, conn: &Connection) -> Result<Option<String>> {
+    let existing = conn
+        .query_row("SELECT name FROM ingredients WHERE lower(trim(name)) = lower(trim(?1)) AND id IS NOT ?2;", (name, ingredient_id), |row| row.get(0))
+        .optional()?;
+
+    Ok(existing)
+}
+
+/// Returns the lifespan in its canonical form, or `None` when it is blank.
+pub fn lifespan(text: &str) -> Result<Option<String>> {
+    if text.trim().is_empty() {
+        return Ok(None);
+    }
+
+    match lifespan::normalize(text) {
+        Some(lifespan) => Ok(Some(lifespan)),
+        None => Err(Error::Validation(format!("Invalid lifespan \"{text}\", expected durations in _y_mo_w_d_h_m_s such as \"5d\", \"2w-3w\" or \"fd:3d-5d;fz:6mo\""))),
+    }
+}
+
+/// Reads a price per kg such as "4.5" or "$4.50".
+pub fn price_text(text: &str) -> Result<f32> {
+    match text.trim().trim_start_matches('$').parse::<f32>() {
+        Ok(price) => price_value(price),
+        Err(_) => Err(Error::Validation(format!("Invalid price \"{text}\", expected a number such as 4.50"))),
+    }
+}
+
+pub fn price_value(price: f32) -> Result<f32> {
+    if !price.is_finite() {
+        return Err(Error::Validation(format!("Invalid price {price}, expected a number such as 4.50")));
+    }
+    if price < 0.0 {
+        return Err(Error::Validation(format!("Invalid price {price}, it cannot be negative")));
+    }
+
+    Ok(price)
+}
+
+/// The price as stored, rounded to cents so that 4.2 is not written as 4.19999980926514.
+pub fn stored_price(price: f32) -> f64 {
+    (f64::from(price) * 100.0).round() / 100.0
+}
+
+/// Reads a recipe quantity in grams.
+pub fn quantity_text(text: &str) -> Result<u32> {
+    match text.trim().parse::<u32>() {
+        Ok(quantity) => quantity_value(quantity),
+        Err(_) => Err(Error::Validation(format!("Invalid quantity \"{text}\", expected a whole number of grams"))),
+    }
+}
+
+pub fn quantity_value(quantity: u32) -> Result<u32> {
+    if quantity == 0 {
+        return Err(Error::Validation("Quantity must be more than 0 g".to_string()));
+    }
+
+    Ok(quantity)
+}
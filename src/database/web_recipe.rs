@@ -0,0 +1,391 @@
+use std::fs;
+use prettytable::{Cell, Row, Table};
+use rusqlite::Connection;
+use serde_json::Value;
+
+use crate::cli_operations::{cancel_prompt, parser::Arguments, user_input::prompt};
+use crate::error::{Error, Result};
+use crate::ingredient_line::{self, closest_ingredient};
+
+use super::cloud::fetch_before_change;
+use super::{commit_change, get, get_connection, insert, validate, Outcome};
+
+/// The parts of a schema.org `Recipe` that are imported.
+pub struct WebRecipe {
+    pub name: String,
+    pub ingredient_lines: Vec<String>,
+    pub steps: Vec<String>,
+}
+
+/// The ingredient a line refers to, with the category to create it in when it is new.
+struct IngredientChoice {
+    name: String,
+    new_category: Option<String>,
+}
+
+/// A recipe line after it has been matched to an ingredient and converted to grams.
+struct PlannedLine {
+    text: String,
+    ingredient: String,
+    // Set when the ingredient does not exist yet and will be created in this category
+    new_category: Option<String>,
+    quantity: u32,
+    note: Option<String>,
+}
+
+/// Imports a recipe from a saved web page or JSON file containing schema.org `Recipe` JSON-LD.
+pub async fn import(args: &mut Arguments) -> Result<()> {
+    let path = args.next_raw_or_prompt("Recipe file (HTML or JSON)");
+    if path.is_empty() {
+        cancel_prompt();
+        return Ok(());
+    }
+
+    let web_recipe = read_web_recipe(&path).map_err(|e| e.in_file(&path))?;
+
+    fetch_before_change("import a recipe").await?;
+
+    let mut conn = get_connection()?;
+
+    let mut dish_name = web_recipe.name.to_lowercase();
+    while dish_name.is_empty() || get::dish_id_by_name(&dish_name, &conn).is_ok() {
+        if !dish_name.is_empty() {
+            eprintln!("Dish \"{dish_name}\" already exists");
+        }
+        dish_name = prompt("Dish name");
+        if dish_name.is_empty() {
+            cancel_prompt();
+            return Ok(());
+        }
+    }
+
+    let planned_lines = match plan_lines(&web_recipe.ingredient_lines, &conn)? {
+        Some(lines) => lines,
+        None => {
+            cancel_prompt();
+            return Ok(());
+        },
+    };
+
+    print_plan(&dish_name, &planned_lines);
+    if !web_recipe.steps.is_empty() {
+        println!("{} steps will be added", web_recipe.steps.len());
+    }
+    println!("Add this recipe?");
+    if prompt("[Y/N]") != "y" {
+        println!("Import aborted");
+        return Ok(());
+    }
+
+    commit_change(&mut conn, &format!("import web recipe {dish_name}"), |tx| {
+        write_plan(&dish_name, &planned_lines, &web_recipe.steps, tx)?;
+        Ok(Outcome::Commit)
+    }).await?;
+
+    println!("Inserted {dish_name} with {} ingredients", planned_lines.len());
+
+    Ok(())
+}
+
+pub fn read_web_recipe(path: &str) -> Result<WebRecipe> {
+    let content = fs::read_to_string(path)?;
+    let trimmed = content.trim_start();
+
+    let documents: Vec<String> = if trimmed.starts_with('{') || trimmed.starts_with('[') {
+        vec![content.clone()]
+    } else {
+        json_ld_blocks(&content)
+    };
+
+    for document in documents {
+        let value: Value = match serde_json::from_str(&document) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        if let Some(recipe) = find_recipe(&value) {
+            let name = recipe.get("name").and_then(Value::as_str).map(decode_html).unwrap_or_default();
+            let mut ingredient_lines: Vec<String> = Vec::new();
+            // "ingredients" is the older name of "recipeIngredient"
+            if let Some(ingredients) = recipe.get("recipeIngredient").or_else(|| recipe.get("ingredients")) {
+                collect_strings(ingredients, &mut ingredient_lines);
+            }
+
+            if ingredient_lines.is_empty() {
+                return Err(Error::Validation("The recipe has no ingredients".to_string()));
+            }
+
+            let mut steps: Vec<String> = Vec::new();
+            if let Some(instructions) = recipe.get("recipeInstructions") {
+                collect_steps(instructions, &mut steps);
+            }
+
+            return Ok(WebRecipe { name: name.trim().to_string(), ingredient_lines, steps });
+        }
+    }
+
+    Err(Error::Validation("No schema.org Recipe found".to_string()))
+}
+
+/// Contents of every `<script type="application/ld+json">` element.
+fn json_ld_blocks(html: &str) -> Vec<String> {
+    // ASCII lowercasing keeps byte offsets identical to the original
+    let lowercase_html = html.to_ascii_lowercase();
+    let mut blocks: Vec<String> = Vec::new();
+    let mut search_from = 0;
+
+    while let Some(offset) = lowercase_html[search_from..].find("<script") {
+        let tag_start = search_from + offset;
+        let content_start = match lowercase_html[tag_start..].find('>') {
+            Some(offset) => tag_start + offset + 1,
+            None => break,
+        };
+        let content_end = match lowercase_html[content_start..].find("</script") {
+            Some(offset) => content_start + offset,
+            None => break,
+        };
+
+        if lowercase_html[tag_start..content_start].contains("application/ld+json") {
+            blocks.push(html[content_start..content_end].to_string());
+        }
+        search_from = content_end;
+    }
+
+    blocks
+}
+
+/// Searches the whole document, since recipes are often nested in `@graph` or arrays.
+fn find_recipe(value: &Value) -> Option<&Value> {
+    match value {
+        Value::Object(object) => {
+            let is_recipe = match object.get("@type") {
+                Some(Value::String(recipe_type)) => recipe_type == "Recipe",
+                Some(Value::Array(types)) => types.iter().any(|t| t.as_str() == Some("Recipe")),
+                _ => false,
+            };
+            if is_recipe {
+                return Some(value);
+            }
+            object.values().find_map(find_recipe)
+        },
+        Value::Array(values) => values.iter().find_map(find_recipe),
+        _ => None,
+    }
+}
+
+fn collect_strings(value: &Value, strings: &mut Vec<String>) {
+    match value {
+        Value::String(s) => {
+            let s = decode_html(s);
+            if !s.trim().is_empty() {
+                strings.push(s.trim().to_string());
+            }
+        },
+        Value::Array(values) => values.iter().for_each(|value| collect_strings(value, strings)),
+        _ => {},
+    }
+}
+
+/// Instructions are a single text, a list of texts, or `HowToStep`s possibly grouped in `HowToSection`s.
+fn collect_steps(value: &Value, steps: &mut Vec<String>) {
+    match value {
+        Value::String(s) => decode_html(s)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .for_each(|line| steps.push(line.to_string())),
+        Value::Array(values) => values.iter().for_each(|value| collect_steps(value, steps)),
+        Value::Object(object) => {
+            if let Some(elements) = object.get("itemListElement") {
+                collect_steps(elements, steps);
+            } else if let Some(text) = object.get("text").or_else(|| object.get("name")) {
+                collect_steps(text, steps);
+            }
+        },
+        _ => {},
+    }
+}
+
+/// Removes tags and decodes the entities commonly found in JSON-LD strings.
+fn decode_html(s: &str) -> String {
+    let mut without_tags = String::new();
+    let mut in_tag = false;
+    for c in s.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => without_tags.push(c),
+            _ => {},
+        }
+    }
+
+    let mut decoded = String::new();
+    let mut rest = without_tags.as_str();
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity_end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+                continue;
+            },
+        };
+        let entity = &rest[1..entity_end];
+        let character = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse::<u32>().ok()))
+                .and_then(char::from_u32),
+        };
+
+        match character {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[entity_end + 1..];
+            },
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            },
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+/// Matches every line to an ingredient, asking about the ones that cannot be matched or converted to grams.
+/// Returns `None` when the user cancels.
+fn plan_lines(lines: &[String], conn: &Connection) -> Result<Option<Vec<PlannedLine>>> {
+    let mut known_names: Vec<String> = conn
+        .prepare("SELECT name FROM ingredients;")?
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?;
+
+    let mut planned_lines: Vec<PlannedLine> = Vec::new();
+
+    for text in lines {
+        let parsed = ingredient_line::parse(text);
+
+        let IngredientChoice { name: ingredient, new_category } = match closest_ingredient(&parsed.name, &known_names) {
+            Some(known) => IngredientChoice { name: known.clone(), new_category: None },
+            None => {
+                println!("No ingredient matches \"{text}\"");
+                match ask_ingredient(&parsed.name, conn)? {
+                    Some(answer) => answer,
+                    None => {
+                        println!("Skipped \"{text}\"");
+                        continue;
+                    },
+                }
+            },
+        };
+
+        let quantity = match parsed.grams() {
+            Some(grams) => grams,
+            None => loop {
+                let input_quantity = prompt(&format!("Quantity (g) of {ingredient} for \"{text}\""));
+                if input_quantity.is_empty() {
+                    return Ok(None);
+                }
+                match validate::quantity_text(&input_quantity) {
+                    Ok(num) => break num,
+                    Err(e) => eprintln!("{e}"),
+                }
+            },
+        };
+
+        if new_category.is_some() && !known_names.contains(&ingredient) {
+            known_names.push(ingredient.clone());
+        }
+
+        // The same ingredient listed twice becomes one recipe line
+        match planned_lines.iter_mut().find(|line| line.ingredient == ingredient) {
+            Some(line) => {
+                line.quantity += quantity;
+                line.text = format!("{}; {text}", line.text);
+            },
+            None => planned_lines.push(PlannedLine { text: text.clone(), ingredient, new_category, quantity, note: parsed.note }),
+        }
+    }
+
+    Ok(Some(planned_lines))
+}
+
+/// Asks for an existing ingredient to use, or a category to create the parsed name as a new ingredient.
+/// Returns `None` when the line should be skipped.
+fn ask_ingredient(parsed_name: &str, conn: &Connection) -> Result<Option<IngredientChoice>> {
+    loop {
+        let answer = prompt(&format!("Existing ingredient, or category (vegetable, fruit, dairy, meat, condiment, grain) to add \"{parsed_name}\""));
+        if answer.is_empty() {
+            return Ok(None);
+        }
+
+        if get::ingredient_id_by_name(&answer, conn).is_ok() {
+            return Ok(Some(IngredientChoice { name: answer, new_category: None }));
+        }
+
+        if get::category_id_by_name(&answer, conn).is_ok() {
+            if parsed_name.is_empty() {
+                eprintln!("The line has no ingredient name, enter an existing ingredient instead");
+                continue;
+            }
+            return Ok(Some(IngredientChoice { name: parsed_name.to_string(), new_category: Some(answer) }));
+        }
+
+        eprintln!("\"{answer}\" is neither an ingredient nor a category");
+    }
+}
+
+fn print_plan(dish_name: &str, planned_lines: &[PlannedLine]) {
+    println!("{}", "-".repeat(50));
+    println!("Recipe for {dish_name}:");
+    let mut table = Table::new();
+    table.add_row(Row::new(vec![
+        Cell::new("Recipe line"),
+        Cell::new("Ingredient"),
+        Cell::new("Quantity (g)"),
+    ]));
+    for line in planned_lines {
+        let ingredient = match &line.new_category {
+            Some(category) => format!("{} (new, {category})", line.ingredient),
+            None => line.ingredient.clone(),
+        };
+        table.add_row(Row::new(vec![
+            Cell::new(&line.text),
+            Cell::new(&ingredient),
+            Cell::new(&line.quantity.to_string()),
+        ]));
+    }
+    table.printstd();
+}
+
+fn write_plan(dish_name: &str, planned_lines: &[PlannedLine], steps: &[String], conn: &Connection) -> Result<()> {
+    for line in planned_lines {
+        if let Some(category) = &line.new_category {
+            insert::ingredient_row(&line.ingredient, category, "", conn)?;
+        }
+    }
+
+    insert::dish_row(dish_name, conn)?;
+
+    for line in planned_lines {
+        insert::recipe_row(dish_name, &line.ingredient, line.quantity, line.note.as_deref(), conn)?;
+    }
+
+    for (index, step) in steps.iter().enumerate() {
+        insert::step_row(dish_name, index as u32 + 1, step, conn)?;
+    }
+
+    Ok(())
+}
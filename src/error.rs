@@ -0,0 +1,113 @@
+use std::fmt;
+use std::io;
+
+use crate::config;
+use crate::database::cloud;
+
+/// Everything that can go wrong in Arino, grouped by what the user can do about it.
+#[derive(Debug)]
+pub enum Error {
+    /// The input breaks a rule, such as a duplicate name or a negative price.
+    Validation(String),
+    /// A dish, ingredient, category or file that does not exist.
+    NotFound(String),
+    /// The local database failed.
+    Database(rusqlite::Error),
+    /// Dropbox could not be reached or answered with an error.
+    Network(String),
+    /// The Dropbox credentials are missing, invalid or expired.
+    Auth(String),
+    /// A local file could not be read or written.
+    Io(io::Error),
+    /// The database in Dropbox changed since the local one was fetched, while the local one has changes that are not synced yet.
+    Conflict(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Exit code of a command run from the shell; 1 is left for a failed script and 2 for a command that could not be parsed.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Validation(_) => 3,
+            Error::NotFound(_) => 4,
+            Error::Database(_) => 5,
+            Error::Network(_) => 6,
+            Error::Auth(_) => 7,
+            Error::Io(_) => 8,
+            Error::Conflict(_) => 9,
+        }
+    }
+
+    /// Names the file that could not be read or written, or whose content was invalid.
+    pub fn in_file(self, path: &str) -> Error {
+        match self {
+            Error::Io(e) => Error::Io(io::Error::new(e.kind(), format!("{path}: {e}"))),
+            Error::Validation(message) => Error::Validation(format!("{path}: {message}")),
+            e => e,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Validation(message) | Error::NotFound(message) | Error::Network(message) | Error::Auth(message) | Error::Conflict(message) => write!(f, "{message}"),
+            Error::Database(e) => write!(f, "Database error: {e}"),
+            Error::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Database(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Database(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Error {
+        Error::Network(e.to_string())
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Error {
+        if e.is_io_error() {
+            match e.into_kind() {
+                csv::ErrorKind::Io(e) => Error::Io(e),
+                kind => Error::Validation(format!("{kind:?}")),
+            }
+        } else {
+            Error::Validation(e.to_string())
+        }
+    }
+}
+
+/// Shows an error to the user, with a hint when there is something to check.
+pub fn report(error: &Error) {
+    eprintln!("Error: {error}");
+    match error {
+        Error::Network(_) if cloud::is_offline() => eprintln!("Run Arino without --offline to use Dropbox"),
+        Error::Network(_) => eprintln!("Check the internet connection and try again, or use --offline to change only the local database"),
+        Error::Conflict(_) => eprintln!("`diff remote` shows how they differ, then `fetch preview` takes the database in Dropbox or `sync preview` uploads the local one over it"),
+        Error::Auth(_) => eprintln!("Run `arino auth status` to check the Dropbox login of profile \"{}\", or `arino auth login` to log in again", config::active_profile().name),
+        _ => {},
+    }
+}
@@ -7,7 +7,6 @@ pub fn flush() {
 pub fn calculate_mean(float_vec: Vec<f32>) -> f32{
     let count = float_vec.len() as f32;
     let sum = float_vec.iter().fold(0.0, |acc, value| acc + value);
-    let mean = sum / count;
 
-    mean
+    sum / count
 }
\ No newline at end of file
@@ -0,0 +1,380 @@
+/// An ingredient line such as "1 1/2 cups rice, rinsed" split into its parts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IngredientLine {
+    pub quantity: Option<f32>,
+    pub unit: Option<Unit>,
+    pub name: String,
+    /// Preparation and size details that are not part of the ingredient, such as "large" or "rinsed".
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub name: &'static str,
+    /// Approximate weight of one unit, assuming the density of water for volumes.
+    /// `None` for units that depend on the ingredient, such as cloves or cans.
+    pub grams: Option<f32>,
+}
+
+const UNITS: &[(&[&str], Unit)] = &[
+    (&["g", "gram", "grams", "gr"], Unit { name: "g", grams: Some(1.0) }),
+    (&["kg", "kgs", "kilogram", "kilograms", "kilo", "kilos"], Unit { name: "kg", grams: Some(1000.0) }),
+    (&["mg", "milligram", "milligrams"], Unit { name: "mg", grams: Some(0.001) }),
+    (&["ml", "milliliter", "milliliters", "millilitre", "millilitres"], Unit { name: "ml", grams: Some(1.0) }),
+    (&["l", "liter", "liters", "litre", "litres"], Unit { name: "l", grams: Some(1000.0) }),
+    (&["tsp", "tsps", "teaspoon", "teaspoons"], Unit { name: "tsp", grams: Some(5.0) }),
+    (&["tbsp", "tbsps", "tbs", "tablespoon", "tablespoons"], Unit { name: "tbsp", grams: Some(15.0) }),
+    (&["cup", "cups"], Unit { name: "cup", grams: Some(240.0) }),
+    (&["oz", "ounce", "ounces"], Unit { name: "oz", grams: Some(28.35) }),
+    (&["lb", "lbs", "pound", "pounds"], Unit { name: "lb", grams: Some(453.6) }),
+    (&["pinch", "pinches"], Unit { name: "pinch", grams: None }),
+    (&["clove", "cloves"], Unit { name: "clove", grams: None }),
+    (&["slice", "slices"], Unit { name: "slice", grams: None }),
+    (&["can", "cans", "tin", "tins"], Unit { name: "can", grams: None }),
+    (&["bunch", "bunches"], Unit { name: "bunch", grams: None }),
+    (&["sprig", "sprigs"], Unit { name: "sprig", grams: None }),
+    (&["stalk", "stalks"], Unit { name: "stalk", grams: None }),
+    (&["handful", "handfuls"], Unit { name: "handful", grams: None }),
+    (&["piece", "pieces"], Unit { name: "piece", grams: None }),
+];
+
+/// Words in front of the name that describe the ingredient rather than name it.
+const DESCRIPTORS: &[&str] = &[
+    "small", "medium", "large", "extra-large", "big",
+    "fresh", "freshly", "finely", "roughly", "thinly",
+    "chopped", "diced", "minced", "sliced", "grated", "crushed", "peeled",
+    "cooked", "softened", "melted", "beaten", "boneless", "skinless",
+];
+
+/// Phrases at the end of the name that are kept as a note.
+const TRAILING_NOTES: &[&str] = &["to taste", "for garnish", "for serving", "optional"];
+
+impl IngredientLine {
+    /// The quantity in grams, when both the quantity and a weight or volume unit are known.
+    pub fn grams(&self) -> Option<u32> {
+        let quantity = self.quantity?;
+        let grams_per_unit = self.unit?.grams?;
+
+        Some((quantity * grams_per_unit).round() as u32)
+    }
+}
+
+/// Parses a free-text ingredient line such as "2 tbsp soy sauce", "1/2 cup rice, rinsed" or "3 large eggs".
+/// Text after the first comma, inside parentheses or in front of the name (sizes, preparation)
+/// goes into the note. Ranges such as "2-3" or "2 to 3" are read as their midpoint.
+pub fn parse(line: &str) -> IngredientLine {
+    let line = line.trim().to_lowercase();
+    let mut front_notes: Vec<String> = Vec::new();
+    let mut back_notes: Vec<String> = Vec::new();
+
+    let mut without_parentheses = String::new();
+    let mut rest = line.as_str();
+    while let Some(open) = rest.find('(') {
+        without_parentheses.push_str(&rest[..open]);
+        match rest[open..].find(')') {
+            Some(close) => {
+                back_notes.push(rest[open + 1..open + close].trim().to_string());
+                rest = &rest[open + close + 1..];
+            },
+            None => {
+                back_notes.push(rest[open + 1..].trim().to_string());
+                rest = "";
+            },
+        }
+    }
+    without_parentheses.push_str(rest);
+
+    let main_part = match without_parentheses.split_once(',') {
+        Some((main_part, comma_note)) => {
+            back_notes.insert(0, comma_note.trim().to_string());
+            main_part.to_string()
+        },
+        None => without_parentheses,
+    };
+
+    let mut tokens: Vec<String> = Vec::new();
+    for word in main_part.split_whitespace() {
+        // "200g" is read as "200 g"
+        let split_at = word.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '/' || c == '-' || is_vulgar_fraction(c)));
+        match split_at {
+            Some(index) if index > 0 && parse_range(&word[..index]).is_some() => {
+                tokens.push(word[..index].to_string());
+                tokens.push(word[index..].to_string());
+            },
+            _ => tokens.push(word.to_string()),
+        }
+    }
+
+    let mut position = 0;
+    let mut quantity: Option<f32> = None;
+    if matches!(tokens.first().map(String::as_str), Some("a") | Some("an")) {
+        quantity = Some(1.0);
+        position = 1;
+    }
+    while let Some(number) = tokens.get(position).and_then(|token| parse_range(token)) {
+        position += 1;
+        // "2 to 3" and "2 - 3"
+        let range_end = match tokens.get(position).map(String::as_str) {
+            Some("to") | Some("-") => tokens.get(position + 1).and_then(|token| parse_number(token)),
+            _ => None,
+        };
+        let number = match range_end {
+            Some(end) => {
+                position += 2;
+                (number + end) / 2.0
+            },
+            None => number,
+        };
+        // "1 1/2" adds up
+        quantity = Some(quantity.unwrap_or(0.0) + number);
+    }
+
+    let unit = tokens.get(position).and_then(|token| find_unit(token));
+    if unit.is_some() {
+        position += 1;
+    }
+
+    if tokens.get(position).map(String::as_str) == Some("of") {
+        position += 1;
+    }
+
+    while let Some(word) = tokens.get(position) {
+        if !DESCRIPTORS.contains(&word.as_str()) {
+            break;
+        }
+        front_notes.push(word.clone());
+        position += 1;
+    }
+
+    let mut name = tokens[position.min(tokens.len())..].join(" ");
+    for trailing_note in TRAILING_NOTES {
+        if let Some(stripped) = name.strip_suffix(trailing_note) {
+            back_notes.insert(0, trailing_note.to_string());
+            name = stripped.trim_end().to_string();
+        }
+    }
+
+    let mut notes: Vec<String> = Vec::new();
+    if !front_notes.is_empty() {
+        notes.push(front_notes.join(" "));
+    }
+    notes.extend(back_notes.into_iter().filter(|note| !note.is_empty()));
+
+    IngredientLine {
+        quantity,
+        unit,
+        name,
+        note: if notes.is_empty() { None } else { Some(notes.join(", ")) },
+    }
+}
+
+/// Reads a number, or a range written without spaces such as "2-3" as its midpoint.
+fn parse_range(token: &str) -> Option<f32> {
+    match token.split_once('-') {
+        Some((start, end)) => Some((parse_number(start)? + parse_number(end)?) / 2.0),
+        None => parse_number(token),
+    }
+}
+
+pub fn find_unit(word: &str) -> Option<Unit> {
+    let word = word.trim_end_matches('.');
+    UNITS
+        .iter()
+        .find(|(aliases, _)| aliases.contains(&word))
+        .map(|(_, unit)| *unit)
+}
+
+/// Reads "2", "1.5", "1/2", "½" and "1½".
+pub fn parse_number(token: &str) -> Option<f32> {
+    if let Some((numerator, denominator)) = token.split_once('/') {
+        let numerator = numerator.parse::<f32>().ok()?;
+        let denominator = denominator.parse::<f32>().ok()?;
+        if denominator == 0.0 {
+            return None;
+        }
+        return Some(numerator / denominator);
+    }
+
+    if let Some(last) = token.chars().last() {
+        if let Some(fraction) = vulgar_fraction_value(last) {
+            let whole = &token[..token.len() - last.len_utf8()];
+            if whole.is_empty() {
+                return Some(fraction);
+            }
+            return whole.parse::<f32>().ok().map(|whole| whole + fraction);
+        }
+    }
+
+    token.parse::<f32>().ok().filter(|number| number.is_finite())
+}
+
+fn is_vulgar_fraction(c: char) -> bool {
+    vulgar_fraction_value(c).is_some()
+}
+
+fn vulgar_fraction_value(c: char) -> Option<f32> {
+    match c {
+        '¼' => Some(0.25),
+        '½' => Some(0.5),
+        '¾' => Some(0.75),
+        '⅓' => Some(1.0 / 3.0),
+        '⅔' => Some(2.0 / 3.0),
+        '⅛' => Some(0.125),
+        _ => None,
+    }
+}
+
+/// Finds the known ingredient a parsed name most likely refers to:
+/// an exact match, then the singular form, then the longest known name the line ends with
+/// ("brown onions" -> "onion") and finally a known name ending with the line ("egg" -> "chicken egg").
+pub fn closest_ingredient<'a>(name: &str, known_names: &'a [String]) -> Option<&'a String> {
+    if name.is_empty() {
+        return None;
+    }
+
+    let singular_name = singular(name);
+    if let Some(exact) = known_names.iter().find(|known| *known == name || **known == singular_name) {
+        return Some(exact);
+    }
+
+    let ends_with_words = |long: &str, short: &str| long.ends_with(short) && long[..long.len() - short.len()].ends_with(' ');
+
+    let contained = known_names
+        .iter()
+        .filter(|known| ends_with_words(&singular_name, known) || ends_with_words(name, known))
+        .max_by_key(|known| known.len());
+    if contained.is_some() {
+        return contained;
+    }
+
+    known_names
+        .iter()
+        .filter(|known| ends_with_words(known, &singular_name))
+        .min_by_key(|known| known.len())
+}
+
+fn singular(name: &str) -> String {
+    if let Some(stem) = name.strip_suffix("ies") {
+        format!("{stem}y")
+    } else if let Some(stem) = name.strip_suffix("oes") {
+        format!("{stem}o")
+    } else if name.ends_with("ss") {
+        name.to_string()
+    } else if let Some(stem) = name.strip_suffix('s') {
+        stem.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_name(line: &IngredientLine) -> Option<&'static str> {
+        line.unit.map(|unit| unit.name)
+    }
+
+    #[test]
+    fn parses_the_request_examples() {
+        let soy_sauce = parse("2 tbsp soy sauce");
+        assert_eq!(soy_sauce.quantity, Some(2.0));
+        assert_eq!(unit_name(&soy_sauce), Some("tbsp"));
+        assert_eq!(soy_sauce.name, "soy sauce");
+        assert_eq!(soy_sauce.note, None);
+        assert_eq!(soy_sauce.grams(), Some(30));
+
+        let rice = parse("1/2 cup rice, rinsed");
+        assert_eq!(rice.quantity, Some(0.5));
+        assert_eq!(unit_name(&rice), Some("cup"));
+        assert_eq!(rice.name, "rice");
+        assert_eq!(rice.note.as_deref(), Some("rinsed"));
+        assert_eq!(rice.grams(), Some(120));
+
+        let eggs = parse("3 large eggs");
+        assert_eq!(eggs.quantity, Some(3.0));
+        assert_eq!(eggs.unit, None);
+        assert_eq!(eggs.name, "eggs");
+        assert_eq!(eggs.note.as_deref(), Some("large"));
+        assert_eq!(eggs.grams(), None);
+    }
+
+    #[test]
+    fn adds_up_mixed_and_vulgar_fractions() {
+        assert_eq!(parse("1 1/2 cups flour").quantity, Some(1.5));
+        assert_eq!(parse("½ cup milk").quantity, Some(0.5));
+        assert_eq!(parse("1½ cups milk").quantity, Some(1.5));
+        assert_eq!(parse("¾ tsp salt").grams(), Some(4));
+    }
+
+    #[test]
+    fn reads_ranges_as_their_midpoint() {
+        let garlic = parse("2-3 cloves garlic, minced");
+        assert_eq!(garlic.quantity, Some(2.5));
+        assert_eq!(unit_name(&garlic), Some("clove"));
+        assert_eq!(garlic.name, "garlic");
+        assert_eq!(garlic.note.as_deref(), Some("minced"));
+        // a clove has no fixed weight
+        assert_eq!(garlic.grams(), None);
+
+        assert_eq!(parse("2 to 3 carrots").quantity, Some(2.5));
+        assert_eq!(parse("1 - 2 cups stock").quantity, Some(1.5));
+    }
+
+    #[test]
+    fn splits_a_unit_written_against_the_number() {
+        let butter = parse("200g butter");
+        assert_eq!(butter.quantity, Some(200.0));
+        assert_eq!(unit_name(&butter), Some("g"));
+        assert_eq!(butter.name, "butter");
+        assert_eq!(parse("1.5kg potatoes").grams(), Some(1500));
+    }
+
+    #[test]
+    fn keeps_unknown_units_in_the_name() {
+        let lettuce = parse("2 heads lettuce");
+        assert_eq!(lettuce.quantity, Some(2.0));
+        assert_eq!(lettuce.unit, None);
+        assert_eq!(lettuce.name, "heads lettuce");
+    }
+
+    #[test]
+    fn moves_descriptors_and_details_into_the_note() {
+        let onions = parse("2 Finely Chopped onions (about 300 g)");
+        assert_eq!(onions.name, "onions");
+        assert_eq!(onions.note.as_deref(), Some("finely chopped, about 300 g"));
+
+        let salt = parse("salt to taste");
+        assert_eq!(salt.quantity, None);
+        assert_eq!(salt.name, "salt");
+        assert_eq!(salt.note.as_deref(), Some("to taste"));
+
+        let pinch = parse("a pinch of salt");
+        assert_eq!(pinch.quantity, Some(1.0));
+        assert_eq!(unit_name(&pinch), Some("pinch"));
+        assert_eq!(pinch.name, "salt");
+    }
+
+    #[test]
+    fn rejects_numbers_it_cannot_read() {
+        assert_eq!(parse_number("1/0"), None);
+        assert_eq!(parse_number("inf"), None);
+        assert_eq!(parse_number("two"), None);
+        assert_eq!(parse_range("2-"), None);
+        assert_eq!(parse_range("2-3"), Some(2.5));
+        assert_eq!(find_unit("tbsp."), find_unit("tablespoon"));
+    }
+
+    #[test]
+    fn finds_the_closest_known_ingredient() {
+        let known: Vec<String> = ["onion", "chicken egg", "brown sugar", "sugar", "tomato"].iter().map(|name| name.to_string()).collect();
+
+        assert_eq!(closest_ingredient("onions", &known).map(String::as_str), Some("onion"));
+        assert_eq!(closest_ingredient("tomatoes", &known).map(String::as_str), Some("tomato"));
+        assert_eq!(closest_ingredient("brown onions", &known).map(String::as_str), Some("onion"));
+        assert_eq!(closest_ingredient("light brown sugar", &known).map(String::as_str), Some("brown sugar"));
+        assert_eq!(closest_ingredient("egg", &known).map(String::as_str), Some("chicken egg"));
+        assert_eq!(closest_ingredient("rice", &known), None);
+        assert_eq!(closest_ingredient("", &known), None);
+    }
+}
@@ -0,0 +1,55 @@
+/// Units of a duration, in the order they are matched so that "mo" is read before "m".
+const UNITS: &[&str] = &["y", "mo", "w", "d", "h", "m", "s"];
+
+/// Returns a lifespan in its canonical form, lowercase and without spaces, or `None` when the text is not a lifespan.
+/// A lifespan is one or more `;` separated parts such as "2mo", "3d-5d" or "fd:3d-5d;fz:6mo-12mo":
+/// an optional storage label (`fd` fridge, `fz` freezer) followed by a duration or a range of two durations.
+/// A duration is one or more numbers with a unit of `_y_mo_w_d_h_m_s`, such as "1.5d" or "1y6mo".
+pub fn normalize(text: &str) -> Option<String> {
+    let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+
+    let parts: Vec<&str> = compact.split(';').filter(|part| !part.is_empty()).collect();
+    if parts.is_empty() || !parts.iter().all(|part| is_part(part)) {
+        return None;
+    }
+
+    Some(parts.join(";"))
+}
+
+fn is_part(part: &str) -> bool {
+    let range = match part.split_once(':') {
+        Some((label, range)) => {
+            if label.is_empty() || !label.chars().all(|c| c.is_ascii_alphabetic()) {
+                return false;
+            }
+            range
+        },
+        None => part,
+    };
+
+    let durations: Vec<&str> = range.split('-').collect();
+    durations.len() <= 2 && durations.iter().all(|duration| is_duration(duration))
+}
+
+fn is_duration(duration: &str) -> bool {
+    let mut rest = duration;
+    if rest.is_empty() {
+        return false;
+    }
+
+    while !rest.is_empty() {
+        let number_length = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+        let number = &rest[..number_length];
+        if number.parse::<f32>().is_err() {
+            return false;
+        }
+        rest = &rest[number_length..];
+
+        match UNITS.iter().find(|unit| rest.starts_with(*unit)) {
+            Some(unit) => rest = &rest[unit.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
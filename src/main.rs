@@ -1,39 +1,174 @@
+pub mod auth;
+pub mod config;
+pub mod credentials;
 pub mod database;
+pub mod error;
 pub mod cli_operations;
 pub mod helper;
+pub mod ingredient_line;
+pub mod lifespan;
+pub mod server;
+pub mod tui;
 
-use cli_operations::{commands::get_command_bimap, user_input::{self, to_command_enum, prompt}};
-use database::{cloud::{has_internet_access, Database}, first_start};
-use tokio;
+use cli_operations::{commands::get_command_bimap, output::{set_output_format, OutputFormat}, parser::parse_command, script, user_input::{self, prompt_raw}};
+use database::{cloud::{require_internet_access, Database}, first_start};
 
 
 #[tokio::main]
 async fn main() {
-    if first_start() {
-        if has_internet_access().await {
-            match database::cloud::fetch(Database::Main).await {
-                Ok(_) => {},
-                Err(e) => {
-                    eprintln!("{e}");
-                    return;
-                },
-            }    
-        } else {
-            eprintln!("Internet access is required to fetch database for first use!");
-            return;
+    let mut cli_args: Vec<String> = std::env::args().skip(1).collect();
+    database::cloud::set_offline(take_switch(&mut cli_args, "--offline"));
+    if let Err(e) = take_format_flag(&mut cli_args) {
+        eprintln!("{e}");
+        std::process::exit(2);
+    }
+    let config = match take_profile_flags(&mut cli_args) {
+        Ok(config) => config,
+        Err(e) => {
+            error::report(&e);
+            std::process::exit(e.exit_code());
+        },
+    };
+    if cli_args.first().map(String::as_str) == Some("profiles") {
+        config::print_profiles(&config);
+        return;
+    }
+    if cli_args.first().map(String::as_str) == Some("auth") {
+        let exit_code = auth::run_from_args(&cli_args[1..]).await;
+        if exit_code != 0 {
+            std::process::exit(exit_code);
+        }
+        return;
+    }
+    let first_start = match first_start() {
+        Ok(first_start) => first_start,
+        Err(e) => {
+            error::report(&e);
+            std::process::exit(e.exit_code());
+        },
+    };
+    if first_start {
+        let fetched = match require_internet_access("fetch database for first use").await {
+            Ok(_) => database::cloud::fetch(Database::Main).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = fetched {
+            error::report(&e);
+            std::process::exit(e.exit_code());
         }
     }
+
+    if cli_args.first().map(String::as_str) == Some("run") {
+        let exit_code = script::run_from_args(&cli_args[1..]).await;
+        if exit_code != 0 {
+            std::process::exit(exit_code);
+        }
+        return;
+    }
+    if cli_args.first().map(String::as_str) == Some("tui") {
+        if let Err(e) = tui::run().await {
+            error::report(&e);
+            std::process::exit(e.exit_code());
+        }
+        return;
+    }
+    if cli_args.first().map(String::as_str) == Some("serve") {
+        if let Err(e) = server::run_from_args(&cli_args[1..]).await {
+            error::report(&e);
+            std::process::exit(e.exit_code());
+        }
+        return;
+    }
+
+    // `arino [--format json] list all dishes` runs a single command and exits
+    if !cli_args.is_empty() {
+        let command_bimap = get_command_bimap();
+        let parsed_command = match parse_command(&cli_args.join(" "), &command_bimap) {
+            Ok(parsed_command) => parsed_command,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(2);
+            },
+        };
+        if let Err(e) = user_input::match_commands(parsed_command, &command_bimap).await {
+            error::report(&e);
+            std::process::exit(e.exit_code());
+        }
+        return;
+    }
     
     println!("-----------------Arino-----------------");
     println!("Type \"help\" for the list of commands");
+    let profile = config::active_profile();
+    println!("Profile \"{}\", database {}", profile.name, profile.db.display());
     let command_bimap = get_command_bimap();
+    database::cloud::start_sync_batching().await;
+    if database::cloud::is_offline() {
+        println!("Working offline: changes are kept in the local database; a run without --offline syncs them, or reports a conflict when the database in Dropbox was changed meanwhile");
+    }
 
     loop {
-        let user_input = prompt("Command");
-        let command_enum = to_command_enum(user_input, &command_bimap);
-        match user_input::match_commands(command_enum, &command_bimap).await {
+        let user_input = prompt_raw(&format!("[{}] Command", database::cloud::status().await));
+        let parsed_command = match parse_command(&user_input, &command_bimap) {
+            Ok(parsed_command) => parsed_command,
+            Err(e) => {
+                eprintln!("{e}");
+                continue;
+            },
+        };
+        match user_input::match_commands(parsed_command, &command_bimap).await {
             Ok(_) => {},
-            Err(e) => eprintln!("{e}"),
+            Err(e) => error::report(&e),
         }
     }
 }
+
+/// Removes `--<flag> <value>` (or `--<flag>=<value>`) from the arguments and returns the value.
+fn take_flag(cli_args: &mut Vec<String>, flag_name: &str) -> Result<Option<String>, String> {
+    let prefix = format!("{flag_name}=");
+    let Some(position) = cli_args.iter().position(|arg| arg == flag_name || arg.starts_with(&prefix)) else {
+        return Ok(None);
+    };
+
+    let flag = cli_args.remove(position);
+    match flag.strip_prefix(&prefix) {
+        Some(value) => Ok(Some(value.to_string())),
+        None if position < cli_args.len() => Ok(Some(cli_args.remove(position))),
+        None => Err(format!("Missing value for {flag_name}")),
+    }
+}
+
+/// Removes `--<flag>` from the arguments and returns whether it was given.
+fn take_switch(cli_args: &mut Vec<String>, flag_name: &str) -> bool {
+    let length = cli_args.len();
+    cli_args.retain(|arg| arg != flag_name);
+    cli_args.len() != length
+}
+
+/// Removes `--profile <name>` and `--db <path>` from the arguments and makes the chosen profile active.
+fn take_profile_flags(cli_args: &mut Vec<String>) -> error::Result<config::Config> {
+    let profile_name = take_flag(cli_args, "--profile").map_err(error::Error::Validation)?;
+    let db = take_flag(cli_args, "--db").map_err(error::Error::Validation)?;
+
+    let config = config::load()?;
+    let profile = config::select(&config, profile_name.as_deref(), db.as_deref())?;
+    profile.create_db_dir()?;
+    config::set_active_profile(profile);
+
+    Ok(config)
+}
+
+/// Removes `--format <name>` (or `--format=<name>`) from the arguments and applies it.
+fn take_format_flag(cli_args: &mut Vec<String>) -> Result<(), String> {
+    let Some(format_name) = take_flag(cli_args, "--format")? else {
+        return Ok(());
+    };
+
+    match OutputFormat::from_name(&format_name.to_lowercase()) {
+        Some(format) => {
+            set_output_format(format);
+            Ok(())
+        },
+        None => Err(format!("Unknown format \"{format_name}\", expected table, json, ndjson or csv")),
+    }
+}
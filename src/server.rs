@@ -0,0 +1,519 @@
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{delete, get};
+use axum::{Json, Router};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Mutex;
+
+use crate::cli_operations::user_input::separate_by;
+use crate::database::cloud::{fetch_before_change_into, start_sync_batching};
+use crate::database::show::{self, dish_by_ingredients, recipe_export};
+use crate::database::delete::InUsePolicy;
+use crate::database::{commit_change, delete, get, get_connection, insert, modify, schema, snapshots, Outcome};
+use crate::error;
+
+const DEFAULT_PORT: u16 = 7878;
+const TOKEN_ENV: &str = "ARINO_API_TOKEN";
+/// The web front end, a single page without external assets so that it works offline.
+const INDEX_HTML: &str = include_str!("server/index.html");
+
+pub struct ServerOptions {
+    pub address: SocketAddr,
+    /// Required as `Authorization: Bearer <token>` on every request when set.
+    pub token: Option<String>,
+}
+
+struct ServerState {
+    conn: Mutex<Connection>,
+    token: Option<String>,
+}
+
+type SharedState = Arc<ServerState>;
+
+/// An error answered as `{"error": "..."}`.
+struct ApiError {
+    status: StatusCode,
+    message: String,
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> ApiError {
+        ApiError { status, message: message.into() }
+    }
+}
+
+impl From<error::Error> for ApiError {
+    fn from(e: error::Error) -> ApiError {
+        let status = match &e {
+            error::Error::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            error::Error::NotFound(_) => StatusCode::NOT_FOUND,
+            error::Error::Database(rusqlite::Error::SqliteFailure(failure, _)) if failure.code == rusqlite::ErrorCode::ConstraintViolation => StatusCode::CONFLICT,
+            error::Error::Conflict(_) => StatusCode::CONFLICT,
+            error::Error::Database(_) | error::Error::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            error::Error::Network(_) | error::Error::Auth(_) => StatusCode::BAD_GATEWAY,
+        };
+        ApiError::new(status, e.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for ApiError {
+    fn from(e: rusqlite::Error) -> ApiError {
+        ApiError::from(error::Error::from(e))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(json!({ "error": self.message }))).into_response()
+    }
+}
+
+#[derive(Deserialize)]
+struct NewDish {
+    name: String,
+    #[serde(default)]
+    recipe: Vec<NewRecipeLine>,
+}
+
+#[derive(Deserialize)]
+struct NewRecipeLine {
+    ingredient: String,
+    quantity: u32,
+    note: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DishUpdate {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct NewIngredient {
+    name: String,
+    category: String,
+    #[serde(default)]
+    lifespan: String,
+}
+
+/// Missing fields leave the ingredient unchanged.
+#[derive(Deserialize)]
+struct IngredientUpdate {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    category: String,
+    #[serde(default)]
+    lifespan: String,
+}
+
+#[derive(Deserialize)]
+struct NewPrice {
+    price: f32,
+}
+
+#[derive(Deserialize)]
+struct CategoryQuery {
+    category: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AvailableQuery {
+    /// Comma separated ingredient names.
+    ingredients: String,
+}
+
+/// What to do with the recipes using an ingredient being deleted, `block` unless given.
+#[derive(Deserialize)]
+struct InUseQuery {
+    #[serde(default)]
+    in_use: String,
+    #[serde(default)]
+    replacement: String,
+}
+
+#[derive(Serialize)]
+struct Written {
+    ok: bool,
+}
+
+/// `arino serve [--port N] [--bind ADDRESS] [--token TOKEN]`.
+pub async fn run_from_args(cli_args: &[String]) -> error::Result<()> {
+    let mut ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let mut port = DEFAULT_PORT;
+    let mut token = std::env::var(TOKEN_ENV).ok().filter(|token| !token.is_empty());
+
+    let mut cli_args = cli_args.iter();
+    while let Some(arg) = cli_args.next() {
+        let value = cli_args.next();
+        let parsed = match (arg.as_str(), value) {
+            ("--port", Some(value)) => value.parse().map(|value| port = value).is_ok(),
+            ("--bind", Some(value)) => value.parse().map(|value| ip = value).is_ok(),
+            ("--token", Some(value)) => {
+                token = Some(value.clone());
+                true
+            },
+            _ => false,
+        };
+        if !parsed {
+            return Err(error::Error::Validation("Usage: arino serve [--port N] [--bind ADDRESS] [--token TOKEN]".to_string()));
+        }
+    }
+
+    if !ip.is_loopback() && token.is_none() {
+        return Err(error::Error::Validation(format!("Refusing to listen on {ip} without a token, set --token or {TOKEN_ENV}")));
+    }
+
+    serve(ServerOptions { address: SocketAddr::new(ip, port), token }).await
+}
+
+pub async fn serve(options: ServerOptions) -> error::Result<()> {
+    // writes are synced in the background, once for every burst of requests
+    start_sync_batching().await;
+
+    let state = Arc::new(ServerState {
+        conn: Mutex::new(get_connection()?),
+        token: options.token,
+    });
+
+    let listener = tokio::net::TcpListener::bind(options.address).await?;
+    println!("Serving Arino on http://{}", listener.local_addr()?);
+
+    axum::serve(listener, router(state)).await?;
+
+    Ok(())
+}
+
+fn router(state: SharedState) -> Router {
+    let api = Router::new()
+        .route("/dishes", get(list_dishes).post(create_dish))
+        .route("/dishes/:dish", get(dish_detail).patch(rename_dish).delete(delete_dish))
+        .route("/dishes/:dish/recipe", get(dish_recipe).post(add_recipe_line))
+        .route("/dishes/:dish/recipe/:ingredient", delete(delete_recipe_line))
+        .route("/ingredients", get(list_ingredients).post(create_ingredient))
+        .route("/ingredients/:ingredient", get(ingredient_detail).patch(update_ingredient).delete(delete_ingredient))
+        .route("/ingredients/:ingredient/prices", get(ingredient_prices).post(add_price))
+        .route("/available-dishes", get(available_dishes))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token));
+
+    Router::new()
+        .route("/", get(index))
+        .nest("/api", api)
+        .with_state(state)
+}
+
+async fn index() -> Html<&'static str> {
+    Html(INDEX_HTML)
+}
+
+async fn require_token(State(state): State<SharedState>, request: Request, next: Next) -> Response {
+    let Some(token) = &state.token else {
+        return next.run(request).await;
+    };
+
+    let provided = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match provided {
+        Some(provided) if constant_time_eq(provided.as_bytes(), token.as_bytes()) => next.run(request).await,
+        _ => ApiError::new(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token").into_response(),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Names are compared the same way as in the REPL, which lowercases its input.
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+async fn read<T>(state: &ServerState, query: impl FnOnce(&Connection) -> Result<T, ApiError>) -> ApiResult<T> {
+    let conn = state.conn.lock().await;
+    query(&conn).map(Json)
+}
+
+/// Runs the change in a transaction and queues a sync once it is committed.
+async fn write(state: &ServerState, command_name: &str, change: impl FnOnce(&Connection) -> error::Result<()>) -> ApiResult<Written> {
+    transaction(state, command_name, false, change).await
+}
+
+/// Same as `write` for requests that delete rows: the database is snapshotted before the change.
+async fn delete_rows(state: &ServerState, command_name: &str, change: impl FnOnce(&Connection) -> error::Result<()>) -> ApiResult<Written> {
+    transaction(state, command_name, true, change).await
+}
+
+/// The latest database is fetched first, like before a change in the REPL, so that the sync does not replace changes made on other devices.
+/// A change made elsewhere while earlier requests still wait for their sync is a conflict (409).
+async fn transaction(state: &ServerState, command_name: &str, snapshot: bool, change: impl FnOnce(&Connection) -> error::Result<()>) -> ApiResult<Written> {
+    // Taken before the fetch, so that no other request uses the database while it is replaced
+    let mut conn = state.conn.lock().await;
+    fetch_before_change_into(&mut conn, "change the database").await?;
+    // A database fetched from an older version is upgraded like any database
+    schema::migrate(&mut conn)?;
+    if snapshot {
+        snapshots::take_from(&conn, &format!("api {command_name}"))?;
+    }
+    commit_change(&mut conn, &format!("api: {command_name}"), |tx| change(tx).map(|_| Outcome::Commit)).await?;
+
+    Ok(Json(Written { ok: true }))
+}
+
+fn dish_id(dish: &str, conn: &Connection) -> Result<u32, ApiError> {
+    Ok(get::dish_id_by_name(dish, conn)?)
+}
+
+fn ingredient_id(ingredient: &str, conn: &Connection) -> Result<u32, ApiError> {
+    Ok(get::ingredient_id_by_name(ingredient, conn)?)
+}
+
+async fn list_dishes(State(state): State<SharedState>) -> ApiResult<Vec<show::DishRow>> {
+    read(&state, |conn| Ok(show::dish_rows(conn)?)).await
+}
+
+async fn create_dish(State(state): State<SharedState>, Json(dish): Json<NewDish>) -> ApiResult<Written> {
+    let name = normalize(&dish.name);
+    write(&state, "new dish", |conn| {
+        insert::dish_row(&name, conn)?;
+        for line in &dish.recipe {
+            insert::recipe_row(&name, &normalize(&line.ingredient), line.quantity, line.note.as_deref(), conn)?;
+        }
+        Ok(())
+    }).await
+}
+
+async fn dish_detail(State(state): State<SharedState>, Path(dish): Path<String>) -> ApiResult<recipe_export::RecipeCard> {
+    read(&state, |conn| Ok(recipe_export::recipe_card(dish_id(&normalize(&dish), conn)?, conn)?)).await
+}
+
+async fn rename_dish(State(state): State<SharedState>, Path(dish): Path<String>, Json(update): Json<DishUpdate>) -> ApiResult<Written> {
+    write(&state, "update dish", |conn| modify::dish_name_row(&normalize(&dish), &normalize(&update.name), conn)).await
+}
+
+async fn delete_dish(State(state): State<SharedState>, Path(dish): Path<String>) -> ApiResult<Written> {
+    delete_rows(&state, "delete dish", |conn| delete::dish_row(&normalize(&dish), conn)).await
+}
+
+async fn dish_recipe(State(state): State<SharedState>, Path(dish): Path<String>) -> ApiResult<Vec<show::RecipeLineRow>> {
+    let dish = normalize(&dish);
+    read(&state, |conn| Ok(show::recipe_lines(dish_id(&dish, conn)?, &dish, conn)?)).await
+}
+
+async fn add_recipe_line(State(state): State<SharedState>, Path(dish): Path<String>, Json(line): Json<NewRecipeLine>) -> ApiResult<Written> {
+    write(&state, "add recipe", |conn| {
+        insert::recipe_row(&normalize(&dish), &normalize(&line.ingredient), line.quantity, line.note.as_deref(), conn)
+    }).await
+}
+
+async fn delete_recipe_line(State(state): State<SharedState>, Path((dish, ingredient)): Path<(String, String)>) -> ApiResult<Written> {
+    delete_rows(&state, "delete ingredient from recipe", |conn| delete::recipe_row(&normalize(&dish), &normalize(&ingredient), conn)).await
+}
+
+async fn list_ingredients(State(state): State<SharedState>, Query(query): Query<CategoryQuery>) -> ApiResult<Vec<show::IngredientRow>> {
+    read(&state, |conn| {
+        let category_id = match query.category.map(|category| normalize(&category)) {
+            Some(category) if !category.is_empty() && category != "all" => get::category_id_by_name(&category, conn)?,
+            _ => 0,
+        };
+        Ok(show::ingredient_rows(category_id, conn)?)
+    }).await
+}
+
+async fn create_ingredient(State(state): State<SharedState>, Json(ingredient): Json<NewIngredient>) -> ApiResult<Written> {
+    write(&state, "new ingredient", |conn| {
+        insert::ingredient_row(&normalize(&ingredient.name), &normalize(&ingredient.category), &normalize(&ingredient.lifespan), conn)
+    }).await
+}
+
+async fn ingredient_detail(State(state): State<SharedState>, Path(ingredient): Path<String>) -> ApiResult<show::IngredientRow> {
+    read(&state, |conn| {
+        let ingredient_id = ingredient_id(&normalize(&ingredient), conn)?;
+        show::ingredient_rows(0, conn)?
+            .into_iter()
+            .find(|row| row.id == ingredient_id)
+            .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, format!("Unknown ingredient \"{ingredient}\"")))
+    }).await
+}
+
+async fn update_ingredient(State(state): State<SharedState>, Path(ingredient): Path<String>, Json(update): Json<IngredientUpdate>) -> ApiResult<Written> {
+    write(&state, "update ingredient", |conn| {
+        modify::ingredient_row(&normalize(&ingredient), &normalize(&update.name), &normalize(&update.lifespan), &normalize(&update.category), conn)
+    }).await
+}
+
+async fn delete_ingredient(State(state): State<SharedState>, Path(ingredient): Path<String>, Query(query): Query<InUseQuery>) -> ApiResult<Written> {
+    let policy = match InUsePolicy::from_name(&normalize(&query.in_use), &normalize(&query.replacement)) {
+        Some(policy) => policy,
+        None => return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "in_use must be block, cascade or replace with a replacement")),
+    };
+    delete_rows(&state, "delete ingredient", |conn| delete::ingredient_row(&normalize(&ingredient), &policy, conn)).await
+}
+
+async fn ingredient_prices(State(state): State<SharedState>, Path(ingredient): Path<String>) -> ApiResult<Vec<show::PriceRow>> {
+    let ingredient = normalize(&ingredient);
+    read(&state, |conn| Ok(show::price_rows(ingredient_id(&ingredient, conn)?, &ingredient, conn)?)).await
+}
+
+async fn add_price(State(state): State<SharedState>, Path(ingredient): Path<String>, Json(price): Json<NewPrice>) -> ApiResult<Written> {
+    write(&state, "add price", |conn| insert::price_row(&normalize(&ingredient), price.price, conn)).await
+}
+
+async fn available_dishes(State(state): State<SharedState>, Query(query): Query<AvailableQuery>) -> ApiResult<Vec<dish_by_ingredients::AvailableDish>> {
+    let ingredients: Vec<String> = separate_by(",", normalize(&query.ingredients))
+        .into_iter()
+        .filter(|ingredient| !ingredient.is_empty())
+        .collect();
+    if ingredients.is_empty() {
+        return Err(ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "No ingredient was given"));
+    }
+
+    read(&state, |conn| {
+        let ingredient_ids = ingredients
+            .iter()
+            .map(|ingredient| get::ingredient_id_by_name(ingredient, conn))
+            .collect::<error::Result<HashSet<u32>>>()?;
+        Ok(dish_by_ingredients::available_dishes(&ingredient_ids, conn)?)
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use axum::routing::post;
+    use super::*;
+    use crate::config::{self, Config, ProfileEntry};
+    use crate::credentials::{self, Credentials};
+
+    /// The database in the stub Dropbox, with how often it was downloaded and uploaded.
+    #[derive(Default)]
+    struct Remote {
+        rev: u32,
+        content: Vec<u8>,
+        downloads: usize,
+        uploads: usize,
+    }
+
+    type SharedRemote = Arc<std::sync::Mutex<Remote>>;
+
+    fn stub_dropbox(remote: SharedRemote) -> Router {
+        Router::new()
+            .route("/2/files/get_metadata", post(|State(remote): State<SharedRemote>| async move {
+                Json(json!({ "rev": format!("r{}", remote.lock().unwrap().rev) }))
+            }))
+            .route("/2/files/download", post(|State(remote): State<SharedRemote>| async move {
+                let mut remote = remote.lock().unwrap();
+                remote.downloads += 1;
+                ([("Dropbox-API-Result", json!({ "rev": format!("r{}", remote.rev) }).to_string())], remote.content.clone())
+            }))
+            .route("/2/files/upload", post(|State(remote): State<SharedRemote>, body: axum::body::Bytes| async move {
+                let mut remote = remote.lock().unwrap();
+                remote.uploads += 1;
+                remote.rev += 1;
+                remote.content = body.to_vec();
+                Json(json!({ "rev": format!("r{}", remote.rev) }))
+            }))
+            .with_state(remote)
+    }
+
+    /// A database file with the given rows, read back as the bytes Dropbox would hold.
+    fn database_content(dir: &std::path::Path, rows: &str) -> Vec<u8> {
+        let path = dir.join("remote.db");
+        let mut conn = Connection::open(&path).unwrap();
+        schema::migrate(&mut conn).unwrap();
+        conn.execute_batch(rows).unwrap();
+        drop(conn);
+        fs::read(path).unwrap()
+    }
+
+    fn names(content: &[u8], dir: &std::path::Path, table: &str) -> Vec<String> {
+        let path = dir.join("names.db");
+        fs::write(&path, content).unwrap();
+        let conn = Connection::open(&path).unwrap();
+        let names = conn.prepare(&format!("SELECT name FROM {table} ORDER BY name;")).unwrap()
+            .query_map([], |row| row.get(0)).unwrap()
+            .collect::<rusqlite::Result<Vec<String>>>().unwrap();
+        names
+    }
+
+    /// Makes the active profile use a fresh database in `dir`, synced with Dropbox at `r1`, and credentials that need no refresh.
+    fn stub_profile(dir: &std::path::Path, address: SocketAddr) -> config::Profile {
+        let entry = ProfileEntry {
+            db: Some(dir.join("database.db").to_string_lossy().into_owned()),
+            credentials: Some(dir.join("credentials.enc").to_string_lossy().into_owned()),
+            api_url: Some(format!("http://{address}")),
+            content_url: Some(format!("http://{address}")),
+            ..ProfileEntry::default()
+        };
+        let config = Config { default_profile: None, profiles: [("stub".to_string(), entry)].into() };
+        let profile = config::select(&config, Some("stub"), None).unwrap();
+        config::set_active_profile(profile.clone());
+
+        std::env::set_var(credentials::PASSPHRASE_ENV, "server test");
+        let credentials = Credentials {
+            client_id: "app-key".to_string(),
+            client_secret: None,
+            refresh_token: "refresh".to_string(),
+            access_token: Some("access".to_string()),
+            expires_at: Some(u64::MAX / 2),
+        };
+        credentials::save(&profile, &credentials).unwrap();
+        fs::write(profile.sync_state_path(), json!({ "rev": "r1", "pending": false }).to_string()).unwrap();
+
+        profile
+    }
+
+    fn temporary_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("arino-server-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_writes_fetch_and_sync_one_after_the_other() {
+        let dir = temporary_dir("concurrent");
+        // Another device added a dish since the local database was synced at r1
+        let remote = Arc::new(std::sync::Mutex::new(Remote {
+            rev: 2,
+            content: database_content(&dir, "INSERT INTO dishes (name) VALUES ('soup from the phone');"),
+            ..Remote::default()
+        }));
+        let dropbox = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let profile = stub_profile(&dir, dropbox.local_addr().unwrap());
+        let dropbox_router = stub_dropbox(Arc::clone(&remote));
+        tokio::spawn(async move { axum::serve(dropbox, dropbox_router).await });
+
+        let state = Arc::new(ServerState { conn: Mutex::new(get_connection().unwrap()), token: None });
+        let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+        let api = format!("http://{}/api", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router(state)).await });
+
+        let client = reqwest::Client::new();
+        let (leek, salt) = tokio::join!(
+            client.post(format!("{api}/ingredients")).json(&json!({ "name": "leek", "category": "vegetable" })).send(),
+            client.post(format!("{api}/ingredients")).json(&json!({ "name": "salt", "category": "condiment" })).send(),
+        );
+        assert!(leek.unwrap().status().is_success());
+        assert!(salt.unwrap().status().is_success());
+
+        // The first write fetched the dish from the phone, the second found Dropbox unchanged since the first one's sync
+        let remote = remote.lock().unwrap();
+        assert_eq!((remote.downloads, remote.uploads, remote.rev), (1, 2, 4));
+        assert_eq!(names(&remote.content, &dir, "ingredients"), vec!["leek", "salt"]);
+        assert_eq!(names(&remote.content, &dir, "dishes"), vec!["soup from the phone"]);
+        assert_eq!(names(&fs::read(&profile.db).unwrap(), &dir, "dishes"), vec!["soup from the phone"]);
+    }
+}
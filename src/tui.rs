@@ -0,0 +1,484 @@
+use std::collections::HashSet;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use rusqlite::Connection;
+
+use crate::database::cloud::{fetch_before_change, has_internet_access, sync, sync_at_exit};
+use crate::database::show::{self, dish_by_ingredients, DishRow, IngredientRow, RecipeLineRow};
+use crate::database::{commit_change, delete, get, get_connection, insert, modify, snapshots, validate, Outcome};
+use crate::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Dishes,
+    Ingredients,
+    Recipe,
+}
+
+impl Pane {
+    fn next(self) -> Pane {
+        match self {
+            Pane::Dishes => Pane::Ingredients,
+            Pane::Ingredients => Pane::Recipe,
+            Pane::Recipe => Pane::Dishes,
+        }
+    }
+
+    fn previous(self) -> Pane {
+        match self {
+            Pane::Dishes => Pane::Recipe,
+            Pane::Ingredients => Pane::Dishes,
+            Pane::Recipe => Pane::Ingredients,
+        }
+    }
+}
+
+/// What the value typed in the footer is used for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Edit {
+    Quantity { ingredient: String },
+    RecipeLine,
+    Price { ingredient: String },
+    /// Editing an ingredient asks for its name, then its lifespan, then its category.
+    IngredientName { ingredient: String },
+    IngredientLifespan { ingredient: String, name: String },
+    IngredientCategory { ingredient: String, name: String, lifespan: String },
+}
+
+impl Edit {
+    fn label(&self) -> String {
+        match self {
+            Edit::Quantity { ingredient } => format!("Quantity of {ingredient} (g)"),
+            Edit::RecipeLine => "Ingredient, quantity".to_string(),
+            Edit::Price { ingredient } => format!("Price of {ingredient} per kg in AUD"),
+            Edit::IngredientName { ingredient } => format!("New name of {ingredient}"),
+            Edit::IngredientLifespan { name, .. } => format!("Lifespan of {name} (in _y_mo_d_h_m_s)"),
+            Edit::IngredientCategory { name, .. } => format!("Category of {name} (vegetable, fruit, dairy, meat, condiment, grain)"),
+        }
+    }
+}
+
+struct App {
+    conn: Connection,
+    focus: Pane,
+    dishes: Vec<DishRow>,
+    ingredients: Vec<IngredientRow>,
+    recipe: Vec<RecipeLineRow>,
+    makeable: Vec<dish_by_ingredients::AvailableDish>,
+    /// Ingredient ids ticked as being in the fridge.
+    fridge: HashSet<u32>,
+    dish_state: ListState,
+    ingredient_state: ListState,
+    recipe_state: ListState,
+    edit: Option<(Edit, String)>,
+    status: String,
+    changes: usize,
+    /// A fetch printed over the interface, which is drawn again from scratch.
+    redraw: bool,
+    quit: bool,
+}
+
+/// `arino tui`; `main` fetches the database for first use before starting it.
+pub async fn run() -> Result<()> {
+    let mut app = App::new(get_connection()?)?;
+    sync_at_exit();
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal).await;
+    ratatui::restore();
+    result?;
+
+    // the sync prints its progress, so it waits until the terminal is restored
+    if app.changes > 0 {
+        println!("{} change(s) made", app.changes);
+        if has_internet_access().await {
+            sync().await?;
+        }
+    }
+
+    Ok(())
+}
+
+impl App {
+    fn new(conn: Connection) -> Result<App> {
+        let mut app = App {
+            conn,
+            focus: Pane::Dishes,
+            dishes: Vec::new(),
+            ingredients: Vec::new(),
+            recipe: Vec::new(),
+            makeable: Vec::new(),
+            fridge: HashSet::new(),
+            dish_state: ListState::default(),
+            ingredient_state: ListState::default(),
+            recipe_state: ListState::default(),
+            edit: None,
+            status: String::new(),
+            changes: 0,
+            redraw: false,
+            quit: false,
+        };
+        app.reload()?;
+
+        Ok(app)
+    }
+
+    async fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        while !self.quit {
+            if std::mem::take(&mut self.redraw) {
+                terminal.clear()?;
+            }
+            terminal.draw(|frame| self.draw(frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    self.handle_key(key).await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reads everything shown again, keeping the selections where possible.
+    fn reload(&mut self) -> Result<()> {
+        self.dishes = show::dish_rows(&self.conn)?;
+        self.ingredients = show::ingredient_rows(0, &self.conn)?;
+        clamp(&mut self.dish_state, self.dishes.len());
+        clamp(&mut self.ingredient_state, self.ingredients.len());
+        self.reload_recipe()?;
+        self.reload_makeable()?;
+
+        Ok(())
+    }
+
+    fn reload_recipe(&mut self) -> Result<()> {
+        self.recipe = match self.selected_dish() {
+            Some(dish) => show::recipe_lines(dish.id, &dish.name, &self.conn)?,
+            None => Vec::new(),
+        };
+        clamp(&mut self.recipe_state, self.recipe.len());
+
+        Ok(())
+    }
+
+    fn reload_makeable(&mut self) -> Result<()> {
+        self.fridge.retain(|id| self.ingredients.iter().any(|ingredient| ingredient.id == *id));
+        self.makeable = if self.fridge.is_empty() {
+            Vec::new()
+        } else {
+            dish_by_ingredients::available_dishes(&self.fridge, &self.conn)?
+        };
+
+        Ok(())
+    }
+
+    fn selected_dish(&self) -> Option<&DishRow> {
+        self.dish_state.selected().and_then(|index| self.dishes.get(index))
+    }
+
+    fn selected_ingredient(&self) -> Option<&IngredientRow> {
+        self.ingredient_state.selected().and_then(|index| self.ingredients.get(index))
+    }
+
+    fn selected_line(&self) -> Option<&RecipeLineRow> {
+        self.recipe_state.selected().and_then(|index| self.recipe.get(index))
+    }
+
+    async fn handle_key(&mut self, key: KeyEvent) {
+        if self.edit.is_some() {
+            self.handle_edit_key(key).await;
+            return;
+        }
+
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        let result = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.quit = true;
+                Ok(())
+            },
+            KeyCode::Tab | KeyCode::Right | KeyCode::Char('l') => {
+                self.focus = self.focus.next();
+                Ok(())
+            },
+            KeyCode::BackTab | KeyCode::Left | KeyCode::Char('h') => {
+                self.focus = self.focus.previous();
+                Ok(())
+            },
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1),
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1),
+            KeyCode::Char(' ') if self.focus == Pane::Ingredients => self.toggle_fridge(),
+            KeyCode::Enter | KeyCode::Char('e') if self.focus == Pane::Ingredients => {
+                if let Some(ingredient) = self.selected_ingredient() {
+                    let name = ingredient.name.clone();
+                    self.edit = Some((Edit::IngredientName { ingredient: name.clone() }, name));
+                }
+                Ok(())
+            },
+            KeyCode::Char('p') if self.focus == Pane::Ingredients => {
+                if let Some(ingredient) = self.selected_ingredient() {
+                    self.edit = Some((Edit::Price { ingredient: ingredient.name.clone() }, String::new()));
+                }
+                Ok(())
+            },
+            KeyCode::Enter | KeyCode::Char('e') if self.focus == Pane::Recipe => {
+                if let Some(line) = self.selected_line() {
+                    let quantity = line.quantity.to_string();
+                    self.edit = Some((Edit::Quantity { ingredient: line.ingredient.clone() }, quantity));
+                }
+                Ok(())
+            },
+            KeyCode::Char('a') if self.focus == Pane::Recipe && self.selected_dish().is_some() => {
+                self.edit = Some((Edit::RecipeLine, String::new()));
+                Ok(())
+            },
+            KeyCode::Char('d') if self.focus == Pane::Recipe => self.delete_line().await,
+            _ => Ok(()),
+        };
+
+        if let Err(e) = result {
+            self.status = e.to_string();
+        }
+    }
+
+    async fn handle_edit_key(&mut self, key: KeyEvent) {
+        let Some((edit, value)) = &mut self.edit else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Esc => {
+                self.edit = None;
+                self.status = "Canceled".to_string();
+            },
+            KeyCode::Backspace => {
+                value.pop();
+            },
+            KeyCode::Char(c) => value.push(c),
+            KeyCode::Enter => {
+                let (edit, value) = (edit.clone(), value.trim().to_lowercase());
+                self.edit = None;
+                self.status = match self.apply_edit(edit, &value).await {
+                    Ok(message) => message,
+                    Err(e) => e.to_string(),
+                };
+            },
+            _ => {},
+        }
+    }
+
+    async fn apply_edit(&mut self, edit: Edit, value: &str) -> Result<String> {
+        let dish = self.selected_dish().map(|dish| dish.name.clone()).unwrap_or_default();
+
+        let message = match edit {
+            Edit::Quantity { ingredient } => {
+                let quantity = parse_quantity(value)?;
+                self.write("update recipe quantity", |conn| modify::recipe_quantity_row(&dish, &ingredient, quantity, conn)).await?;
+                format!("{ingredient} in {dish} is now {quantity} g")
+            },
+            Edit::RecipeLine => {
+                let (ingredient, quantity) = match value.rsplit_once(',') {
+                    Some((ingredient, quantity)) => (ingredient.trim().to_string(), parse_quantity(quantity)?),
+                    None => return Err(Error::Validation("Expected \"ingredient, quantity\"".to_string())),
+                };
+                self.write("add recipe", |conn| insert::recipe_row(&dish, &ingredient, quantity, None, conn)).await?;
+                format!("Added {quantity} g {ingredient} to {dish}")
+            },
+            Edit::Price { ingredient } => {
+                let price = validate::price_text(value)?;
+                self.write("add price", |conn| insert::price_row(&ingredient, price, conn)).await?;
+                format!("Added ${price:.2} to {ingredient}")
+            },
+            // Each step is checked before the next one is asked, and the ingredient is only changed after the last one
+            Edit::IngredientName { ingredient } => {
+                let ingredient_id = get::ingredient_id_by_name(&ingredient, &self.conn)?;
+                let name = validate::ingredient_name(value, Some(ingredient_id), &self.conn)?;
+                let lifespan = self.selected_ingredient().and_then(|row| row.lifespan.clone()).unwrap_or_default();
+                self.edit = Some((Edit::IngredientLifespan { ingredient, name }, lifespan));
+                String::new()
+            },
+            Edit::IngredientLifespan { ingredient, name } => {
+                validate::lifespan(value)?;
+                let category = self.selected_ingredient().and_then(|row| row.category.clone()).unwrap_or_default();
+                self.edit = Some((Edit::IngredientCategory { ingredient, name, lifespan: value.to_string() }, category));
+                String::new()
+            },
+            Edit::IngredientCategory { ingredient, name, lifespan } => {
+                self.write("update ingredient", |conn| modify::ingredient_row(&ingredient, &name, &lifespan, value, conn)).await?;
+                format!("Updated {name}")
+            },
+        };
+
+        Ok(message)
+    }
+
+    /// Runs the change in a transaction, then refreshes every pane.
+    async fn write(&mut self, command_name: &str, change: impl FnOnce(&Connection) -> Result<()>) -> Result<()> {
+        self.transaction(command_name, false, change).await
+    }
+
+    /// Same as `write` for changes that delete rows: the fetched database is snapshotted before the change.
+    async fn delete_rows(&mut self, command_name: &str, change: impl FnOnce(&Connection) -> Result<()>) -> Result<()> {
+        self.transaction(command_name, true, change).await
+    }
+
+    /// The latest database is fetched first, like before a change in the REPL, so that the sync on quit does not replace
+    /// changes made on other devices; a change made elsewhere since the first edit of the session is a conflict.
+    async fn transaction(&mut self, command_name: &str, snapshot: bool, change: impl FnOnce(&Connection) -> Result<()>) -> Result<()> {
+        self.redraw = true;
+        fetch_before_change("change the database").await?;
+        // The fetch may have replaced the database file under the open connection
+        self.conn = get_connection()?;
+        if snapshot {
+            snapshots::take_from(&self.conn, &format!("tui {command_name}"))?;
+        }
+
+        commit_change(&mut self.conn, &format!("tui: {command_name}"), |tx| change(tx).map(|_| Outcome::Commit)).await?;
+        self.changes += 1;
+
+        self.reload()
+    }
+
+    async fn delete_line(&mut self) -> Result<()> {
+        let (Some(dish), Some(line)) = (self.selected_dish(), self.selected_line()) else {
+            return Ok(());
+        };
+        let (dish, ingredient) = (dish.name.clone(), line.ingredient.clone());
+
+        self.delete_rows("delete ingredient from recipe", |conn| delete::recipe_row(&dish, &ingredient, conn)).await?;
+        self.status = format!("Removed {ingredient} from {dish}");
+
+        Ok(())
+    }
+
+    fn toggle_fridge(&mut self) -> Result<()> {
+        let Some(id) = self.selected_ingredient().map(|ingredient| ingredient.id) else {
+            return Ok(());
+        };
+        if !self.fridge.remove(&id) {
+            self.fridge.insert(id);
+        }
+
+        self.reload_makeable()
+    }
+
+    fn move_selection(&mut self, step: isize) -> Result<()> {
+        let (state, len) = match self.focus {
+            Pane::Dishes => (&mut self.dish_state, self.dishes.len()),
+            Pane::Ingredients => (&mut self.ingredient_state, self.ingredients.len()),
+            Pane::Recipe => (&mut self.recipe_state, self.recipe.len()),
+        };
+        if len == 0 {
+            return Ok(());
+        }
+
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some((current + step).rem_euclid(len as isize) as usize));
+
+        if self.focus == Pane::Dishes {
+            self.recipe_state.select(Some(0));
+            self.reload_recipe()?;
+        }
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] = Layout::vertical([Constraint::Min(5), Constraint::Length(3)]).areas(frame.area());
+        let [dishes, ingredients, right] = Layout::horizontal([
+            Constraint::Percentage(25),
+            Constraint::Percentage(35),
+            Constraint::Percentage(40),
+        ]).areas(main);
+        let [recipe, makeable] = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(65), Constraint::Percentage(35)])
+            .areas(right);
+
+        let dish_items: Vec<ListItem> = self.dishes.iter().map(|dish| ListItem::new(dish.name.clone())).collect();
+        self.draw_list(frame, dishes, "Dishes", Pane::Dishes, dish_items);
+
+        let ingredient_items: Vec<ListItem> = self.ingredients
+            .iter()
+            .map(|ingredient| {
+                let tick = if self.fridge.contains(&ingredient.id) { "[x]" } else { "[ ]" };
+                let price = match ingredient.mean_price {
+                    Some(price) => format!("${price:.2}/kg"),
+                    None => "-".to_string(),
+                };
+                ListItem::new(format!("{tick} {}  {price}", ingredient.name))
+            })
+            .collect();
+        self.draw_list(frame, ingredients, "Ingredients (space: in fridge, e: edit, p: price)", Pane::Ingredients, ingredient_items);
+
+        let recipe_title = match self.selected_dish() {
+            Some(dish) => format!("Recipe of {} (e: quantity, a: add, d: delete)", dish.name),
+            None => "Recipe".to_string(),
+        };
+        let recipe_items: Vec<ListItem> = self.recipe
+            .iter()
+            .map(|line| match &line.note {
+                Some(note) if !note.is_empty() => ListItem::new(format!("{} g {}, {note}", line.quantity, line.ingredient)),
+                _ => ListItem::new(format!("{} g {}", line.quantity, line.ingredient)),
+            })
+            .collect();
+        self.draw_list(frame, recipe, &recipe_title, Pane::Recipe, recipe_items);
+
+        let makeable_lines: Vec<Line> = if self.fridge.is_empty() {
+            vec![Line::from("Tick ingredients to see what can be made")]
+        } else if self.makeable.is_empty() {
+            vec![Line::from("No available dishes")]
+        } else {
+            self.makeable.iter().map(|dish| Line::from(dish.name.clone())).collect()
+        };
+        frame.render_widget(
+            Paragraph::new(makeable_lines).block(Block::default().borders(Borders::ALL).title("Makeable now")),
+            makeable,
+        );
+
+        let footer_text = match &self.edit {
+            Some((edit, value)) => format!("{}: {value}_", edit.label()),
+            None if !self.status.is_empty() => self.status.clone(),
+            None => "tab: next pane  j/k: move  q: quit".to_string(),
+        };
+        frame.render_widget(Paragraph::new(footer_text).block(Block::default().borders(Borders::ALL)), footer);
+    }
+
+    fn draw_list(&mut self, frame: &mut Frame, area: Rect, title: &str, pane: Pane, items: Vec<ListItem>) {
+        let border_style = if self.focus == pane {
+            Style::default().fg(Color::Green)
+        } else {
+            Style::default()
+        };
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).border_style(border_style).title(title.to_string()))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+        let state = match pane {
+            Pane::Dishes => &mut self.dish_state,
+            Pane::Ingredients => &mut self.ingredient_state,
+            Pane::Recipe => &mut self.recipe_state,
+        };
+        frame.render_stateful_widget(list, area, state);
+    }
+}
+
+/// Keeps the selection inside a list that may have shrunk.
+fn clamp(state: &mut ListState, len: usize) {
+    state.select(match (state.selected(), len) {
+        (_, 0) => None,
+        (Some(index), _) => Some(index.min(len - 1)),
+        (None, _) => Some(0),
+    });
+}
+
+fn parse_quantity(value: &str) -> Result<u32> {
+    validate::quantity_text(value)
+}